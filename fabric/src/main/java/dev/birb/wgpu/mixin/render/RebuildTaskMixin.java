@@ -46,6 +46,8 @@ public class RebuildTaskMixin implements RebuildTaskAccessor {
 //
 //            byte[][] skyIndices = new byte[27][2048];
 //            byte[][] blockIndices = new byte[27][2048];
+//            int[][] biomeIds = new int[27][];
+//            Registry<Biome> biomes = world.getRegistryManager().getOrThrow(RegistryKeys.BIOME);
 //            BlockPos origin = chunk.getOrigin();
 //            Vec3i sectionCoord = new Vec3i(origin.getX()>>4,origin.getY()>>4,origin.getZ()>>4);
 //            for(int x=0;x<3;x++){
@@ -56,13 +58,22 @@ public class RebuildTaskMixin implements RebuildTaskAccessor {
 //                        int id = x+3*y+9*z;
 //                        Palette<?> palette;
 //                        PalettedContainer<?> section;
+//                        ReadableContainer<RegistryEntry<Biome>> biomeContainer;
 //                        try {
-//                            section = worldChunk.getSection(world.sectionCoordToIndex(sectionCoord.getY()+y-1)).getBlockStateContainer();
+//                            ChunkSection chunkSection = worldChunk.getSection(world.sectionCoordToIndex(sectionCoord.getY()+y-1));
+//                            section = chunkSection.getBlockStateContainer();
+//                            biomeContainer = chunkSection.getBiomeContainer();
 //                            palette = section.data.palette;
 //                        } catch (ArrayIndexOutOfBoundsException e) {
 //                            continue;
 //                        }
 //
+//                        //Indexed like the biome container, as x | z << 2 | y << 4
+//                        biomeIds[id] = new int[64];
+//                        for(int cell=0;cell<64;cell++){
+//                            biomeIds[id][cell] = biomes.getRawId(biomeContainer.get(cell & 3, cell >> 4, (cell >> 2) & 3).value());
+//                        }
+//
 //                        long sectionPos = ChunkSectionPos.from(sectionCoord.getX()+x-1,sectionCoord.getY()+y-1,sectionCoord.getZ()+z-1).asLong();
 //                        if(skyLightProvider != null && blockLightProvider != null) {
 //                            ChunkNibbleArray skyNibble = skyLightProvider.lightStorage.uncachedStorage.get(sectionPos);
@@ -108,7 +119,7 @@ public class RebuildTaskMixin implements RebuildTaskAccessor {
 //                    }
 //                }
 //            }
-//            WgpuNative.bakeSection(sectionCoord.getX(),sectionCoord.getY(),sectionCoord.getZ(),paletteIndices, storageIndices, blockIndices, skyIndices);
+//            WgpuNative.bakeSection(sectionCoord.getX(),sectionCoord.getY(),sectionCoord.getZ(),paletteIndices, storageIndices, blockIndices, skyIndices, biomeIds);
 //            return CompletableFuture.completedFuture(ChunkBuilder.Result.SUCCESSFUL);
 //        }
 //    }
@@ -0,0 +1,155 @@
+package dev.birb.wgpu.render;
+
+import dev.birb.wgpu.palette.RustPalette;
+import dev.birb.wgpu.rust.WgpuNative;
+import io.netty.buffer.ByteBuf;
+import io.netty.buffer.Unpooled;
+import net.minecraft.client.world.ClientWorld;
+import net.minecraft.fluid.FlowableFluid;
+import net.minecraft.fluid.FluidState;
+import net.minecraft.network.PacketByteBuf;
+import net.minecraft.registry.Registry;
+import net.minecraft.registry.RegistryKeys;
+import net.minecraft.registry.entry.RegistryEntry;
+import net.minecraft.registry.tag.FluidTags;
+import net.minecraft.util.collection.PackedIntegerArray;
+import net.minecraft.util.collection.PaletteStorage;
+import net.minecraft.util.math.ChunkSectionPos;
+import net.minecraft.world.biome.Biome;
+import net.minecraft.world.chunk.ChunkNibbleArray;
+import net.minecraft.world.chunk.ChunkSection;
+import net.minecraft.world.chunk.ChunkStatus;
+import net.minecraft.world.chunk.Palette;
+import net.minecraft.world.chunk.PalettedContainer;
+import net.minecraft.world.chunk.ReadableContainer;
+import net.minecraft.world.chunk.WorldChunk;
+import net.minecraft.world.chunk.light.ChunkLightProvider;
+
+import java.util.Arrays;
+
+/**
+ * The blocks, fluids, light and biomes of a section and the 26 around it, in the layout {@link WgpuNative#bakeSection} takes.
+ * Each of the 27 sections is indexed as x + 3 * y + 9 * z, offset by one from the section in the middle.
+ */
+public class SectionSnapshot {
+    public final long[] paletteIndices = new long[27];
+    public final long[] storageIndices = new long[27];
+    public final byte[][] blockIndices = new byte[27][2048];
+    public final byte[][] skyIndices = new byte[27][2048];
+    public final int[][] biomeIds = new int[27][];
+    /**
+     * The fluid in each block as {@code fluid | (level - 1) << 2 | falling << 5}, where the fluid is 1 for water and 2
+     * for lava, indexed as x | z << 4 | y << 8. Null for sections without fluids.
+     */
+    public final byte[][] fluidStates = new byte[27][];
+
+    private SectionSnapshot() {
+        Arrays.fill(paletteIndices, -1);
+        Arrays.fill(storageIndices, -1);
+    }
+
+    public static SectionSnapshot take(ClientWorld world, int sectionX, int sectionY, int sectionZ) {
+        SectionSnapshot snapshot = new SectionSnapshot();
+
+        ChunkLightProvider<?, ?> skyLightProvider = world.getLightingProvider().skyLightProvider;
+        ChunkLightProvider<?, ?> blockLightProvider = world.getLightingProvider().blockLightProvider;
+        Registry<Biome> biomes = world.getRegistryManager().getOrThrow(RegistryKeys.BIOME);
+
+        for (int x = 0; x < 3; x++) {
+            for (int z = 0; z < 3; z++) {
+                WorldChunk worldChunk = (WorldChunk) world.getChunk(sectionX + x - 1, sectionZ + z - 1, ChunkStatus.FULL, false);
+                if (worldChunk == null) continue;
+
+                for (int y = 0; y < 3; y++) {
+                    int id = x + 3 * y + 9 * z;
+                    ChunkSection chunkSection;
+                    Palette<?> palette;
+                    PalettedContainer<?> section;
+                    ReadableContainer<RegistryEntry<Biome>> biomeContainer;
+                    try {
+                        chunkSection = worldChunk.getSection(world.sectionCoordToIndex(sectionY + y - 1));
+                        section = chunkSection.getBlockStateContainer();
+                        biomeContainer = chunkSection.getBiomeContainer();
+                        palette = section.data.palette;
+                    } catch (ArrayIndexOutOfBoundsException e) {
+                        continue;
+                    }
+
+                    //Indexed like the biome container, as x | z << 2 | y << 4
+                    snapshot.biomeIds[id] = new int[64];
+                    for (int cell = 0; cell < 64; cell++) {
+                        snapshot.biomeIds[id][cell] = biomes.getRawId(biomeContainer.get(cell & 3, cell >> 4, (cell >> 2) & 3).value());
+                    }
+
+                    snapshot.fluidStates[id] = packFluids(chunkSection);
+
+                    long sectionPos = ChunkSectionPos.from(sectionX + x - 1, sectionY + y - 1, sectionZ + z - 1).asLong();
+                    if (skyLightProvider != null && blockLightProvider != null) {
+                        ChunkNibbleArray skyNibble = skyLightProvider.lightStorage.uncachedStorage.get(sectionPos);
+                        ChunkNibbleArray blockNibble = blockLightProvider.lightStorage.uncachedStorage.get(sectionPos);
+                        if (skyNibble != null) {
+                            snapshot.skyIndices[id] = skyNibble.asByteArray();
+                        }
+                        if (blockNibble != null) {
+                            snapshot.blockIndices[id] = blockNibble.asByteArray();
+                        }
+                    }
+
+                    PaletteStorage paletteStorage = section.data.storage;
+
+                    if (paletteStorage instanceof PackedIntegerArray array) {
+                        RustPalette rustPalette = new RustPalette(section.idList);
+
+                        ByteBuf buf = Unpooled.buffer(palette.getPacketSize());
+                        PacketByteBuf packetBuf = new PacketByteBuf(buf);
+                        if (palette.getSize() == 1) {
+                            packetBuf.writeInt(1);
+                        }
+                        palette.writePacket(packetBuf);
+                        rustPalette.readPacket(packetBuf);
+
+                        snapshot.paletteIndices[id] = rustPalette.getSlabIndex();
+                        snapshot.storageIndices[id] = WgpuNative.createPaletteStorage(
+                                paletteStorage.getData(),
+                                array.elementsPerLong,
+                                paletteStorage.getElementBits(),
+                                array.maxValue,
+                                array.indexScale,
+                                array.indexOffset,
+                                array.indexShift,
+                                paletteStorage.getSize()
+                        );
+                    }
+                }
+            }
+        }
+
+        return snapshot;
+    }
+
+    private static byte[] packFluids(ChunkSection section) {
+        byte[] packed = null;
+
+        for (int index = 0; index < 4096; index++) {
+            FluidState fluidState = section.getFluidState(index & 15, index >> 8, (index >> 4) & 15);
+
+            int fluid;
+            if (fluidState.isIn(FluidTags.WATER)) {
+                fluid = 1;
+            } else if (fluidState.isIn(FluidTags.LAVA)) {
+                fluid = 2;
+            } else {
+                continue;
+            }
+
+            if (packed == null) {
+                packed = new byte[4096];
+            }
+
+            boolean falling = fluidState.contains(FlowableFluid.FALLING) && fluidState.get(FlowableFluid.FALLING);
+            packed[index] = (byte) (fluid | (fluidState.getLevel() - 1) << 2 | (falling ? 1 << 5 : 0));
+        }
+
+        return packed;
+    }
+}
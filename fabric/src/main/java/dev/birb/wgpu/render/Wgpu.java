@@ -2,12 +2,22 @@ package dev.birb.wgpu.render;
 
 import dev.birb.wgpu.entity.EntityState;
 import dev.birb.wgpu.palette.RustBlockStateAccessor;
+import dev.birb.wgpu.rust.WgpuNative;
 import dev.birb.wgpu.rust.WgpuTextureManager;
 import lombok.Getter;
 import lombok.Setter;
+import net.minecraft.block.Block;
 import net.minecraft.client.MinecraftClient;
+import net.minecraft.client.world.ClientWorld;
+import net.minecraft.client.render.RenderLayer;
+import net.minecraft.client.render.RenderLayers;
+import net.minecraft.registry.Registries;
+import net.minecraft.registry.Registry;
+import net.minecraft.registry.RegistryKeys;
+import net.minecraft.util.Identifier;
 import net.minecraft.util.crash.CrashReport;
 import net.minecraft.util.math.BlockPos;
+import net.minecraft.world.biome.Biome;
 import sun.misc.Unsafe;
 
 import java.lang.reflect.Field;
@@ -155,6 +165,22 @@ public class Wgpu {
         EntityState.matrixIndices.get(entity).put(part, index);
     }
 
+    // Biome ids are only known once the world's registries are synced, and can change between worlds
+    public static void registerBiomes(ClientWorld world) {
+        Registry<Biome> biomes = world.getRegistryManager().getOrThrow(RegistryKeys.BIOME);
+
+        for (Biome biome : biomes) {
+            WgpuNative.registerBiome(
+                    biomes.getRawId(biome),
+                    biome.getTemperature(),
+                    biome.weather.downfall(),
+                    biome.getWaterColor(),
+                    biome.getEffects().getGrassColor().orElse(-1),
+                    biome.getEffects().getFoliageColor().orElse(-1)
+            );
+        }
+    }
+
     public static int helperGetBlockColor(int x, int y, int z, int tintIndex) {
         MinecraftClient client = MinecraftClient.getInstance();
 
@@ -180,6 +206,38 @@ public class Wgpu {
 //        return 0xffffffff;
     }
     
+    /**
+     * The section at the given section coordinates and the ones around it, for rebaking sections whose blocks changed.
+     * Null when there's no world.
+     */
+    @SuppressWarnings("unused") // called from rust
+    public static SectionSnapshot helperSnapshotSection(int x, int y, int z) {
+        ClientWorld world = MinecraftClient.getInstance().world;
+
+        if (world == null) {
+            return null;
+        }
+
+        return SectionSnapshot.take(world, x, y, z);
+    }
+
+    /**
+     * 0 = solid, 1 = cutout, 2 = transparent
+     */
+    @SuppressWarnings("unused") // called from rust
+    public static int helperGetBlockRenderLayer(String blockId) {
+        Block block = Registries.BLOCK.get(Identifier.of(blockId));
+        RenderLayer layer = RenderLayers.getBlockLayer(block.getDefaultState());
+
+        if (layer == RenderLayer.getTranslucent() || layer == RenderLayer.getTripwire()) {
+            return 2;
+        } else if (layer == RenderLayer.getCutout() || layer == RenderLayer.getCutoutMipped()) {
+            return 1;
+        }
+
+        return 0;
+    }
+
     @SuppressWarnings("unused") // called from rust
     public static void windowFocused(boolean focused) {
         MinecraftClient.getInstance().onWindowFocusChanged(focused);
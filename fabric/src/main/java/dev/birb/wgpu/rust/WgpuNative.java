@@ -78,6 +78,12 @@ public class WgpuNative {
 
     public static native String getBackend();
 
+    public static native String getChunkBufferStats(long scene);
+
+    public static native void setSectionPos(int x, int y, int z, long scene);
+
+    public static native void markBlockDirty(int x, int y, int z, long scene);
+
     public static native void setWorldRenderState(boolean render);
 
     public static native double getMouseX();
@@ -110,7 +116,9 @@ public class WgpuNative {
 
     public static native void setCamera(double x, double y, double z, float renderYaw, float renderPitch);
 
-    public static native void bakeSection(int x, int y, int z, long[] paletteIndices, long[] storageIndices, byte[][] blockIndices, byte[][] skyIndices);
+    public static native void registerBiome(int id, float temperature, float downfall, int waterColor, int grassColor, int foliageColor);
+
+    public static native void bakeSection(int x, int y, int z, long[] paletteIndices, long[] storageIndices, byte[][] blockIndices, byte[][] skyIndices, int[][] biomeIds, byte[][] fluidStates);
 
     public static native void setMatrix(int type, float[] mat);
 
@@ -122,7 +130,7 @@ public class WgpuNative {
 
     public static native void reloadShaders();
 
-    public static native void render(float tickDelta, long startTime, boolean tick);
+    public static native void render(float tickDelta, long startTime, boolean tick, long scene, int width, int height);
 
     public static native void createDevice(long window, long getWindow, int w, int h);
 
@@ -49,6 +49,20 @@ struct VertexResult {
 
 var<push_constant> section_pos: vec3i;
 
+fn snorm8(x: u32) -> f32 {
+    return max(f32(i32(x << 24u) >> 24u) / 127.0, -1.0);
+}
+
+// Inverse of Vertex::encode_normal
+fn decode_normal(packed: u32) -> vec3<f32> {
+    var oct = vec2(snorm8(packed & 0xffu), snorm8((packed >> 8u) & 0xffu));
+    var y = 1.0 - abs(oct.x) - abs(oct.y);
+    if(y < 0.0) {
+        oct = (1.0 - abs(oct.yx)) * select(vec2(-1.0), vec2(1.0), oct >= vec2(0.0));
+    }
+    return normalize(vec3(oct.x, y, oct.y));
+}
+
 @vertex
 fn vert(
     @builtin(vertex_index) vi: u32,
@@ -74,10 +88,10 @@ fn vert(
     var v2_lc = 0.066666666666667 * vec2(f32(vert2_v4 & 15u), f32((vert2_v4 >> 4u) & 15u));
     var v3_lc = 0.066666666666667 * vec2(f32(vert3_v4 & 15u), f32((vert3_v4 >> 4u) & 15u));
     var v4_lc = 0.066666666666667 * vec2(f32(vert4_v4 & 15u), f32((vert4_v4 >> 4u) & 15u));
-    var v1_ao = f32((vert1_v4 >> 8u) & 0xff) * 0.333333;
-    var v2_ao = f32((vert2_v4 >> 8u) & 0xff) * 0.333333;
-    var v3_ao = f32((vert3_v4 >> 8u) & 0xff) * 0.333333;
-    var v4_ao = f32((vert4_v4 >> 8u) & 0xff) * 0.333333;
+    var v1_ao = f32((vert1_v4 >> 8u) & 0xff) * 0.003921568627451;
+    var v2_ao = f32((vert2_v4 >> 8u) & 0xff) * 0.003921568627451;
+    var v3_ao = f32((vert3_v4 >> 8u) & 0xff) * 0.003921568627451;
+    var v4_ao = f32((vert4_v4 >> 8u) & 0xff) * 0.003921568627451;
 
     var uv = array<vec2<f32>,4>(
             vec2(1.0,1.0),
@@ -115,10 +129,10 @@ fn vert(
 
     vr.color = vec4(f32(r) * 0.003921568627451, f32(g) * 0.003921568627451, f32(b) * 0.003921568627451, 1.0);
 
-    var ao: f32 = f32((v4 >> 8u) & 0xff) * 0.33333;
+    var ao: f32 = f32((v4 >> 8u) & 0xff) * 0.003921568627451;
 
-    var u: f32 = f32((v2 >> 16u) & 0xffffu) * 0.00048828125;
-    var v: f32 = f32(v3 & 0xffffu) * 0.00048828125;
+    var u: f32 = f32((v2 >> 16u) & 0xffffu);
+    var v: f32 = f32(v3 & 0xffffu);
 
     if(((v3 >> 29u) & 1u) == 1u) {
         x = 16.0;
@@ -140,6 +154,7 @@ fn vert(
     vr.tex_coords2 = vec2(0.0, 0.0);
     vr.world_pos = world_pos;
     vr.ao = ao;
+    vr.normal = decode_normal(v4 >> 16u);
 
     var light_coords = vec2<u32>(v4 & 15u, (v4 >> 4u) & 15u);
     vr.light_coords = 0.066666666666666 * vec2(f32(light_coords.x), f32(light_coords.y));
@@ -160,12 +175,12 @@ fn frag(
 //    var ao: f32 = (in.ao * 0.7) + 0.3;
 
     var lc = mix(mix(in.lc3, in.lc4, in.light_uv.x), mix(in.lc2, in.lc1, in.light_uv.x), in.light_uv.y);
-    var ao = 0.6 + 0.4 * mix(mix(in.ao3, in.ao4, in.light_uv.x), mix(in.ao2, in.ao1, in.light_uv.x), in.light_uv.y);
+    var ao = mix(mix(in.ao3, in.ao4, in.light_uv.x), mix(in.ao2, in.ao1, in.light_uv.x), in.light_uv.y);
 //    var ao = mix(mix(0.0, 0.0, in.light_uv.x), mix(0.0, 1.0, in.light_uv.x), in.light_uv.y);
 
     var light = max(lc.x, lc.y) * 0.7 + 0.3;
 
-    let col = in.color * vec4(light, light, light, 1.0) * vec4(ao, ao, ao, 1.0) * textureSample(t_texture, t_sampler, in.tex_coords);
+    let col = in.color * vec4(light, light, light, 1.0) * vec4(ao, ao, ao, 1.0) * textureSample(t_texture, t_sampler, in.tex_coords / vec2<f32>(textureDimensions(t_texture)));
 
 //    let light = textureSample(lightmap_texture, lightmap_sampler, vec2(max(in.light_coords.x, in.light_coords.y), 0.0));
 
@@ -5,10 +5,6 @@ struct UV {
     padding: f32
 };
 
-struct UVs {
-    uvs: array<UV>
-};
-
 struct ChunkOffset {
     x: i32,
     z: i32
@@ -24,6 +20,27 @@ struct ChunkOffset {
 
 @group(1) @binding(0) var<storage> chunk_data: array<u32>;
 
+struct SectionDraw {
+    position: vec3<i32>,
+    layer: u32,
+    vertex_offset: u32,
+    first_index: u32,
+    index_count: u32,
+    padding: u32
+}
+
+struct SectionDrawUniform {
+    camera_section: vec2<i32>,
+    draw_count: u32,
+    max_draws: u32
+}
+
+@group(2) @binding(0) var<storage> section_draws: array<SectionDraw>;
+@group(2) @binding(1) var<uniform> section_uniform: SectionDrawUniform;
+
+// Indexed by the animation index of a vertex, index 0 is for textures which aren't animated
+@group(3) @binding(0) var<storage> animated_uvs: array<UV>;
+
 struct VertexResult {
     @builtin(position) pos: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
@@ -46,13 +63,29 @@ struct VertexResult {
     @interpolate(flat) @location(17) int: u32
 };
 
-var<push_constant> section_pos: vec3i;
+fn snorm8(x: u32) -> f32 {
+    return max(f32(i32(x << 24u) >> 24u) / 127.0, -1.0);
+}
+
+// Inverse of Vertex::encode_normal
+fn decode_normal(packed: u32) -> vec3<f32> {
+    var oct = vec2(snorm8(packed & 0xffu), snorm8((packed >> 8u) & 0xffu));
+    var y = 1.0 - abs(oct.x) - abs(oct.y);
+    if(y < 0.0) {
+        oct = (1.0 - abs(oct.yx)) * select(vec2(-1.0), vec2(1.0), oct >= vec2(0.0));
+    }
+    return normalize(vec3(oct.x, y, oct.y));
+}
 
 @vertex
 fn vert(
     @builtin(vertex_index) vi: u32,
-    @builtin(instance_index) base_vertex: u32
+    @builtin(instance_index) draw_index: u32
 ) -> VertexResult {
+    var draw = section_draws[draw_index];
+    var base_vertex = draw.vertex_offset;
+    var section_pos = vec3<i32>(draw.position.x - section_uniform.camera_section.x, draw.position.y, draw.position.z - section_uniform.camera_section.y);
+
 //    var vert1_i = (vi >> 2) << 4;
 //    var vert1_i = (vi << 2) & 0xfffffffc;
 //    var vert1_i = ((vi >> 2u) << 2u)+base_vertex;
@@ -73,10 +106,10 @@ fn vert(
     var v2_lc = 0.066666666666667 * vec2(f32(vert2_v4 & 15u), f32((vert2_v4 >> 4u) & 15u));
     var v3_lc = 0.066666666666667 * vec2(f32(vert3_v4 & 15u), f32((vert3_v4 >> 4u) & 15u));
     var v4_lc = 0.066666666666667 * vec2(f32(vert4_v4 & 15u), f32((vert4_v4 >> 4u) & 15u));
-    var v1_ao = f32((vert1_v4 >> 8u) & 0xff) * 0.333333;
-    var v2_ao = f32((vert2_v4 >> 8u) & 0xff) * 0.333333;
-    var v3_ao = f32((vert3_v4 >> 8u) & 0xff) * 0.333333;
-    var v4_ao = f32((vert4_v4 >> 8u) & 0xff) * 0.333333;
+    var v1_ao = f32((vert1_v4 >> 8u) & 0xff) * 0.003921568627451;
+    var v2_ao = f32((vert2_v4 >> 8u) & 0xff) * 0.003921568627451;
+    var v3_ao = f32((vert3_v4 >> 8u) & 0xff) * 0.003921568627451;
+    var v4_ao = f32((vert4_v4 >> 8u) & 0xff) * 0.003921568627451;
 
     var uv = array<vec2<f32>,4>(
             vec2(1.0,1.0),
@@ -108,10 +141,11 @@ fn vert(
     var y: f32 = f32((v1 >> 8u) & 0xffu) * 0.0625;
     var z: f32 = f32((v1 >> 16u) & 0xffu) * 0.0625;
 
-    var ao: f32 = f32((v4 >> 8u) & 0xff) * 0.33333;
+    var ao: f32 = f32((v4 >> 8u) & 0xff) * 0.003921568627451;
 
-    var u: f32 = f32((v2 >> 16u) & 0xffffu) * 0.00048828125;
-    var v: f32 = f32(v3 & 0xffffu) * 0.00048828125;
+    var u: f32 = f32((v2 >> 16u) & 0xffffu);
+    var v: f32 = f32(v3 & 0xffffu);
+    var uv_offset: u32 = (v3 >> 16u) & 0x3ffu;
 
     if(((v3 >> 29u) & 1u) == 1u) {
         x = 16.0;
@@ -130,15 +164,22 @@ fn vert(
 
     vr.pos = mat4_persp * mat4_view * mat4_model * vec4(world_pos, 1.0);
     vr.tex_coords = vec2<f32>(u, v);
-    vr.tex_coords2 = vec2(0.0, 0.0);
+    vr.tex_coords2 = vr.tex_coords;
+    vr.blend = 0.0;
+
+    if(uv_offset != 0u) {
+        var animation = animated_uvs[uv_offset];
+        vr.tex_coords = vr.tex_coords + animation.uv1;
+        vr.tex_coords2 = vr.tex_coords2 + animation.uv2;
+        vr.blend = animation.blend;
+    }
     vr.world_pos = world_pos;
     vr.ao = ao;
+    vr.normal = decode_normal(v4 >> 16u);
 
     var light_coords = vec2<u32>(v4 & 15u, (v4 >> 4u) & 15u);
     vr.light_coords = 0.066666666666666 * vec2(f32(light_coords.x), f32(light_coords.y));
 
-    vr.blend = 0.0;
-
     return vr;
 }
 
@@ -153,12 +194,15 @@ fn frag(
 //    var ao: f32 = (in.ao * 0.7) + 0.3;
 
     var lc = mix(mix(in.lc3, in.lc4, in.light_uv.x), mix(in.lc2, in.lc1, in.light_uv.x), in.light_uv.y);
-    var ao = 0.7 + 0.3 * mix(mix(in.ao3, in.ao4, in.light_uv.x), mix(in.ao2, in.ao1, in.light_uv.x), in.light_uv.y);
+    var ao = mix(mix(in.ao3, in.ao4, in.light_uv.x), mix(in.ao2, in.ao1, in.light_uv.x), in.light_uv.y);
 //    var ao = mix(mix(0.0, 0.0, in.light_uv.x), mix(0.0, 1.0, in.light_uv.x), in.light_uv.y);
 
     var light = max(lc.x, lc.y);
 
-    let col = vec4(light, light, light, 1.0) * vec4(ao, ao, ao, 1.0) * textureSample(t_texture, t_sampler, in.tex_coords);
+    let dimensions = vec2<f32>(textureDimensions(t_texture));
+    let tex = mix(textureSample(t_texture, t_sampler, in.tex_coords / dimensions), textureSample(t_texture, t_sampler, in.tex_coords2 / dimensions), in.blend);
+
+    let col = vec4(light, light, light, 1.0) * vec4(ao, ao, ao, 1.0) * tex;
 
 //    let light = textureSample(lightmap_texture, lightmap_sampler, vec2(max(in.light_coords.x, in.light_coords.y), 0.0));
 
@@ -1,11 +1,11 @@
 use std::fmt::Debug;
-use std::time::Instant;
 
 use glam::IVec3;
 use wgpu_mc::mc::block::{BlockstateKey, ChunkBlockState};
-use wgpu_mc::mc::chunk::{bake_section, BlockStateProvider, LightLevel};
+use wgpu_mc::mc::chunk::{BlockStateProvider, Fluid, FluidState, LightLevel};
+use wgpu_mc::mc::scheduler::BakePriority;
+use wgpu_mc::mc::tint::Biome;
 use wgpu_mc::mc::Scene;
-use wgpu_mc::render::pipeline::BLOCK_ATLAS;
 use wgpu_mc::WmRenderer;
 struct SimpleBlockstateProvider(BlockstateKey);
 
@@ -37,8 +37,19 @@ impl BlockStateProvider for SimpleBlockstateProvider {
         false
     }
 
-    fn get_block_color(&self, _pos: IVec3, _tint_index: i32) -> u32 {
-        0xffffffff
+    fn get_biome(&self, _pos: IVec3) -> Option<Biome> {
+        Some(Biome::default())
+    }
+
+    //A pool of water on top of the floor in the middle of each section
+    fn get_fluid_state(&self, pos: IVec3) -> Option<FluidState> {
+        let middle = |coord: i32| (4..12).contains(&coord.rem_euclid(16));
+
+        (pos.y == 2 && middle(pos.x) && middle(pos.z)).then_some(FluidState {
+            fluid: Fluid::Water,
+            level: 8,
+            falling: false,
+        })
     }
 }
 
@@ -49,26 +60,9 @@ impl Debug for SimpleBlockstateProvider {
 }
 
 pub fn make_chunks(wm: &WmRenderer, pos: IVec3, _scene: &Scene) {
-    let bm = wm.mc.block_manager.read();
-    let atlases = wm.mc.texture_manager.atlases.read();
-    let atlas = atlases.get(BLOCK_ATLAS).unwrap();
-
-    let (index, _, block) = bm.blocks.get_full("minecraft:quartz_block").unwrap();
-
-    let (_, augment) = block
-        .get_model_by_key(
-            // [("facing", &StateValue::String("north".into()))],
-            [],
-            &*wm.mc.resource_provider,
-            atlas,
-            0,
-        )
-        .unwrap();
+    let key = wm.mc.parse_block_state("minecraft:quartz_block").unwrap();
 
-    let provider = SimpleBlockstateProvider(BlockstateKey {
-        block: index as u16,
-        augment,
-    });
-    let _time = Instant::now();
-    bake_section(pos, wm, &provider);
+    let provider = SimpleBlockstateProvider(key);
+    wm.bake_scheduler
+        .schedule(wm, pos, provider, BakePriority::Normal);
 }
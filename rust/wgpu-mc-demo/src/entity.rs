@@ -56,7 +56,9 @@ pub fn describe_entity(wm: &WmRenderer) -> (Arc<Entity>, BundledEntityInstances)
 
     let texture_bytes = wm.mc.resource_provider.get_bytes(&texture_rp).unwrap();
 
-    entity_atlas_guard.allocate([(&texture_rp, &texture_bytes)], &*wm.mc.resource_provider);
+    entity_atlas_guard
+        .allocate([(&texture_rp, &texture_bytes)], &*wm.mc.resource_provider)
+        .unwrap();
     entity_atlas_guard.upload(wm);
 
     let entity = Arc::new(Entity::new(
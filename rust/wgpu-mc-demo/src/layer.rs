@@ -0,0 +1,164 @@
+use wgpu_mc::mc::chunk::RenderLayer;
+
+/// The layer vanilla draws a block in, see `RenderLayers`. Vanilla decides this in code rather than in the resources,
+/// and the demo can't ask the game. Cutout and cutout mipped blocks both go in [RenderLayer::Cutout].
+pub fn vanilla_render_layer(block: &str) -> RenderLayer {
+    let name = block.strip_prefix("minecraft:").unwrap_or(block);
+
+    let matches = |(exact, suffixes): (&[&str], &[&str])| {
+        exact.contains(&name) || suffixes.iter().any(|suffix| name.ends_with(suffix))
+    };
+
+    if matches(VANILLA_TRANSPARENT_BLOCKS) {
+        RenderLayer::Transparent
+    } else if matches(VANILLA_CUTOUT_BLOCKS) || name.starts_with("potted_") {
+        RenderLayer::Cutout
+    } else {
+        RenderLayer::Solid
+    }
+}
+
+/// The names and name suffixes of the blocks vanilla draws translucent
+const VANILLA_TRANSPARENT_BLOCKS: (&[&str], &[&str]) = (
+    &[
+        "ice",
+        "frosted_ice",
+        "nether_portal",
+        "slime_block",
+        "honey_block",
+        "bubble_column",
+        "tinted_glass",
+        "tripwire",
+    ],
+    &["_stained_glass", "_stained_glass_pane"],
+);
+
+/// The names and name suffixes of the blocks vanilla draws cutout or cutout mipped
+const VANILLA_CUTOUT_BLOCKS: (&[&str], &[&str]) = (
+    &[
+        "grass_block",
+        "glass",
+        "glass_pane",
+        "iron_bars",
+        "chain",
+        "tripwire_hook",
+        "hopper",
+        "mangrove_roots",
+        "cobweb",
+        "short_grass",
+        "grass",
+        "tall_grass",
+        "fern",
+        "large_fern",
+        "dead_bush",
+        "seagrass",
+        "tall_seagrass",
+        "dandelion",
+        "poppy",
+        "blue_orchid",
+        "allium",
+        "azure_bluet",
+        "oxeye_daisy",
+        "cornflower",
+        "wither_rose",
+        "lily_of_the_valley",
+        "sunflower",
+        "lilac",
+        "rose_bush",
+        "peony",
+        "torchflower",
+        "torchflower_crop",
+        "pitcher_plant",
+        "pitcher_crop",
+        "pink_petals",
+        "brown_mushroom",
+        "red_mushroom",
+        "crimson_fungus",
+        "warped_fungus",
+        "crimson_roots",
+        "warped_roots",
+        "nether_sprouts",
+        "hanging_roots",
+        "fire",
+        "soul_fire",
+        "spawner",
+        "trial_spawner",
+        "vault",
+        "redstone_wire",
+        "repeater",
+        "comparator",
+        "ladder",
+        "cactus",
+        "sugar_cane",
+        "bamboo",
+        "bamboo_sapling",
+        "kelp",
+        "kelp_plant",
+        "wheat",
+        "carrots",
+        "potatoes",
+        "beetroots",
+        "nether_wart",
+        "cocoa",
+        "sweet_berry_bush",
+        "pumpkin_stem",
+        "melon_stem",
+        "attached_pumpkin_stem",
+        "attached_melon_stem",
+        "vine",
+        "glow_lichen",
+        "lily_pad",
+        "weeping_vines",
+        "weeping_vines_plant",
+        "twisting_vines",
+        "twisting_vines_plant",
+        "cave_vines",
+        "cave_vines_plant",
+        "spore_blossom",
+        "azalea",
+        "flowering_azalea",
+        "big_dripleaf",
+        "big_dripleaf_stem",
+        "small_dripleaf",
+        "mangrove_propagule",
+        "brewing_stand",
+        "beacon",
+        "flower_pot",
+        "end_rod",
+        "chorus_plant",
+        "chorus_flower",
+        "turtle_egg",
+        "sea_pickle",
+        "conduit",
+        "scaffolding",
+        "stonecutter",
+        "lantern",
+        "soul_lantern",
+        "campfire",
+        "soul_campfire",
+        "pointed_dripstone",
+        "amethyst_cluster",
+        "sculk_sensor",
+        "calibrated_sculk_sensor",
+        "sculk_shrieker",
+        "sculk_vein",
+        "frogspawn",
+        "copper_grate",
+        "rail",
+        "torch",
+    ],
+    &[
+        "_leaves",
+        "_sapling",
+        "_door",
+        "_trapdoor",
+        "_tulip",
+        "_torch",
+        "_rail",
+        "_coral",
+        "_coral_fan",
+        "_coral_wall_fan",
+        "_amethyst_bud",
+        "_copper_grate",
+    ],
+);
@@ -1,5 +1,5 @@
 use arrayvec::ArrayVec;
-use glam::{ivec2, ivec3, IVec3, Mat4};
+use glam::{ivec3, IVec3, Mat4, Vec3Swizzles};
 use parking_lot::lock_api::RwLock;
 use std::cell::{OnceCell, RefCell};
 use std::collections::HashMap;
@@ -12,11 +12,14 @@ use winit::dpi::PhysicalSize;
 
 use crate::camera::Camera;
 use crate::chunk::make_chunks;
+use crate::layer::vanilla_render_layer;
 use futures::executor::block_on;
 use wgpu_mc::mc::direction::Direction;
+use wgpu_mc::mc::resource::pack::{DirectoryPack, ResourcePack, ResourcePackStack, ZipPack};
 use wgpu_mc::mc::resource::{ResourcePath, ResourceProvider};
 use wgpu_mc::mc::Scene;
 use wgpu_mc::render::graph::{RenderGraph, ResourceBacking};
+use wgpu_mc::render::indirect::INDIRECT_CULLING_FEATURES;
 use wgpu_mc::render::shaderpack::ShaderPackConfig;
 use wgpu_mc::wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu_mc::wgpu::{BufferBindingType, Extent3d, PresentMode};
@@ -28,17 +31,33 @@ use winit::window::Window;
 
 mod camera;
 mod chunk;
+mod layer;
 
-struct FsResourceProvider {
-    pub asset_root: PathBuf,
+//The resource pack format the demo renders as, which only decides which overlays of a pack apply
+const PACK_FORMAT: u32 = 34;
+
+#[derive(Default)]
+struct Args {
+    //Resource packs given with `--pack <dir or zip>`, lowest priority first
+    packs: Vec<PathBuf>,
+    //Given with `--dump-atlases <dir>`, where every atlas is written to once the blocks are baked
+    dump_atlases: Option<PathBuf>,
 }
 
-//ResourceProvider is what wm uses to fetch resources. This is a basic implementation that's just backed by the filesystem
-impl ResourceProvider for FsResourceProvider {
-    fn get_bytes(&self, id: &ResourcePath) -> Option<Vec<u8>> {
-        let real_path = self.asset_root.join(id.0.replace(':', "/"));
+impl Args {
+    fn parse() -> Self {
+        let mut args = std::env::args().skip(1);
+        let mut parsed = Self::default();
 
-        fs::read(real_path).ok()
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "--pack" => parsed.packs.extend(args.next().map(PathBuf::from)),
+                "--dump-atlases" => parsed.dump_atlases = args.next().map(PathBuf::from),
+                _ => {}
+            }
+        }
+
+        parsed
     }
 }
 
@@ -49,6 +68,7 @@ struct Application {
     render_graph: Option<RenderGraph>,
     camera: Option<Camera>,
     last_frame: Instant,
+    start_time: Instant,
     window: OnceCell<Arc<Window>>,
 }
 impl Application {
@@ -60,6 +80,7 @@ impl Application {
             render_graph: None,
             camera: None,
             last_frame: Instant::now(),
+            start_time: Instant::now(),
             window: OnceCell::new(),
         }
     }
@@ -104,7 +125,9 @@ impl ApplicationHandler for Application {
                 required_features: wgpu::Features::default()
                     | wgpu::Features::DEPTH_CLIP_CONTROL
                     | wgpu::Features::PUSH_CONSTANTS
-                    | wgpu::Features::MULTI_DRAW_INDIRECT,
+                    | wgpu::Features::MULTI_DRAW_INDIRECT
+                    //Sections are culled on the CPU instead if these aren't supported
+                    | (adapter.features() & INDIRECT_CULLING_FEATURES),
                 required_limits,
                 memory_hints: wgpu::MemoryHints::Performance,
             },
@@ -144,13 +167,29 @@ impl ApplicationHandler for Application {
             config: RwLock::new(surface_config),
         };
 
-        let rsp = Arc::new(FsResourceProvider {
-            asset_root: crate_root::root()
-                .unwrap()
-                .join("wgpu-mc-demo")
-                .join("res")
-                .join("assets"),
-        });
+        //ResourceProvider is what wm uses to fetch resources. The demo's assets are extracted into res/assets by the
+        //build script, and any packs passed with --pack are stacked on top of them
+        let mut rsp = ResourcePackStack::new().with(
+            ResourcePack::new(
+                DirectoryPack::new(crate_root::root().unwrap().join("wgpu-mc-demo").join("res")),
+                PACK_FORMAT,
+            )
+            .unwrap(),
+        );
+
+        let args = Args::parse();
+
+        for pack in &args.packs {
+            let pack = if pack.is_dir() {
+                ResourcePack::new(DirectoryPack::new(pack), PACK_FORMAT)
+            } else {
+                ZipPack::open(pack).and_then(|zip| ResourcePack::new(zip, PACK_FORMAT))
+            };
+
+            rsp.push(pack.unwrap());
+        }
+
+        let rsp = Arc::new(rsp);
 
         let _mc_root = crate_root::root()
             .unwrap()
@@ -161,6 +200,12 @@ impl ApplicationHandler for Application {
 
         let wm = WmRenderer::new(display, rsp);
 
+        wm.mc
+            .block_manager
+            .write()
+            .biome_tints
+            .add_vanilla_sources();
+
         let blockstates_path = _mc_root.join("blockstates");
 
         let blocks = {
@@ -188,7 +233,25 @@ impl ApplicationHandler for Application {
 
         wm.init();
 
-        wm.mc.bake_blocks(&wm, blocks.iter().map(|(a, b)| (a, b)));
+        for (atlas, errors) in &wm.mc.load_atlases(&wm) {
+            log::warn!("{atlas} is missing sprites: {errors:?}");
+        }
+
+        let report = wm.mc.bake_blocks(
+            &wm,
+            blocks.iter().map(|(a, b)| (a, b, vanilla_render_layer(a))),
+        );
+
+        for (block, errors) in &report {
+            log::warn!("{block} uses the missing model: {errors:?}");
+        }
+
+        if let Some(directory) = &args.dump_atlases {
+            match wm.mc.texture_manager.dump_atlases(directory) {
+                Ok(()) => log::info!("Dumped the atlases to {}", directory.display()),
+                Err(error) => log::error!("Couldn't dump the atlases: {error:?}"),
+            }
+        }
 
         let pack = serde_yaml::from_str::<ShaderPackConfig>(
             &wm.mc
@@ -337,6 +400,16 @@ impl ApplicationHandler for Application {
                         camera.build_perspective_matrix().to_cols_array_2d();
                     let view: [[f32; 4]; 4] = camera.build_view_matrix().to_cols_array_2d();
 
+                    self.render_graph
+                        .as_mut()
+                        .unwrap()
+                        .update_atlas_textures(wm);
+
+                    //A tick is 50ms, as the game runs at 20 ticks per second
+                    wm.update_animated_textures(
+                        (self.start_time.elapsed().as_millis() / 50) as u64,
+                    );
+
                     if let ResourceBacking::Buffer(buffer, _) =
                         &self.render_graph.as_ref().unwrap().resources["@mat4_model"]
                     {
@@ -346,10 +419,14 @@ impl ApplicationHandler for Application {
                             bytemuck::cast_slice(&Mat4::IDENTITY.to_cols_array()),
                         );
                     }
-                    *self.scene.as_mut().unwrap().camera_section_pos.write() = ivec2(
-                        camera.position.x.floor() as i32 >> 4,
-                        camera.position.z.floor() as i32 >> 4,
-                    );
+                    let camera_section = camera.position.floor().as_ivec3() >> 4;
+                    *self.scene.as_mut().unwrap().camera_section_pos.write() = camera_section.xz();
+                    self.scene
+                        .as_ref()
+                        .unwrap()
+                        .section_storage
+                        .write()
+                        .set_camera_section(camera_section);
 
                     if let ResourceBacking::Buffer(buffer, _) =
                         &self.render_graph.as_ref().unwrap().resources["@mat4_perspective"]
@@ -394,6 +471,7 @@ impl ApplicationHandler for Application {
                             array_layer_count: None,
                         });
 
+                    wm.sort_translucent_sections(self.scene.as_ref().unwrap(), camera.position);
                     wm.submit_chunk_updates(self.scene.as_ref().unwrap());
 
                     let mut command_encoder = wm
@@ -17,6 +17,7 @@ use std::ffi::{c_char, c_int, CStr, CString};
 use std::num::{NonZeroIsize, NonZeroU64};
 use std::ops::Range;
 use std::sync::Arc;
+use wgpu_mc::render::indirect::INDIRECT_CULLING_FEATURES;
 use wgpu_mc::texture::TextureAndView;
 use wgpu_mc::wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu_mc::wgpu::{BufferUsages, TextureUsages, TextureViewDescriptor};
@@ -148,7 +149,9 @@ pub fn createDevice(
                 | wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY
                 | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
                 // | wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY
-                | wgpu::Features::MULTI_DRAW_INDIRECT,
+                | wgpu::Features::MULTI_DRAW_INDIRECT
+                //Sections are culled on the CPU instead if these aren't supported
+                | (adapter.features() & INDIRECT_CULLING_FEATURES),
             required_limits,
             memory_hints: wgpu::MemoryHints::Performance,
         },
@@ -173,6 +176,12 @@ pub fn createDevice(
 
     let wm = WmRenderer::new(display, resource_provider);
 
+    wm.mc
+        .block_manager
+        .write()
+        .biome_tints
+        .add_vanilla_sources();
+
     wm.init();
 
     let blit_shader = wm
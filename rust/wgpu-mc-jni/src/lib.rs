@@ -8,8 +8,10 @@ use crossbeam_channel::{unbounded, Receiver, Sender};
 use glam::{ivec2, ivec3, IVec3, Mat4};
 use jni::objects::{
     AutoElements, GlobalRef, JByteArray, JClass, JFloatArray, JIntArray, JLongArray, JObject,
-    JObjectArray, JPrimitiveArray, JString, JValue, JValueOwned, ReleaseMode, WeakRef,
+    JObjectArray, JPrimitiveArray, JStaticMethodID, JString, JValue, JValueOwned, ReleaseMode,
+    WeakRef,
 };
+use jni::signature::{Primitive, ReturnType};
 use jni::sys::{jboolean, jbyte, jfloat, jint, jlong, jsize, jstring, JNI_FALSE, JNI_TRUE};
 use jni::{JNIEnv, JavaVM};
 use jni_fn::jni_fn;
@@ -18,8 +20,7 @@ use palette::PALETTE_STORAGE;
 use parking_lot::{Mutex, RwLock};
 use pia::PIA_STORAGE;
 use rayon::{ThreadPool, ThreadPoolBuilder};
-use renderer::MATRICES;
-use std::cell::RefCell;
+use renderer::{CAMERA_POS, MATRICES};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::io::{stdout, Cursor, Write};
@@ -32,8 +33,10 @@ use wgpu_mc::render::graph::{Geometry, RenderGraph, ResourceBacking};
 use wgpu_mc::wgpu::util::DeviceExt;
 
 use wgpu_mc::mc::block::{BlockstateKey, ChunkBlockState};
-use wgpu_mc::mc::chunk::{bake_section, BlockStateProvider, LightLevel};
+use wgpu_mc::mc::chunk::{BlockStateProvider, Fluid, FluidState, LightLevel, RenderLayer};
 use wgpu_mc::mc::resource::{ResourcePath, ResourceProvider};
+use wgpu_mc::mc::scheduler::BakePriority;
+use wgpu_mc::mc::tint::Biome;
 use wgpu_mc::mc::{RenderEffectsData, Scene, SkyState};
 use wgpu_mc::minecraft_assets::schemas::blockstates::multipart::StateValue;
 use wgpu_mc::render::pipeline::BLOCK_ATLAS;
@@ -91,6 +94,9 @@ static MC_STATE: Lazy<ArcSwap<MinecraftRenderState>> = Lazy::new(|| {
     }))
 });
 
+/// Animated textures are advanced by the time since the first frame, as the game time isn't passed to [render]
+static START_TIME: Lazy<Instant> = Lazy::new(Instant::now);
+
 static CLEAR_COLOR: Lazy<ArcSwap<[f32; 3]>> = Lazy::new(|| ArcSwap::new(Arc::new([0.0; 3])));
 
 static AIR: Lazy<BlockstateKey> = Lazy::new(|| BlockstateKey {
@@ -108,7 +114,10 @@ static AIR: Lazy<BlockstateKey> = Lazy::new(|| BlockstateKey {
 });
 
 static BLOCKS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Biomes by their raw id in the world's biome registry, see [registerBiome]
+static BIOMES: RwLock<Vec<Option<Biome>>> = RwLock::new(Vec::new());
 static BLOCK_STATES: Mutex<Vec<(String, String, GlobalRef)>> = Mutex::new(Vec::new());
+static BLOCK_COLOR_HELPER: OnceCell<BlockColorHelper> = OnceCell::new();
 pub static SETTINGS: RwLock<Option<Settings>> = RwLock::new(None);
 
 pub fn call_static_from_class_loader<'env>(
@@ -139,6 +148,26 @@ pub fn call_static_from_class_loader<'env>(
 pub struct SectionHolder {
     pub block_data: Option<(JavaPalette, PackedIntegerArray)>,
     pub light_data: Option<DeserializedLightData>,
+    /// The biome of each 4x4x4 cell, indexed like vanilla's biome container as `x | z << 2 | y << 4`
+    pub biomes: Option<Box<[Option<Biome>; 64]>>,
+    /// The fluid in each block as packed by `SectionSnapshot`, or `None` if the section has no fluids
+    pub fluids: Option<Box<[u8; 4096]>>,
+}
+
+/// Unpacks a fluid packed by `SectionSnapshot`, as `fluid | (level - 1) << 2 | falling << 5` where the fluid is 1 for
+/// water and 2 for lava
+fn unpack_fluid_state(packed: u8) -> Option<FluidState> {
+    let fluid = match packed & 0b11 {
+        1 => Fluid::Water,
+        2 => Fluid::Lava,
+        _ => return None,
+    };
+
+    Some(FluidState {
+        fluid,
+        level: ((packed >> 2) & 0b111) + 1,
+        falling: packed & (1 << 5) != 0,
+    })
 }
 
 #[derive(Debug)]
@@ -211,8 +240,38 @@ impl BlockStateProvider for MinecraftBlockstateProvider {
         self.sections[(rel_pos + 1).dot(ivec3(1, 3, 9)) as usize].is_none()
     }
 
-    fn get_block_color(&self, _pos: IVec3, _tint_index: i32) -> u32 {
-        0xffffffff
+    fn get_biome(&self, pos: IVec3) -> Option<Biome> {
+        let section_pos: IVec3 = (pos >> 4) + 1;
+
+        if section_pos.cmplt(IVec3::ZERO).any() || section_pos.cmpgt(ivec3(2, 2, 2)).any() {
+            return None;
+        }
+
+        let biomes = self.sections[section_pos.dot(ivec3(1, 3, 9)) as usize]
+            .as_ref()?
+            .biomes
+            .as_ref()?;
+
+        let cell = (pos & 15) >> 2;
+
+        biomes[(cell.x | (cell.z << 2) | (cell.y << 4)) as usize]
+    }
+
+    fn get_fluid_state(&self, pos: IVec3) -> Option<FluidState> {
+        let section_pos: IVec3 = (pos >> 4) + 1;
+
+        if section_pos.cmplt(IVec3::ZERO).any() || section_pos.cmpgt(ivec3(2, 2, 2)).any() {
+            return None;
+        }
+
+        let fluids = self.sections[section_pos.dot(ivec3(1, 3, 9)) as usize]
+            .as_ref()?
+            .fluids
+            .as_ref()?;
+
+        let local = pos & 15;
+
+        unpack_fluid_state(fluids[(local.x | (local.z << 4) | (local.y << 8)) as usize])
     }
 }
 
@@ -326,20 +385,88 @@ pub fn reloadStorage(_env: JNIEnv, _class: JClass, clampedViewDistance: jint, sc
     let mut section_storage = scene.section_storage.write();
     section_storage.clear();
     section_storage.set_width(clampedViewDistance);
+
+    if let Some(wm) = RENDERER.get() {
+        wm.bake_scheduler.clear();
+    }
 }
 
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
-pub fn setSectionPos(_env: JNIEnv, _class: JClass, x: jint, z: jint, scene: jlong) {
+pub fn getChunkBufferStats(env: JNIEnv, _class: JClass, scene: jlong) -> jstring {
+    let scene = unsafe { &*(scene as *const Scene) };
+    let stats = scene.section_storage.read().stats();
+
+    env.new_string(format!(
+        "Chunk buffer: {}/{} MiB ({:.0}% used, {:.0}% fragmented)",
+        stats.used / (1024 * 1024),
+        stats.capacity / (1024 * 1024),
+        stats.occupancy() * 100.0,
+        stats.fragmentation() * 100.0
+    ))
+    .unwrap()
+    .into_raw()
+}
+
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn setSectionPos(_env: JNIEnv, _class: JClass, x: jint, y: jint, z: jint, scene: jlong) {
     let scene = unsafe { &mut *(scene as *mut Scene) };
     *scene.camera_section_pos.write() = ivec2(x, z);
+    scene
+        .section_storage
+        .write()
+        .set_camera_section(ivec3(x, y, z));
+}
+
+/// `Wgpu.helperGetBlockColor`, resolved once from a thread which can see the mod's classes so that workers don't
+/// have to look it up for every face
+struct BlockColorHelper {
+    class: GlobalRef,
+    method: JStaticMethodID,
+}
+
+impl BlockColorHelper {
+    fn resolve(env: &mut JNIEnv) -> jni::errors::Result<Self> {
+        let class = env.find_class("dev/birb/wgpu/render/Wgpu")?;
+        let method = env.get_static_method_id(&class, "helperGetBlockColor", "(IIII)I")?;
+
+        Ok(Self {
+            class: env.new_global_ref(class)?,
+            method,
+        })
+    }
+
+    fn call(&self, env: &mut JNIEnv, pos: IVec3, tint_index: i32) -> jni::errors::Result<u32> {
+        //Locals created by the call are freed with the frame instead of piling up on the attached worker
+        env.with_local_frame(4, |env| {
+            let class: &JClass = self.class.as_obj().into();
+
+            unsafe {
+                env.call_static_method_unchecked(
+                    class,
+                    self.method,
+                    ReturnType::Primitive(Primitive::Int),
+                    &[
+                        JValue::Int(pos.x).as_jni(),
+                        JValue::Int(pos.y).as_jni(),
+                        JValue::Int(pos.z).as_jni(),
+                        JValue::Int(tint_index).as_jni(),
+                    ],
+                )
+            }?
+            .i()
+            .map(|color| color as u32)
+        })
+    }
 }
 
-struct MinecraftBlockStateProviderWrapper<'a> {
+/// Baked on the bake scheduler's workers, which attach themselves to the JVM to look up block colors
+struct MinecraftBlockStateProviderWrapper {
     internal: MinecraftBlockstateProvider,
-    env: RefCell<JNIEnv<'a>>,
+    jvm: JavaVM,
+    block_colors: Option<&'static BlockColorHelper>,
 }
 
-impl<'a> BlockStateProvider for MinecraftBlockStateProviderWrapper<'a> {
+impl BlockStateProvider for MinecraftBlockStateProviderWrapper {
     fn get_state(&self, pos: IVec3) -> ChunkBlockState {
         self.internal.get_state(pos)
     }
@@ -352,44 +479,55 @@ impl<'a> BlockStateProvider for MinecraftBlockStateProviderWrapper<'a> {
         self.internal.is_section_empty(rel_pos)
     }
 
+    fn get_biome(&self, pos: IVec3) -> Option<Biome> {
+        self.internal.get_biome(pos)
+    }
+
+    fn get_fluid_state(&self, pos: IVec3) -> Option<FluidState> {
+        self.internal.get_fluid_state(pos)
+    }
+
     fn get_block_color(&self, pos: IVec3, tint_index: i32) -> u32 {
-        self.env
-            .borrow_mut()
-            .call_static_method(
-                "dev/birb/wgpu/render/Wgpu",
-                "helperGetBlockColor",
-                "(IIII)I",
-                &[
-                    JValue::Int(pos.x),
-                    JValue::Int(pos.y),
-                    JValue::Int(pos.z),
-                    JValue::Int(tint_index),
-                ],
-            )
-            .unwrap()
-            .i()
-            .unwrap() as u32
+        let Some(block_colors) = self.block_colors else {
+            return 0xffffffff;
+        };
+
+        let Ok(mut env) = self.jvm.attach_current_thread_as_daemon() else {
+            return 0xffffffff;
+        };
+
+        match block_colors.call(&mut env, pos, tint_index) {
+            Ok(color) => color,
+            Err(error) => {
+                //Leaving the exception pending would break the next call on this thread
+                if let jni::errors::Error::JavaException = error {
+                    let _ = env.exception_clear();
+                }
+
+                log::warn!("Couldn't get the color of the block at {pos}: {error}");
+
+                0xffffffff
+            }
+        }
     }
 }
 
-#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
-pub fn bakeSection(
-    mut env: JNIEnv,
-    _class: JClass,
-    x: jint,
-    y: jint,
-    z: jint,
-    paletteIndices: JLongArray,
-    storageIndices: JLongArray,
-    blockBytes: JObjectArray,
-    skyBytes: JObjectArray,
-) {
+/// Reads a section and the ones around it as passed to [bakeSection]
+fn section_provider(
+    env: &mut JNIEnv,
+    palette_indices: &JLongArray,
+    storage_indices: &JLongArray,
+    block_light: &JObjectArray,
+    sky_light: &JObjectArray,
+    biome_ids: &JObjectArray,
+    fluid_states: &JObjectArray,
+) -> MinecraftBlockStateProviderWrapper {
     let palette_elements =
-        unsafe { env.get_array_elements(&paletteIndices, ReleaseMode::NoCopyBack) }.unwrap();
+        unsafe { env.get_array_elements(palette_indices, ReleaseMode::NoCopyBack) }.unwrap();
     let palettes =
         unsafe { slice::from_raw_parts(palette_elements.as_ptr(), palette_elements.len()) };
     let storage_elements =
-        unsafe { env.get_array_elements(&storageIndices, ReleaseMode::NoCopyBack) }.unwrap();
+        unsafe { env.get_array_elements(storage_indices, ReleaseMode::NoCopyBack) }.unwrap();
     let storages =
         unsafe { slice::from_raw_parts(storage_elements.as_ptr(), storage_elements.len()) };
     const NONE: Option<SectionHolder> = None;
@@ -398,6 +536,8 @@ pub fn bakeSection(
         air: *AIR,
     };
 
+    let registered_biomes = BIOMES.read();
+
     for i in 0..27 {
         let mut palette_storage = PALETTE_STORAGE.write();
         let mut pia_storage = PIA_STORAGE.write();
@@ -414,7 +554,7 @@ pub fn bakeSection(
         };
         let sky_array = unsafe {
             JPrimitiveArray::from_raw(
-                env.get_object_array_element(&skyBytes, i as jsize)
+                env.get_object_array_element(sky_light, i as jsize)
                     .unwrap()
                     .into_raw(),
             )
@@ -423,7 +563,7 @@ pub fn bakeSection(
             unsafe { env.get_array_elements(&sky_array, ReleaseMode::NoCopyBack) }.unwrap();
         let block_array = unsafe {
             JPrimitiveArray::from_raw(
-                env.get_object_array_element(&blockBytes, i as jsize)
+                env.get_object_array_element(block_light, i as jsize)
                     .unwrap()
                     .into_raw(),
             )
@@ -431,8 +571,46 @@ pub fn bakeSection(
         let block_bytes =
             unsafe { env.get_array_elements(&block_array, ReleaseMode::NoCopyBack) }.unwrap();
 
+        //Sections outside of the world have no biomes
+        let biome_array: JIntArray = env
+            .get_object_array_element(&biome_ids, i as jsize)
+            .unwrap()
+            .into();
+
+        let biomes = if biome_array.is_null() {
+            None
+        } else {
+            let ids =
+                unsafe { env.get_array_elements(&biome_array, ReleaseMode::NoCopyBack) }.unwrap();
+
+            let mut biomes = Box::new([None; 64]);
+            biomes.iter_mut().zip(ids.iter()).for_each(|(biome, &id)| {
+                *biome = registered_biomes.get(id as usize).copied().flatten();
+            });
+
+            Some(biomes)
+        };
+
+        //Only sections with fluids in them have an array
+        let fluid_array: JByteArray = env
+            .get_object_array_element(fluid_states, i as jsize)
+            .unwrap()
+            .into();
+
+        let fluids = if fluid_array.is_null() {
+            None
+        } else {
+            let mut fluids = Box::new([0u8; 4096]);
+            env.get_byte_array_region(&fluid_array, 0, bytemuck::cast_slice_mut(&mut fluids[..]))
+                .unwrap();
+
+            Some(fluids)
+        };
+
         bsp.sections[i] = Some(SectionHolder {
             block_data,
+            biomes,
+            fluids,
             light_data: Some(DeserializedLightData {
                 sky_light: Box::new(
                     unsafe { slice::from_raw_parts(sky_bytes.as_ptr(), sky_bytes.len()) }
@@ -448,17 +626,104 @@ pub fn bakeSection(
         });
     }
 
-    // THREAD_POOL.get().unwrap().spawn(move || {
-    let wm = RENDERER.get().unwrap();
-    // let env = jvm.attach_current_thread_as_daemon().unwrap();
+    let block_colors = BLOCK_COLOR_HELPER
+        .get_or_try_init(|| BlockColorHelper::resolve(env))
+        .map_err(|error| log::error!("Couldn't resolve Wgpu.helperGetBlockColor: {error}"))
+        .ok();
 
-    let wrapper = MinecraftBlockStateProviderWrapper {
+    MinecraftBlockStateProviderWrapper {
         internal: bsp,
-        env: RefCell::new(env),
-    };
+        jvm: env.get_java_vm().unwrap(),
+        block_colors,
+    }
+}
+
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn bakeSection(
+    mut env: JNIEnv,
+    _class: JClass,
+    x: jint,
+    y: jint,
+    z: jint,
+    paletteIndices: JLongArray,
+    storageIndices: JLongArray,
+    blockBytes: JObjectArray,
+    skyBytes: JObjectArray,
+    biomeIds: JObjectArray,
+    fluidStates: JObjectArray,
+) {
+    let wm = RENDERER.get().unwrap();
+
+    let wrapper = section_provider(
+        &mut env,
+        &paletteIndices,
+        &storageIndices,
+        &blockBytes,
+        &skyBytes,
+        &biomeIds,
+        &fluidStates,
+    );
+
+    wm.bake_scheduler
+        .schedule(wm, ivec3(x, y, z), wrapper, BakePriority::Normal);
+}
 
-    bake_section(ivec3(x, y, z), wm, &wrapper);
-    // })
+/// Takes a new snapshot of a section through `Wgpu.helperSnapshotSection`, to rebake it after its blocks changed.
+/// `None` if there's no world to take it from.
+fn snapshot_section(
+    env: &mut JNIEnv,
+    pos: IVec3,
+) -> jni::errors::Result<Option<MinecraftBlockStateProviderWrapper>> {
+    env.with_local_frame(16, |env| {
+        let snapshot = env
+            .call_static_method(
+                "dev/birb/wgpu/render/Wgpu",
+                "helperSnapshotSection",
+                "(III)Ldev/birb/wgpu/render/SectionSnapshot;",
+                &[JValue::Int(pos.x), JValue::Int(pos.y), JValue::Int(pos.z)],
+            )?
+            .l()?;
+
+        if snapshot.is_null() {
+            return Ok(None);
+        }
+
+        let palette_indices: JLongArray = env
+            .get_field(&snapshot, "paletteIndices", "[J")?
+            .l()?
+            .into();
+        let storage_indices: JLongArray = env
+            .get_field(&snapshot, "storageIndices", "[J")?
+            .l()?
+            .into();
+        let block_light: JObjectArray =
+            env.get_field(&snapshot, "blockIndices", "[[B")?.l()?.into();
+        let sky_light: JObjectArray = env.get_field(&snapshot, "skyIndices", "[[B")?.l()?.into();
+        let biome_ids: JObjectArray = env.get_field(&snapshot, "biomeIds", "[[I")?.l()?.into();
+        let fluid_states: JObjectArray =
+            env.get_field(&snapshot, "fluidStates", "[[B")?.l()?.into();
+
+        Ok(Some(section_provider(
+            env,
+            &palette_indices,
+            &storage_indices,
+            &block_light,
+            &sky_light,
+            &biome_ids,
+            &fluid_states,
+        )))
+    })
+}
+
+/// Marks the sections around a block which changed to be rebaked during the next [render]
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn markBlockDirty(_env: JNIEnv, _class: JClass, x: jint, y: jint, z: jint, scene: jlong) {
+    let scene = unsafe { &*(scene as *const Scene) };
+
+    scene
+        .section_storage
+        .write()
+        .mark_block_dirty(ivec3(x, y, z));
 }
 
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
@@ -468,13 +733,43 @@ pub fn registerBlock(mut env: JNIEnv, _class: JClass, name: JString) {
     BLOCKS.lock().push(name);
 }
 
+/// Registers the climate of a biome by its raw id, so that sections can be tinted without calling back into Java.
+/// Grass and foliage colours are `-1` when the biome uses the colormaps.
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
-pub fn render(
+pub fn registerBiome(
     _env: JNIEnv,
     _class: JClass,
+    id: jint,
+    temperature: jfloat,
+    downfall: jfloat,
+    waterColor: jint,
+    grassColor: jint,
+    foliageColor: jint,
+) {
+    let color = |color: jint| (color != -1).then_some(color as u32 & 0xffffff);
+
+    let mut biomes = BIOMES.write();
+
+    if biomes.len() <= id as usize {
+        biomes.resize(id as usize + 1, None);
+    }
+
+    biomes[id as usize] = Some(Biome {
+        temperature,
+        downfall,
+        water_color: waterColor as u32 & 0xffffff,
+        grass_color: color(grassColor),
+        foliage_color: color(foliageColor),
+    });
+}
+
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn render(
+    mut env: JNIEnv,
+    _class: JClass,
     _tick_delta: jfloat,
     _start_time: jlong,
-    _tick: jlong,
+    _tick: jboolean,
     scene: jlong,
     width: jint,
     height: jint,
@@ -483,15 +778,34 @@ pub fn render(
     let height = height as u32;
 
     let wm = RENDERER.get().unwrap();
-    let render_graph = RENDER_GRAPH.get().unwrap().lock();
+    let mut render_graph = RENDER_GRAPH.get().unwrap().lock();
     let mut geometry = CUSTOM_GEOMETRY.get().unwrap().lock();
     let scene = unsafe { &mut *(scene as *mut Scene) };
 
+    wm.rebake_dirty_sections(scene, |pos| {
+        snapshot_section(&mut env, pos)
+            .map_err(|error| {
+                if let jni::errors::Error::JavaException = error {
+                    let _ = env.exception_clear();
+                }
+
+                log::warn!("Couldn't take a snapshot of section {pos} to rebake it: {error}");
+            })
+            .ok()
+            .flatten()
+    });
     wm.submit_chunk_updates(scene);
+    render_graph.update_atlas_textures(wm);
+    //A tick is 50ms, as the game runs at 20 ticks per second
+    wm.update_animated_textures((START_TIME.elapsed().as_millis() / 50) as u64);
     let pos = *scene.camera_section_pos.read();
     scene.section_storage.write().trim(pos);
     *scene.entity_instances.lock() = ENTITY_INSTANCES.lock().clone();
 
+    if let Some(camera_pos) = *CAMERA_POS.lock() {
+        wm.sort_translucent_sections(scene, camera_pos);
+    }
+
     let matrices = MATRICES.lock();
     if let ResourceBacking::Buffer(buffer, _) = &render_graph.resources["@mat4_perspective"] {
         wm.gpu
@@ -566,21 +880,56 @@ pub fn cacheBlockStates(mut env: JNIEnv, _class: JClass) {
         let blockstates = blocks
             .iter()
             .map(|identifier| {
+                let jidentifier = env.new_string(identifier).unwrap();
+
+                let layer = match env
+                    .call_static_method(
+                        "dev/birb/wgpu/render/Wgpu",
+                        "helperGetBlockRenderLayer",
+                        "(Ljava/lang/String;)I",
+                        &[JValue::Object(&jidentifier.into())],
+                    )
+                    .and_then(|layer| layer.i())
+                {
+                    Ok(1) => RenderLayer::Cutout,
+                    Ok(2) => RenderLayer::Transparent,
+                    Ok(_) => RenderLayer::Solid,
+                    Err(error) => {
+                        //Leaving the exception pending would break the next call
+                        if let jni::errors::Error::JavaException = error {
+                            let _ = env.exception_clear();
+                        }
+
+                        log::warn!(
+                            "Couldn't get the render layer of {identifier}, drawing it solid: {error}"
+                        );
+
+                        RenderLayer::Solid
+                    }
+                };
+
                 (
                     identifier.clone(),
-                    ResourcePath::from(&identifier[..])
-                        .prepend("blockstates/")
-                        .append(".json"),
+                    ResourcePath::from(&identifier[..]).blockstate_file(),
+                    layer,
                 )
             })
             .collect::<Vec<_>>();
 
-        wm.mc.bake_blocks(
+        for (atlas, errors) in &wm.mc.load_atlases(wm) {
+            log::warn!("{atlas} is missing sprites: {errors:?}");
+        }
+
+        let report = wm.mc.bake_blocks(
             wm,
             blockstates
                 .iter()
-                .map(|(string, resource)| (string, resource)),
+                .map(|(string, resource, layer)| (string, resource, *layer)),
         );
+
+        for (block, errors) in &report {
+            log::warn!("{block} uses the missing model: {errors:?}");
+        }
     }
 
     let mut states = BLOCK_STATES.lock();
@@ -1,6 +1,7 @@
 use byteorder::LittleEndian;
+use glam::{dvec3, Vec3};
 use jni::objects::{AutoElements, JClass, JFloatArray, ReleaseMode};
-use jni::sys::{jfloat, jint, jlong};
+use jni::sys::{jdouble, jfloat, jint, jlong};
 use jni::{objects::JString, JNIEnv};
 use jni_fn::jni_fn;
 use once_cell::sync::Lazy;
@@ -31,6 +32,9 @@ pub struct Matrices {
     pub terrain_transformation: [[f32; 4]; 4],
 }
 
+/// The camera's position in world space, which translucent geometry is sorted against
+pub static CAMERA_POS: Mutex<Option<Vec3>> = Mutex::new(None);
+
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
 pub fn reloadShaders(_env: JNIEnv, _class: JClass) {
     load_shaders(RENDERER.get().unwrap());
@@ -70,6 +74,19 @@ pub fn setMatrix(mut env: JNIEnv, _class: JClass, id: jint, float_array: JFloatA
     }
 }
 
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn setCamera(
+    _env: JNIEnv,
+    _class: JClass,
+    x: jdouble,
+    y: jdouble,
+    z: jdouble,
+    _yaw: jfloat,
+    _pitch: jfloat,
+) {
+    *CAMERA_POS.lock() = Some(dvec3(x, y, z).as_vec3());
+}
+
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
 pub fn scheduleStop(_env: JNIEnv, _class: JClass) {
     let _ = SHOULD_STOP.set(());
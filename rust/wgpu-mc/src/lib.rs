@@ -38,13 +38,12 @@ so it's up to you to provide them to wgpu-mc.
 See the [render::entity] module for an example of rendering an example entity.
  */
 
-use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
 
-use glam::IVec3;
-use mc::chunk::BakedLayer;
+use glam::{IVec3, Vec3};
+use mc::chunk::{sort_translucent_indices, BakedSection, BlockStateProvider, RenderLayer};
 use mc::Scene;
 pub use minecraft_assets;
 use parking_lot::{Mutex, RwLock};
@@ -54,7 +53,9 @@ use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
 use crate::mc::resource::ResourceProvider;
+use crate::mc::scheduler::{BakePriority, BakeScheduler, DEFAULT_CHUNK_UPLOAD_BUDGET};
 use crate::mc::MinecraftState;
+use crate::render::animation::AnimatedUV;
 use crate::render::atlas::Atlas;
 use crate::render::pipeline::{create_bind_group_layouts, BLOCK_ATLAS, ENTITY_ATLAS};
 
@@ -75,8 +76,11 @@ pub struct Display {
     pub config: RwLock<wgpu::SurfaceConfiguration>,
 }
 
-/// Tuple of chunk positions and baked layers
-pub type ChunkUpdateData = (IVec3, Vec<BakedLayer>);
+/// Tuple of chunk positions and baked sections
+pub type ChunkUpdateData = (IVec3, BakedSection);
+
+/// Tuple of a section position, the centroids which were sorted, and the sorted index data of its translucent layer
+pub type TranslucentSortData = (IVec3, Arc<Vec<Vec3>>, Vec<u8>);
 
 /// The main wgpu-mc renderer struct
 /// Resources pertaining to Minecraft go in `MinecraftState`.
@@ -87,6 +91,13 @@ pub struct WmRenderer {
     pub bind_group_layouts: Arc<HashMap<String, BindGroupLayout>>,
     pub mc: MinecraftState,
     pub chunk_update_queue: (Sender<ChunkUpdateData>, Mutex<Receiver<ChunkUpdateData>>),
+    pub translucent_sort_queue: (
+        Sender<TranslucentSortData>,
+        Mutex<Receiver<TranslucentSortData>>,
+    ),
+    pub bake_scheduler: BakeScheduler,
+    /// How many bytes of baked sections are uploaded per frame at most, at least one section is always uploaded
+    pub chunk_upload_budget: RwLock<usize>,
 }
 
 #[derive(Copy, Clone)]
@@ -103,32 +114,54 @@ impl WmRenderer {
     pub fn new(display: Display, resource_provider: Arc<dyn ResourceProvider>) -> WmRenderer {
         let mc = MinecraftState::new(&display, resource_provider);
         let (sender, receiver) = channel();
+        let (sort_sender, sort_receiver) = channel();
         Self {
             bind_group_layouts: Arc::new(create_bind_group_layouts(&display.device)),
             gpu: display,
             mc,
             chunk_update_queue: (sender, Mutex::new(receiver)),
+            translucent_sort_queue: (sort_sender, Mutex::new(sort_receiver)),
+            //Leave a core for the render thread
+            bake_scheduler: BakeScheduler::new(
+                std::thread::available_parallelism().map_or(1, |threads| threads.get() - 1),
+            ),
+            chunk_upload_budget: RwLock::new(DEFAULT_CHUNK_UPLOAD_BUDGET),
         }
     }
 
     pub fn init(&self) {
-        let atlases = [BLOCK_ATLAS, ENTITY_ATLAS]
+        let mip_levels = *self.mc.mip_levels.read();
+
+        //The entity shaders assume the entity atlas is ATLAS_DIMENSIONS wide, so it can't grow
+        let atlases = [(BLOCK_ATLAS, true, mip_levels), (ENTITY_ATLAS, false, 0)]
             .iter()
-            .map(|&name| (name.into(), Atlas::new(&self.gpu, false)))
+            .map(|&(name, resizes, mip_levels)| {
+                (
+                    name.into(),
+                    Atlas::with_mip_levels(&self.gpu, resizes, mip_levels),
+                )
+            })
             .collect();
 
         *self.mc.texture_manager.atlases.write() = atlases;
+
+        //The terrain pipelines need the buffer bound before any animated textures are loaded
+        self.update_animated_textures(0);
     }
 
-    pub fn upload_animated_block_buffer(&self, data: Vec<f32>) {
-        let d = data.as_slice();
+    /// Writes the frame offsets of the animated block textures to the SSBO the terrain shader reads them from. The
+    /// buffer is replaced by a bigger one if they don't fit, along with its bind group.
+    pub fn upload_animated_block_buffer(&self, data: &[AnimatedUV]) {
+        let d: &[u8] = bytemuck::cast_slice(data);
 
-        let buf = self.mc.animated_block_buffer.borrow().load_full();
+        let fits = (**self.mc.animated_block_buffer.load())
+            .as_ref()
+            .is_some_and(|buffer| buffer.size() >= d.len() as wgpu::BufferAddress);
 
-        if buf.is_none() {
+        if !fits {
             let animated_block_buffer = self.gpu.device.create_buffer(&BufferDescriptor {
                 label: None,
-                size: (d.len() * 8) as wgpu::BufferAddress,
+                size: d.len() as wgpu::BufferAddress,
                 usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
                 mapped_at_creation: false,
             });
@@ -155,34 +188,161 @@ impl WmRenderer {
         self.gpu.queue.write_buffer(
             (**self.mc.animated_block_buffer.load()).as_ref().unwrap(),
             0,
-            bytemuck::cast_slice(d),
+            d,
         );
     }
 
+    /// Advances the animated block textures to a tick, which is usually the game time. Should be called every frame
+    /// or tick, see [Atlas::animation_frames].
+    pub fn update_animated_textures(&self, tick: u64) {
+        let frames = match self.mc.texture_manager.atlases.read().get(BLOCK_ATLAS) {
+            Some(atlas) => atlas.animation_frames(tick),
+            None => return,
+        };
+
+        self.upload_animated_block_buffer(&frames);
+    }
+
+    /// Uploads baked sections until [WmRenderer::chunk_upload_budget] is used up, leaving the rest for the
+    /// following frames
     pub fn submit_chunk_updates(&self, scene: &Scene) {
+        self.bake_scheduler
+            .set_camera_section(*scene.camera_section_pos.read());
+
         let receiver = self.chunk_update_queue.1.lock();
-        let updates = receiver.try_iter();
+        let budget = (*self.chunk_upload_budget.read()).max(1);
+        let mut uploaded = 0;
+
+        while uploaded < budget {
+            let Ok((pos, baked_section)) = receiver.try_recv() else {
+                break;
+            };
+
+            if !self.bake_scheduler.take_result(pos) {
+                continue;
+            }
+
+            uploaded += baked_section
+                .layers
+                .iter()
+                .map(|layer| layer.vertices.len() + layer.indices.len())
+                .sum::<usize>();
 
-        updates.for_each(|(pos, layers)| {
             let mut storage = scene.section_storage.write();
-            let section = storage.replace(pos, &layers);
+            let section = storage.replace(pos, &baked_section);
+            let layers = &baked_section.layers;
+
+            //The buffer has to be moved before anything is written to the new ranges
+            if let Some(moves) = storage.take_relocation() {
+                scene.relocate_chunk_buffer(self, storage.capacity(), &moves);
+            }
+
+            let chunk_buffer = scene.chunk_buffer.load();
+
             for (i, ranges) in section.layers.iter().enumerate() {
                 if let Some(ranges) = ranges {
                     self.gpu.queue.write_buffer(
-                        &scene.chunk_buffer.buffer,
+                        &chunk_buffer.buffer,
                         ranges.vertex_range.start as u64 * 4,
                         &layers[i].vertices,
                     );
                     self.gpu.queue.write_buffer(
-                        &scene.chunk_buffer.buffer,
+                        &chunk_buffer.buffer,
                         ranges.index_range.start as u64 * 4,
                         &layers[i].indices,
                     );
                 }
             }
+        }
+
+        {
+            let mut storage = scene.section_storage.write();
+
+            if storage.needs_compaction() {
+                storage.compact();
+
+                if let Some(moves) = storage.take_relocation() {
+                    scene.relocate_chunk_buffer(self, storage.capacity(), &moves);
+                }
+            }
+        }
+
+        let receiver = self.translucent_sort_queue.1.lock();
+        let storage = scene.section_storage.read();
+        let chunk_buffer = scene.chunk_buffer.load();
+
+        receiver.try_iter().for_each(|(pos, centroids, indices)| {
+            let section = match storage.get(&pos) {
+                Some(section) => section,
+                None => return,
+            };
+
+            //The section may have been rebaked while it was being sorted
+            let current = section
+                .translucent_centroids
+                .as_ref()
+                .is_some_and(|current| Arc::ptr_eq(current, &centroids));
+
+            if !current {
+                return;
+            }
+
+            if let Some(Some(ranges)) = section.layers.get(RenderLayer::Transparent as usize) {
+                self.gpu.queue.write_buffer(
+                    &chunk_buffer.buffer,
+                    ranges.index_range.start as u64 * 4,
+                    &indices,
+                );
+            }
+        });
+    }
+
+    /// Sorts the translucent geometry of every section back to front on a worker thread, once the camera has
+    /// moved far enough. Only the index data is uploaded again, during [WmRenderer::submit_chunk_updates].
+    pub fn sort_translucent_sections(&self, scene: &Scene, camera_pos: Vec3) {
+        let jobs = scene
+            .section_storage
+            .write()
+            .translucent_sort_jobs(camera_pos);
+
+        if jobs.is_empty() {
+            return;
+        }
+
+        let sender = self.translucent_sort_queue.0.clone();
+
+        rayon::spawn(move || {
+            for (pos, centroids) in jobs {
+                let indices =
+                    sort_translucent_indices(&centroids, camera_pos - (pos * 16).as_vec3());
+
+                if sender.send((pos, centroids, indices)).is_err() {
+                    return;
+                }
+            }
         });
     }
 
+    /// Rebakes the sections affected by blocks marked with [SectionStorage::mark_block_dirty] ahead of any other
+    /// bakes. Call once per frame, so that several changes to the same section within a frame only rebake it once.
+    /// `provider` supplies the blocks around each section, or `None` to skip it.
+    ///
+    /// [SectionStorage::mark_block_dirty]: mc::chunk::SectionStorage::mark_block_dirty
+    pub fn rebake_dirty_sections<Provider: BlockStateProvider + Send + 'static>(
+        &self,
+        scene: &Scene,
+        mut provider: impl FnMut(IVec3) -> Option<Provider>,
+    ) {
+        let dirty = scene.section_storage.write().take_dirty_sections();
+
+        for pos in dirty {
+            if let Some(provider) = provider(pos) {
+                self.bake_scheduler
+                    .schedule(self, pos, provider, BakePriority::High);
+            }
+        }
+    }
+
     pub fn get_backend_description(&self) -> String {
         format!(
             "wgpu {} ({})",
@@ -1,14 +1,19 @@
-use crate::mc::chunk::RenderLayer;
-use glam::{vec3, Mat3, Vec3};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use glam::{vec2, vec3, IVec3, Mat3, Vec2, Vec3};
 use itertools::Itertools;
 use minecraft_assets::api::ModelResolver;
 use minecraft_assets::schemas;
 use minecraft_assets::schemas::blockstates::ModelProperties;
+use parking_lot::RwLock;
 use serde_derive::{Deserialize, Serialize};
 
+use crate::mc::chunk::RenderLayer;
 use crate::mc::direction::Direction;
 use crate::mc::resource::{ResourcePath, ResourceProvider};
-use crate::render::atlas::Atlas;
+use crate::render::atlas::{Atlas, AtlasError};
 use crate::texture::UV;
 
 /// A block position: x, y, z
@@ -69,99 +74,545 @@ pub struct BlockModelFace {
     pub normal: Vec3,
     pub tint_index: i32,
     pub animation_uv_offset: u32,
+    /// Whether the face is darkened depending on the direction it faces
+    pub shade: bool,
+    /// Whether the face receives smooth lighting, `false` if the model turns off `ambientocclusion`
+    pub ambient_occlusion: bool,
 }
 
-fn recurse_model_parents(
-    model: &schemas::Model,
-    resource_provider: &dyn ResourceProvider,
-    models: &mut Vec<ResourcePath>,
-) {
-    if let Some(parent_path_string) = &model.parent {
-        let parent_path: ResourcePath = ResourcePath::from(parent_path_string)
-            .prepend("models/")
-            .append(".json");
-        recurse_model_parents(
-            &serde_json::from_str(
+/// Models nested deeper than this are assumed to have a cycle in their parents
+const MAX_MODEL_DEPTH: usize = 64;
+
+/// Parsed and resolved block models by path. Shared by every model baked by one call of
+/// [MinecraftState::bake_blocks](crate::mc::MinecraftState::bake_blocks), so that common parents like
+/// `block/cube_all` are only read once
+#[derive(Debug, Default)]
+pub struct ModelCache {
+    /// Models as they are in their JSON files
+    parsed: RwLock<HashMap<ResourcePath, Arc<schemas::Model>>>,
+    /// Models merged with their parents, with their texture references resolved
+    resolved: RwLock<HashMap<ResourcePath, Arc<schemas::Model>>>,
+}
+
+impl ModelCache {
+    /// The path of the model JSON of a blockstate variant
+    pub fn model_path(model_properties: &ModelProperties) -> ResourcePath {
+        ResourcePath::from(&model_properties.model).model_file()
+    }
+
+    fn parse(
+        &self,
+        path: &ResourcePath,
+        resource_provider: &dyn ResourceProvider,
+    ) -> Result<Arc<schemas::Model>, MeshBakeError> {
+        if let Some(model) = self.parsed.read().get(path) {
+            return Ok(model.clone());
+        }
+
+        let model: Arc<schemas::Model> = Arc::new(
+            serde_json::from_str(
                 &resource_provider
-                    .get_string(&parent_path)
-                    .expect(&parent_path.0),
+                    .get_string(path)
+                    .ok_or_else(|| MeshBakeError::UnresolvedResourcePath(path.clone()))?,
             )
-            .unwrap(),
-            resource_provider,
-            models,
+            .map_err(MeshBakeError::JsonError)?,
         );
-        models.push(parent_path);
+
+        self.parsed.write().insert(path.clone(), model.clone());
+
+        Ok(model)
     }
-}
 
-fn resolve_model(
-    model: schemas::Model,
-    resource_provider: &dyn ResourceProvider,
-) -> schemas::Model {
-    if model.parent.is_none() {
-        return model;
+    fn collect_parents(
+        &self,
+        model: &schemas::Model,
+        resource_provider: &dyn ResourceProvider,
+        parents: &mut Vec<Arc<schemas::Model>>,
+    ) -> Result<(), MeshBakeError> {
+        if let Some(parent_path_string) = &model.parent {
+            let parent_path: ResourcePath = ResourcePath::from(parent_path_string).model_file();
+
+            if parents.len() >= MAX_MODEL_DEPTH {
+                return Err(MeshBakeError::ParentCycle(parent_path));
+            }
+
+            let parent = self.parse(&parent_path, resource_provider)?;
+
+            self.collect_parents(&parent, resource_provider, parents)?;
+            parents.push(parent);
+        }
+
+        Ok(())
     }
 
-    let mut parent_paths = Vec::new();
-    recurse_model_parents(&model, resource_provider, &mut parent_paths);
+    /// The model at `path`, merged with its parents
+    pub fn resolve(
+        &self,
+        path: &ResourcePath,
+        resource_provider: &dyn ResourceProvider,
+    ) -> Result<Arc<schemas::Model>, MeshBakeError> {
+        if let Some(model) = self.resolved.read().get(path) {
+            return Ok(model.clone());
+        }
 
-    let parents: Vec<schemas::Model> = parent_paths
-        .iter()
-        .map(|parent_path| {
-            serde_json::from_str(&resource_provider.get_string(parent_path).unwrap()).unwrap()
-        })
-        .collect();
+        let model = self.parse(path, resource_provider)?;
+
+        let resolved = if model.parent.is_none() {
+            model
+        } else {
+            let mut parents = Vec::new();
+            self.collect_parents(&model, resource_provider, &mut parents)?;
 
-    let mut schema = ModelResolver::resolve_model([&model].into_iter().chain(parents.iter()));
+            let mut schema = ModelResolver::resolve_model(
+                [&*model]
+                    .into_iter()
+                    .chain(parents.iter().map(|parent| &**parent)),
+            );
 
-    if let Some(textures) = &mut schema.textures {
-        let copy = textures.clone();
+            if let Some(textures) = &mut schema.textures {
+                let copy = textures.clone();
 
-        textures.iter_mut().for_each(|(_key, texture)| {
-            if texture.reference().is_some() {
-                texture.0 = texture.resolve(&copy).unwrap().to_string();
+                //References which can't be resolved are left as they are, and reported by ModelMesh::bake
+                textures.iter_mut().for_each(|(_key, texture)| {
+                    if texture.reference().is_some() {
+                        if let Some(resolved) = texture.resolve(&copy) {
+                            texture.0 = resolved.to_string();
+                        }
+                    }
+                })
             }
-        })
+
+            Arc::new(schema)
+        };
+
+        self.resolved.write().insert(path.clone(), resolved.clone());
+
+        Ok(resolved)
     }
 
-    schema
+    /// Every texture used by a resolved model
+    pub fn textures(&self) -> HashSet<ResourcePath> {
+        self.resolved
+            .read()
+            .values()
+            .flat_map(|model| model.textures.iter().flat_map(|textures| textures.iter()))
+            .filter(|(_key, texture)| texture.reference().is_none())
+            .map(|(_key, texture)| (&texture.0).into())
+            .collect()
+    }
 }
 
-fn get_atlas_uv(face: &schemas::models::ElementFace, block_atlas: &Atlas) -> Option<UV> {
+/// The UV of a face in the atlas, and the UV of its whole sprite
+fn get_atlas_uv(
+    face: &schemas::models::ElementFace,
+    block_atlas: &Atlas,
+) -> Result<(UV, UV), MeshBakeError> {
     let uv = face.uv.unwrap_or([0.0, 0.0, 16.0, 16.0]).map(|x| x as u16);
-    let atlas_map = block_atlas.uv_map.read();
-    atlas_map
-        .get(&(&face.texture.0).into())
+    let texture: ResourcePath = (&face.texture.0).into();
+    let tex = block_atlas
+        .uv_map
+        .read()
+        .get(&texture)
         .copied()
-        .map(|tex| {
-            let tw = (tex.1 .0 - tex.0 .0, tex.1 .1 - tex.0 .1);
-            let uvs = match face.rotation {
-                0 => ((uv[0], uv[1]), (uv[2], uv[3])),
-                90 => ((tw.1 - uv[1], uv[0]), (tw.1 - uv[3], uv[2])),
-                180 => ((tw.0 - uv[0], tw.1 - uv[1]), (tw.0 - uv[2], tw.1 - uv[3])),
-                270 => ((uv[1], tw.0 - uv[0]), (uv[3], tw.0 - uv[2])),
-                _ => unreachable!(),
-            };
-            (
-                (tex.0 .0 + uvs.0 .0, tex.0 .1 + uvs.0 .1),
-                (tex.0 .0 + uvs.1 .0, tex.0 .1 + uvs.1 .1),
-            )
-        })
+        .ok_or(MeshBakeError::TextureNotInAtlas(texture))?;
+
+    let tw = (tex.1 .0 - tex.0 .0, tex.1 .1 - tex.0 .1);
+    let uvs = match face.rotation {
+        0 => ((uv[0], uv[1]), (uv[2], uv[3])),
+        90 => ((tw.1 - uv[1], uv[0]), (tw.1 - uv[3], uv[2])),
+        180 => ((tw.0 - uv[0], tw.1 - uv[1]), (tw.0 - uv[2], tw.1 - uv[3])),
+        270 => ((uv[1], tw.0 - uv[0]), (uv[3], tw.0 - uv[2])),
+        rotation => return Err(MeshBakeError::InvalidFaceRotation(rotation)),
+    };
+
+    Ok((
+        (
+            (tex.0 .0 + uvs.0 .0, tex.0 .1 + uvs.0 .1),
+            (tex.0 .0 + uvs.1 .0, tex.0 .1 + uvs.1 .1),
+        ),
+        tex,
+    ))
+}
+
+/// The faces of an element, their direction, and their corners in the order the vertices are emitted. Corners are
+/// indexed by 0bxyz, see [ModelMesh::bake]. Textures are laid onto each face the same way as in vanilla's `CubeFace`,
+/// see [face_uv].
+const ELEMENT_FACES: [(schemas::models::BlockFace, Direction, [usize; 4]); 6] = [
+    (
+        schemas::models::BlockFace::South,
+        Direction::South,
+        [0b101, 0b111, 0b011, 0b001],
+    ),
+    (
+        schemas::models::BlockFace::West,
+        Direction::West,
+        [0b001, 0b011, 0b010, 0b000],
+    ),
+    (
+        schemas::models::BlockFace::North,
+        Direction::North,
+        [0b000, 0b010, 0b110, 0b100],
+    ),
+    (
+        schemas::models::BlockFace::East,
+        Direction::East,
+        [0b100, 0b110, 0b111, 0b101],
+    ),
+    (
+        schemas::models::BlockFace::Up,
+        Direction::Up,
+        [0b111, 0b110, 0b010, 0b011],
+    ),
+    (
+        schemas::models::BlockFace::Down,
+        Direction::Down,
+        [0b100, 0b101, 0b001, 0b000],
+    ),
+];
+
+/// Whether a blockstate variant can be rotated by `rotation` degrees, which has to be a right angle like vanilla
+fn is_block_rotation(rotation: u32) -> bool {
+    matches!(rotation, 0 | 90 | 180 | 270)
+}
+
+/// Applies the `x` and `y` rotation of a blockstate variant to a point in the block. Rotations which aren't
+/// right angles are rejected by [ModelMesh::bake] before getting here, and leave the point as it is.
+fn rotate_block(v: Vec3, x: u32, y: u32) -> Vec3 {
+    let v = match x {
+        90 => vec3(v.x, 1.0 - v.z, v.y),
+        180 => vec3(v.x, 1.0 - v.y, 1.0 - v.z),
+        270 => vec3(v.x, v.z, 1.0 - v.y),
+        _ => v,
+    };
+
+    match y {
+        90 => vec3(1.0 - v.z, v.y, v.x),
+        180 => vec3(1.0 - v.x, v.y, 1.0 - v.z),
+        270 => vec3(v.z, v.y, 1.0 - v.x),
+        _ => v,
+    }
+}
+
+/// Applies the `x` and `y` rotation of a blockstate variant to a direction
+fn rotate_direction(dir: Direction, x: u32, y: u32) -> Direction {
+    Direction::from_normal(
+        rotate_block(Vec3::splat(0.5) + dir.to_vec().as_vec3() * 0.5, x, y) - 0.5,
+    )
+}
+
+/// Whether an axis-aligned box spans the whole side of the block in `dir`
+fn covers_side(min: Vec3, max: Vec3, dir: Direction) -> bool {
+    let axis = dir.to_vec().abs().as_vec3();
+    let across = Vec3::ONE - axis;
+
+    let on_side = if dir.to_vec().cmpgt(IVec3::ZERO).any() {
+        max.dot(axis) >= 1.0
+    } else {
+        min.dot(axis) <= 0.0
+    };
+
+    on_side && (min * across).cmple(Vec3::ZERO).all() && (max * across).cmpge(across).all()
+}
+
+/// Where a point on a face of the block lands in the texture of a face with the default UV, from 0 to 1
+fn face_uv(dir: Direction, p: Vec3) -> Vec2 {
+    match dir {
+        Direction::South => vec2(p.x, 1.0 - p.y),
+        Direction::West => vec2(p.z, 1.0 - p.y),
+        Direction::North => vec2(1.0 - p.x, 1.0 - p.y),
+        Direction::East => vec2(1.0 - p.z, 1.0 - p.y),
+        Direction::Up => vec2(p.x, p.z),
+        Direction::Down => vec2(p.x, 1.0 - p.z),
+    }
+}
+
+/// The inverse of [face_uv]
+fn face_point(dir: Direction, uv: Vec2) -> Vec3 {
+    match dir {
+        Direction::South => vec3(uv.x, 1.0 - uv.y, 1.0),
+        Direction::West => vec3(0.0, 1.0 - uv.y, uv.x),
+        Direction::North => vec3(1.0 - uv.x, 1.0 - uv.y, 0.0),
+        Direction::East => vec3(1.0, 1.0 - uv.y, 1.0 - uv.x),
+        Direction::Up => vec3(uv.x, 1.0, uv.y),
+        Direction::Down => vec3(uv.x, 0.0, 1.0 - uv.y),
+    }
+}
+
+/// Moves the UV of a vertex on a face pointing in `dir` along with the blockstate rotation, so that the texture
+/// keeps its orientation in the world, like `uvlock` does in vanilla
+fn lock_uv(tex_coords: [u16; 2], sprite: UV, dir: Direction, x: u32, y: u32) -> [u16; 2] {
+    let origin = vec2(sprite.0 .0 as f32, sprite.0 .1 as f32);
+    let size = vec2(
+        (sprite.1 .0 - sprite.0 .0) as f32,
+        (sprite.1 .1 - sprite.0 .1) as f32,
+    );
+
+    let rotated_dir = rotate_direction(dir, x, y);
+
+    let uv = (vec2(tex_coords[0] as f32, tex_coords[1] as f32) - origin) / size;
+    let uv = face_uv(rotated_dir, rotate_block(face_point(dir, uv), x, y));
+    let uv = (origin + uv * size).round();
+
+    [uv.x as u16, uv.y as u16]
+}
+
+/// The normal of a quad from its transformed vertices, or `None` if it has no area
+fn face_normal(vertices: &[BlockMeshVertex; 4]) -> Option<Vec3> {
+    //The diagonals still span the face if one of its edges has collapsed
+    (vertices[2].position - vertices[0].position)
+        .cross(vertices[3].position - vertices[1].position)
+        .try_normalize()
+}
+
+/// Where the texture of a face is in the atlas, see [get_atlas_uv]
+struct FaceTexture {
+    /// The part of the sprite the face shows
+    uv: UV,
+    sprite: UV,
+    animation_uv_offset: u32,
+    /// Whether [FaceTexture::uv] has no see-through pixels
+    opaque: bool,
+}
+
+/// The faces of one element along with their `cullface`, and what the element adds to [ModelMesh::cull] and
+/// [ModelMesh::full_cube]
+struct BakedElement {
+    faces: Vec<(Option<Direction>, BlockModelFace)>,
+    cull: u8,
+    full_cube: bool,
+}
+
+/// Transforms an element by its own rotation and the rotation of the blockstate variant, and looks up the texture of
+/// each of its faces with `texture`
+fn bake_element(
+    element: &schemas::models::Element,
+    model_properties: &ModelProperties,
+    ambient_occlusion: bool,
+    texture: impl Fn(&schemas::models::ElementFace) -> Result<FaceTexture, MeshBakeError>,
+) -> Result<BakedElement, MeshBakeError> {
+    let rot = &element.rotation;
+    let matrix = match rot.axis {
+        schemas::models::Axis::X => Mat3::from_rotation_x(rot.angle.to_radians()),
+        schemas::models::Axis::Y => Mat3::from_rotation_y(rot.angle.to_radians()),
+        schemas::models::Axis::Z => Mat3::from_rotation_z(rot.angle.to_radians()),
+    };
+    let vec_origin = Vec3::from_array(rot.origin) / 16.0;
+
+    //Stretches the element back across the whole block, like the diagonal planes of cross models
+    let scale = if rot.rescale && rot.angle != 0.0 {
+        let factor = 1.0 / rot.angle.to_radians().cos();
+
+        match rot.axis {
+            schemas::models::Axis::X => vec3(1.0, factor, factor),
+            schemas::models::Axis::Y => vec3(factor, 1.0, factor),
+            schemas::models::Axis::Z => vec3(factor, factor, 1.0),
+        }
+    } else {
+        Vec3::ONE
+    };
+
+    let vertex_transform = |v: Vec3| {
+        rotate_block(
+            (matrix * (v - vec_origin)) * scale + vec_origin,
+            model_properties.x,
+            model_properties.y,
+        )
+    };
+
+    let from = Vec3::from_array(element.from) / 16.0;
+    let to = Vec3::from_array(element.to) / 16.0;
+
+    //Indexed by 0bxyz, a set bit meaning the corner is at `to` on that axis
+    let corners: [Vec3; 8] = std::array::from_fn(|index| {
+        vertex_transform(vec3(
+            if index & 0b100 != 0 { to.x } else { from.x },
+            if index & 0b010 != 0 { to.y } else { from.y },
+            if index & 0b001 != 0 { to.z } else { from.z },
+        ))
+    });
+
+    let uv_lock = model_properties.uv_lock && (model_properties.x != 0 || model_properties.y != 0);
+
+    let (min, max) = corners.iter().fold(
+        (Vec3::INFINITY, Vec3::NEG_INFINITY),
+        |(min, max), corner| (min.min(*corner), max.max(*corner)),
+    );
+
+    let mut baked = BakedElement {
+        faces: Vec::new(),
+        cull: 0,
+        full_cube: rot.angle == 0.0 && min.cmple(Vec3::ZERO).all() && max.cmpge(Vec3::ONE).all(),
+    };
+
+    for (block_face, dir, face_corners) in &ELEMENT_FACES {
+        let Some(tex) = element.faces.get(block_face) else {
+            continue;
+        };
+        let FaceTexture {
+            uv,
+            sprite,
+            animation_uv_offset,
+            opaque,
+        } = texture(tex)?;
+
+        let rotated_dir = rotate_direction(*dir, model_properties.x, model_properties.y);
+
+        //Only faces which are opaque all the way across hide the face of the block next to them
+        if rot.angle == 0.0 && covers_side(min, max, rotated_dir) && opaque {
+            baked.cull |= 1 << rotated_dir as u8;
+        }
+
+        let cull_face = tex.cull_face.as_ref().map(|cull_face| {
+            rotate_direction(cull_face.into(), model_properties.x, model_properties.y)
+        });
+
+        let tex_coords = [
+            [uv.1 .0, uv.1 .1],
+            [uv.1 .0, uv.0 .1],
+            [uv.0 .0, uv.0 .1],
+            [uv.0 .0, uv.1 .1],
+        ];
+
+        let mut face = BlockModelFace {
+            vertices: std::array::from_fn(|index| BlockMeshVertex {
+                position: corners[face_corners[index]],
+                tex_coords: if uv_lock {
+                    lock_uv(
+                        tex_coords[index],
+                        sprite,
+                        *dir,
+                        model_properties.x,
+                        model_properties.y,
+                    )
+                } else {
+                    tex_coords[index]
+                },
+            }),
+            normal: dir.to_vec().as_vec3(),
+            tint_index: tex.tint_index,
+            animation_uv_offset,
+            shade: element.shade,
+            ambient_occlusion,
+        };
+
+        //Rotated elements don't face along an axis anymore
+        if let Some(normal) = face_normal(&face.vertices) {
+            face.normal = normal;
+        }
+
+        baked.faces.push((cull_face, face));
+    }
+
+    Ok(baked)
 }
 
 pub struct RenderSettings {
     pub opaque: bool,
 }
 
-/// TODO: Use actual error handling library
 #[derive(Debug)]
 pub enum MeshBakeError {
     UnresolvedTextureReference(String),
     UnresolvedResourcePath(ResourcePath),
     JsonError(serde_json::Error),
+    /// A model which is, directly or not, its own parent
+    ParentCycle(ResourcePath),
+    MissingTexture(ResourcePath),
+    /// A face whose texture isn't in the block atlas, usually because its texture variable isn't defined
+    TextureNotInAtlas(ResourcePath),
+    /// A blockstate variant rotated by something other than a right angle
+    InvalidRotation(u32),
+    /// A face whose UVs are rotated by something other than a right angle
+    InvalidFaceRotation(u32),
+    Atlas(AtlasError),
+}
+
+impl Display for MeshBakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnresolvedTextureReference(reference) => {
+                write!(f, "unresolved texture reference, {reference}")
+            }
+            Self::UnresolvedResourcePath(path) => write!(f, "couldn't find {path}"),
+            Self::JsonError(error) => write!(f, "invalid json: {error}"),
+            Self::ParentCycle(path) => write!(f, "model {path} is its own parent"),
+            Self::MissingTexture(path) => write!(f, "couldn't find the texture {path}"),
+            Self::TextureNotInAtlas(path) => write!(f, "texture {path} isn't in the block atlas"),
+            Self::InvalidRotation(rotation) => {
+                write!(
+                    f,
+                    "invalid rotation {rotation}, only multiples of 90 are allowed"
+                )
+            }
+            Self::InvalidFaceRotation(rotation) => write!(
+                f,
+                "invalid face rotation {rotation}, only multiples of 90 are allowed"
+            ),
+            Self::Atlas(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for MeshBakeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::JsonError(error) => Some(error),
+            Self::Atlas(error) => Some(error),
+            _ => None,
+        }
+    }
 }
 
-/// A block model which has been baked into a mesh and is ready for rendering
+/// Resolves the model of a blockstate variant, and reads the textures it uses which aren't `allocated` yet
+pub fn load_model(
+    model_properties: &ModelProperties,
+    resource_provider: &dyn ResourceProvider,
+    model_cache: &ModelCache,
+    allocated: impl Fn(&ResourcePath) -> bool,
+) -> Result<(Arc<schemas::Model>, Vec<(ResourcePath, Vec<u8>)>), MeshBakeError> {
+    if let Some(rotation) = [model_properties.x, model_properties.y]
+        .into_iter()
+        .find(|rotation| !is_block_rotation(*rotation))
+    {
+        return Err(MeshBakeError::InvalidRotation(rotation));
+    }
+
+    //Recursively resolve the model using it's parents if it has any
+    let model =
+        model_cache.resolve(&ModelCache::model_path(model_properties), resource_provider)?;
+
+    let Some(textures) = &model.textures else {
+        return Ok((model, Vec::new()));
+    };
+
+    //Make sure the textures in the model are fully resolved with no references
+    if let Some(reference) = textures
+        .iter()
+        .find(|(_key, value)| value.reference().is_some())
+    {
+        return Err(MeshBakeError::UnresolvedTextureReference(format!(
+            "key: {} value: {:?}",
+            reference.0, reference.1
+        )));
+    }
+
+    let unallocated_textures = textures
+        .iter()
+        .map(|(_, texture)| ResourcePath::from(&texture.0))
+        .filter(|texture_id| !allocated(texture_id))
+        .map(|path| {
+            resource_provider
+                .get_bytes(&path.texture_file())
+                .map(|bytes| (path.clone(), bytes))
+                .ok_or(MeshBakeError::MissingTexture(path))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok((model, unallocated_textures))
+}
+
+/// A block model which has been baked into a mesh and is ready for rendering. Faces are sorted by their
+/// `cullface`, and are skipped when the neighbour in that direction covers them.
 #[derive(Debug)]
 pub struct ModelMesh {
     pub north: Vec<BlockModelFace>,
@@ -170,8 +621,14 @@ pub struct ModelMesh {
     pub east: Vec<BlockModelFace>,
     pub up: Vec<BlockModelFace>,
     pub down: Vec<BlockModelFace>,
+    /// Faces without a `cullface`, which are always drawn
     pub any: Vec<BlockModelFace>,
+    /// A bit for each [Direction] in which the model covers the whole side of the block with opaque faces,
+    /// hiding the faces of the neighbour there
     pub cull: u8,
+    /// Whether an unrotated element fills the whole block, whatever its textures look like. Stands in for
+    /// `AbstractBlockState::isFullCube`, which darkens the corners of the faces around the block
+    pub full_cube: bool,
     pub layer: RenderLayer,
 }
 
@@ -180,426 +637,111 @@ impl ModelMesh {
         model_properties: impl IntoIterator<Item = &'a ModelProperties>,
         resource_provider: &dyn ResourceProvider,
         block_atlas: &Atlas,
+        model_cache: &ModelCache,
+        layer: RenderLayer,
     ) -> Result<Self, MeshBakeError> {
+        //The faces which completely cover a side of the block, see [ModelMesh::cull]
+        let mut cull = 0u8;
+        let mut full_cube = false;
+
         let mesh = model_properties
             .into_iter()
             .map(|model_properties: &ModelProperties| {
-                let model_resource_path = ResourcePath::from(&model_properties.model)
-                    .prepend("models/")
-                    .append(".json");
-
-                //Recursively resolve the model using it's parents if it has any
-                let model: schemas::Model = resolve_model(
-                    //Parse the JSON into the model schema
-                    serde_json::from_str(
-                        //Get the model JSON
-                        &resource_provider
-                            .get_string(&model_resource_path)
-                            .ok_or_else(|| {
-                                MeshBakeError::UnresolvedResourcePath(model_resource_path)
-                            })?,
-                    )
-                    .map_err(MeshBakeError::JsonError)?,
-                    resource_provider,
-                );
-                if let Some(textures) = model.textures {
-                    //Make sure the textures in the model are fully resolved with no references
-                    if let Some(reference) = textures
-                        .iter()
-                        .find(|(_key, value)| value.reference().is_some())
-                    {
-                        return Err(MeshBakeError::UnresolvedTextureReference(format!(
-                            "key: {} value: {:?}",
-                            reference.0, reference.1
-                        )));
-                    }
+                let (model, unallocated_textures) =
+                    load_model(model_properties, resource_provider, model_cache, |path| {
+                        block_atlas.uv_map.read().contains_key(path)
+                    })?;
 
-                    let uv_map = block_atlas.uv_map.read();
-
-                    let unallocated_textures: Vec<ResourcePath> = textures
-                        .iter()
-                        .filter_map(|(_, texture)| {
-                            let texture_id: ResourcePath = (&texture.0).into();
-                            if !uv_map.contains_key(&texture_id) {
-                                //Block UV atlas doesn't contain a texture, so we add it
-                                Some(texture_id)
-                            } else {
-                                None
-                            }
-                        })
-                        .collect();
-
-                    drop(uv_map);
-
-                    let unallocated_textures: Vec<(&ResourcePath, Vec<u8>)> = unallocated_textures
-                        .iter()
-                        .map(|path| {
-                            (
-                                path,
-                                resource_provider
-                                    .get_bytes(&path.prepend("textures/").append(".png"))
-                                    .unwrap(),
-                            )
-                        })
-                        .collect();
-
-                    if !unallocated_textures.is_empty() {
-                        block_atlas.allocate(
-                            unallocated_textures
-                                .iter()
-                                .map(|(path, data)| (*path, data)),
+                //Block UV atlas doesn't contain these textures, so we add them
+                if !unallocated_textures.is_empty() {
+                    block_atlas
+                        .allocate(
+                            unallocated_textures.iter().map(|(path, data)| (path, data)),
                             resource_provider,
-                        );
-                    }
-                };
-
-                Ok(model
-                    .elements
-                    .iter()
-                    .flatten()
-                    .flat_map(|element| {
-                        //Face textures
-                        let north = element
-                            .faces
-                            .get(&schemas::models::BlockFace::North)
-                            .as_ref()
-                            .and_then(|tex| {
-                                get_atlas_uv(tex, block_atlas).map(|uv| {
-                                    (
-                                        //The default UV for this texture
-                                        uv,
-                                        //If this texture has an animation, get the offset, otherwise default to 0
-                                        *block_atlas
-                                            .animated_texture_offsets
-                                            .read()
-                                            .get(&(&tex.texture.0).into())
-                                            .unwrap_or(&0),
-                                        tex.tint_index,
-                                    )
-                                })
-                            });
-
-                        let east = element
-                            .faces
-                            .get(&schemas::models::BlockFace::East)
-                            .as_ref()
-                            .and_then(|tex| {
-                                get_atlas_uv(tex, block_atlas).map(|uv| {
-                                    (
-                                        //The default UV for this texture
-                                        uv,
-                                        //If this texture has an animation, get the offset, otherwise default to 0
-                                        *block_atlas
-                                            .animated_texture_offsets
-                                            .read()
-                                            .get(&(&tex.texture.0).into())
-                                            .unwrap_or(&0),
-                                        tex.tint_index,
-                                    )
-                                })
-                            });
-
-                        let south = element
-                            .faces
-                            .get(&schemas::models::BlockFace::South)
-                            .as_ref()
-                            .and_then(|tex| {
-                                get_atlas_uv(tex, block_atlas).map(|uv| {
-                                    (
-                                        //The default UV for this texture
-                                        uv,
-                                        //If this texture has an animation, get the offset, otherwise default to 0
-                                        *block_atlas
-                                            .animated_texture_offsets
-                                            .read()
-                                            .get(&(&tex.texture.0).into())
-                                            .unwrap_or(&0),
-                                        tex.tint_index,
-                                    )
-                                })
-                            });
-
-                        let west = element
-                            .faces
-                            .get(&schemas::models::BlockFace::West)
-                            .as_ref()
-                            .and_then(|tex| {
-                                get_atlas_uv(tex, block_atlas).map(|uv| {
-                                    (
-                                        //The default UV for this texture
-                                        uv,
-                                        //If this texture has an animation, get the offset, otherwise default to 0
-                                        *block_atlas
-                                            .animated_texture_offsets
-                                            .read()
-                                            .get(&(&tex.texture.0).into())
-                                            .unwrap_or(&0),
-                                        tex.tint_index,
-                                    )
-                                })
-                            });
-
-                        let up = element
-                            .faces
-                            .get(&schemas::models::BlockFace::Up)
-                            .as_ref()
-                            .and_then(|tex| {
-                                get_atlas_uv(tex, block_atlas).map(|uv| {
-                                    (
-                                        //The default UV for this texture
-                                        uv,
-                                        //If this texture has an animation, get the offset, otherwise default to 0
-                                        *block_atlas
-                                            .animated_texture_offsets
-                                            .read()
-                                            .get(&(&tex.texture.0).into())
-                                            .unwrap_or(&0),
-                                        tex.tint_index,
-                                    )
-                                })
-                            });
-
-                        let down = element
-                            .faces
-                            .get(&schemas::models::BlockFace::Down)
-                            .as_ref()
-                            .and_then(|tex| {
-                                get_atlas_uv(tex, block_atlas).map(|uv| {
-                                    (
-                                        //The default UV for this texture
-                                        uv,
-                                        //If this texture has an animation, get the offset, otherwise default to 0
-                                        *block_atlas
-                                            .animated_texture_offsets
-                                            .read()
-                                            .get(&(&tex.texture.0).into())
-                                            .unwrap_or(&0),
-                                        tex.tint_index,
-                                    )
-                                })
-                            });
-                        let rot = &element.rotation;
-                        let matrix = match rot.axis {
-                            schemas::models::Axis::X => {
-                                Mat3::from_rotation_x(rot.angle.to_radians())
-                            }
-                            schemas::models::Axis::Y => {
-                                Mat3::from_rotation_y(rot.angle.to_radians())
-                            }
-                            schemas::models::Axis::Z => {
-                                Mat3::from_rotation_z(rot.angle.to_radians())
-                            }
-                        };
-                        let vec_origin = Vec3::from_array(rot.origin) / 16.0;
-
-                        let vertex_transform = |v: Vec3| {
-                            let v = match model_properties.x {
-                                0 => v,
-                                90 => vec3(v.x, 1.0 - v.z, v.y),
-                                180 => vec3(v.x, 1.0 - v.y, 1.0 - v.z),
-                                270 => vec3(v.x, v.z, 1.0 - v.y),
-                                _ => panic!("invalid rotation"),
-                            };
-                            let v = matrix * (v - vec_origin) + vec_origin;
-
-                            match model_properties.y {
-                                0 => v,
-                                90 => vec3(1.0 - v.z, v.y, v.x),
-                                180 => vec3(1.0 - v.x, v.y, 1.0 - v.z),
-                                270 => vec3(v.z, v.y, 1.0 - v.x),
-                                _ => panic!("invalid rotation"),
-                            }
-                        };
-
-                        let p000 = vertex_transform(vec3(
-                            element.from[0] / 16.0,
-                            element.from[1] / 16.0,
-                            element.from[2] / 16.0,
-                        ));
-                        let p001 = vertex_transform(vec3(
-                            element.from[0] / 16.0,
-                            element.from[1] / 16.0,
-                            element.to[2] / 16.0,
-                        ));
-                        let p010 = vertex_transform(vec3(
-                            element.from[0] / 16.0,
-                            element.to[1] / 16.0,
-                            element.from[2] / 16.0,
-                        ));
-                        let p011 = vertex_transform(vec3(
-                            element.from[0] / 16.0,
-                            element.to[1] / 16.0,
-                            element.to[2] / 16.0,
-                        ));
-                        let p100 = vertex_transform(vec3(
-                            element.to[0] / 16.0,
-                            element.from[1] / 16.0,
-                            element.from[2] / 16.0,
-                        ));
-                        let p101 = vertex_transform(vec3(
-                            element.to[0] / 16.0,
-                            element.from[1] / 16.0,
-                            element.to[2] / 16.0,
-                        ));
-                        let p110 = vertex_transform(vec3(
-                            element.to[0] / 16.0,
-                            element.to[1] / 16.0,
-                            element.from[2] / 16.0,
-                        ));
-                        let p111 = vertex_transform(vec3(
-                            element.to[0] / 16.0,
-                            element.to[1] / 16.0,
-                            element.to[2] / 16.0,
-                        ));
-
-                        let mut faces = vec![];
-                        faces.extend(south.map(|south_face| BlockModelFace {
-                            vertices: [
-                                BlockMeshVertex {
-                                    position: p101,
-                                    tex_coords: [south_face.0 .1 .0, south_face.0 .1 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p111,
-                                    tex_coords: [south_face.0 .1 .0, south_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p011,
-                                    tex_coords: [south_face.0 .0 .0, south_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p001,
-                                    tex_coords: [south_face.0 .0 .0, south_face.0 .1 .1],
-                                },
-                            ],
-                            normal: vec3(0.0, 0.0, 1.0),
-                            tint_index: south_face.2,
-                            animation_uv_offset: south_face.1,
-                        }));
-                        faces.extend(west.map(|west_face| BlockModelFace {
-                            vertices: [
-                                BlockMeshVertex {
-                                    position: p001,
-                                    tex_coords: [west_face.0 .1 .0, west_face.0 .1 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p011,
-                                    tex_coords: [west_face.0 .1 .0, west_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p010,
-                                    tex_coords: [west_face.0 .0 .0, west_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p000,
-                                    tex_coords: [west_face.0 .0 .0, west_face.0 .1 .1],
-                                },
-                            ],
-                            normal: vec3(-1.0, 0.0, 0.0),
-                            tint_index: west_face.2,
-                            animation_uv_offset: west_face.1,
-                        }));
-                        faces.extend(north.map(|north_face| BlockModelFace {
-                            vertices: [
-                                BlockMeshVertex {
-                                    position: p000,
-                                    tex_coords: [north_face.0 .1 .0, north_face.0 .1 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p010,
-                                    tex_coords: [north_face.0 .1 .0, north_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p110,
-                                    tex_coords: [north_face.0 .0 .0, north_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p100,
-                                    tex_coords: [north_face.0 .0 .0, north_face.0 .1 .1],
-                                },
-                            ],
-                            normal: vec3(0.0, 0.0, -1.0),
-                            tint_index: north_face.2,
-                            animation_uv_offset: north_face.1,
-                        }));
-                        faces.extend(east.map(|east_face| BlockModelFace {
-                            vertices: [
-                                BlockMeshVertex {
-                                    position: p100,
-                                    tex_coords: [east_face.0 .1 .0, east_face.0 .1 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p110,
-                                    tex_coords: [east_face.0 .1 .0, east_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p111,
-                                    tex_coords: [east_face.0 .0 .0, east_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p101,
-                                    tex_coords: [east_face.0 .0 .0, east_face.0 .1 .1],
-                                },
-                            ],
-                            normal: vec3(1.0, 0.0, 0.0),
-                            tint_index: east_face.2,
-                            animation_uv_offset: east_face.1,
-                        }));
-                        faces.extend(up.map(|up_face| BlockModelFace {
-                            vertices: [
-                                BlockMeshVertex {
-                                    position: p010,
-                                    tex_coords: [up_face.0 .1 .0, up_face.0 .1 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p011,
-                                    tex_coords: [up_face.0 .1 .0, up_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p111,
-                                    tex_coords: [up_face.0 .0 .0, up_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p110,
-                                    tex_coords: [up_face.0 .0 .0, up_face.0 .1 .1],
-                                },
-                            ],
-                            normal: vec3(0.0, 1.0, 0.0),
-                            tint_index: up_face.2,
-                            animation_uv_offset: up_face.1,
-                        }));
-
-                        faces.extend(down.map(|down_face| BlockModelFace {
-                            vertices: [
-                                BlockMeshVertex {
-                                    position: p000,
-                                    tex_coords: [down_face.0 .1 .0, down_face.0 .1 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p100,
-                                    tex_coords: [down_face.0 .1 .0, down_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p101,
-                                    tex_coords: [down_face.0 .0 .0, down_face.0 .0 .1],
-                                },
-                                BlockMeshVertex {
-                                    position: p001,
-                                    tex_coords: [down_face.0 .0 .0, down_face.0 .1 .1],
-                                },
-                            ],
-                            normal: vec3(0.0, -1.0, 0.0),
-                            tint_index: down_face.2,
-                            animation_uv_offset: down_face.1,
-                        }));
-                        faces
-                    })
-                    .collect::<Vec<BlockModelFace>>())
+                        )
+                        .map_err(MeshBakeError::Atlas)?;
+                }
+
+                let ambient_occlusion = model.ambient_occlusion.unwrap_or(true);
+
+                let mut faces = Vec::new();
+
+                for element in model.elements.iter().flatten() {
+                    let baked =
+                        bake_element(element, model_properties, ambient_occlusion, |tex| {
+                            let (uv, sprite) = get_atlas_uv(tex, block_atlas)?;
+
+                            Ok(FaceTexture {
+                                uv,
+                                sprite,
+                                //If this texture has an animation, get the offset, otherwise default to 0
+                                animation_uv_offset: *block_atlas
+                                    .animated_texture_offsets
+                                    .read()
+                                    .get(&(&tex.texture.0).into())
+                                    .unwrap_or(&0),
+                                opaque: block_atlas.is_opaque(uv),
+                            })
+                        })?;
+
+                    cull |= baked.cull;
+                    full_cube |= baked.full_cube;
+                    faces.extend(baked.faces);
+                }
+
+                Ok(faces)
             })
             .flatten_ok()
-            .collect::<Result<Vec<BlockModelFace>, MeshBakeError>>()?;
+            .collect::<Result<Vec<(Option<Direction>, BlockModelFace)>, MeshBakeError>>()?;
+        Ok(Self::from_faces(mesh, cull, full_cube, layer))
+    }
+
+    /// A full cube with the missing texture, which stands in for blocks whose model couldn't be baked. Has no
+    /// faces if there was no space left in the atlas for the texture.
+    pub fn missing(block_atlas: &Atlas, resource_provider: &dyn ResourceProvider) -> Self {
+        let Some(uv) = block_atlas.missing_texture(resource_provider) else {
+            return Self::from_faces([], 0, false, RenderLayer::Solid);
+        };
+
+        let tex_coords = [
+            [uv.1 .0, uv.1 .1],
+            [uv.1 .0, uv.0 .1],
+            [uv.0 .0, uv.0 .1],
+            [uv.0 .0, uv.1 .1],
+        ];
+
+        let faces = ELEMENT_FACES.iter().map(|(_, dir, face_corners)| {
+            (
+                Some(*dir),
+                BlockModelFace {
+                    vertices: std::array::from_fn(|index| BlockMeshVertex {
+                        position: vec3(
+                            ((face_corners[index] >> 2) & 1) as f32,
+                            ((face_corners[index] >> 1) & 1) as f32,
+                            (face_corners[index] & 1) as f32,
+                        ),
+                        tex_coords: tex_coords[index],
+                    }),
+                    normal: dir.to_vec().as_vec3(),
+                    tint_index: -1,
+                    animation_uv_offset: 0,
+                    shade: true,
+                    ambient_occlusion: true,
+                },
+            )
+        });
+
+        Self::from_faces(faces, 0b111111, true, RenderLayer::Solid)
+    }
+
+    fn from_faces(
+        faces: impl IntoIterator<Item = (Option<Direction>, BlockModelFace)>,
+        cull: u8,
+        full_cube: bool,
+        layer: RenderLayer,
+    ) -> Self {
         let mut result = Self {
-            layer: RenderLayer::Solid,
+            layer,
             north: vec![],
             south: vec![],
             west: vec![],
@@ -607,54 +749,348 @@ impl ModelMesh {
             up: vec![],
             down: vec![],
             any: vec![],
-            cull: 0,
+            cull,
+            full_cube,
         };
-        mesh.iter().for_each(|face| {
-            let full_face = (face.vertices[0].position.fract() == vec3(0.0, 0.0, 0.0)
-                && face.vertices[1].position.fract() == vec3(0.0, 0.0, 0.0)
-                && face.vertices[2].position.fract() == vec3(0.0, 0.0, 0.0)
-                && face.vertices[3].position.fract() == vec3(0.0, 0.0, 0.0))
-                as u8;
-            if face.vertices[0].position.x == 0.0
-                && face.vertices[1].position.x == 0.0
-                && face.vertices[2].position.x == 0.0
-            {
-                result.west.push(*face);
-                result.cull |= full_face << Direction::West as u8;
-            } else if face.vertices[0].position.x == 1.0
-                && face.vertices[1].position.x == 1.0
-                && face.vertices[2].position.x == 1.0
-            {
-                result.east.push(*face);
-                result.cull |= full_face << Direction::East as u8;
-            } else if face.vertices[0].position.y == 0.0
-                && face.vertices[1].position.y == 0.0
-                && face.vertices[2].position.y == 0.0
-            {
-                result.down.push(*face);
-                result.cull |= full_face << Direction::Down as u8;
-            } else if face.vertices[0].position.y == 1.0
-                && face.vertices[1].position.y == 1.0
-                && face.vertices[2].position.y == 1.0
-            {
-                result.up.push(*face);
-                result.cull |= full_face << Direction::Up as u8;
-            } else if face.vertices[0].position.z == 0.0
-                && face.vertices[1].position.z == 0.0
-                && face.vertices[2].position.z == 0.0
-            {
-                result.north.push(*face);
-                result.cull |= full_face << Direction::North as u8;
-            } else if face.vertices[0].position.z == 1.0
-                && face.vertices[1].position.z == 1.0
-                && face.vertices[2].position.z == 1.0
-            {
-                result.south.push(*face);
-                result.cull |= full_face << Direction::South as u8;
-            } else {
-                result.any.push(*face);
+        faces
+            .into_iter()
+            .for_each(|(cull_face, face)| match cull_face {
+                Some(Direction::West) => result.west.push(face),
+                Some(Direction::East) => result.east.push(face),
+                Some(Direction::Down) => result.down.push(face),
+                Some(Direction::Up) => result.up.push(face),
+                Some(Direction::North) => result.north.push(face),
+                Some(Direction::South) => result.south.push(face),
+                None => result.any.push(face),
+            });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 16x16 sprite, away from the corner of the atlas
+    const SPRITE: UV = ((32, 16), (48, 32));
+
+    fn element(json: &str) -> schemas::models::Element {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn variant(json: &str) -> ModelProperties {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn bake(element: &schemas::models::Element, variant: &ModelProperties) -> BakedElement {
+        bake_textured(element, variant, true)
+    }
+
+    /// Bakes an element with every face showing the part of [SPRITE] given by its `uv`, which is `opaque` or has
+    /// see-through pixels
+    fn bake_textured(
+        element: &schemas::models::Element,
+        variant: &ModelProperties,
+        opaque: bool,
+    ) -> BakedElement {
+        bake_element(element, variant, true, |tex| {
+            let [u0, v0, u1, v1] = tex.uv.unwrap_or([0.0, 0.0, 16.0, 16.0]).map(|x| x as u16);
+
+            Ok(FaceTexture {
+                uv: (
+                    (SPRITE.0 .0 + u0, SPRITE.0 .1 + v0),
+                    (SPRITE.0 .0 + u1, SPRITE.0 .1 + v1),
+                ),
+                sprite: SPRITE,
+                animation_uv_offset: 0,
+                opaque,
+            })
+        })
+        .unwrap()
+    }
+
+    /// Where vanilla puts a point on a face of the block in the face's texture, in pixels. Same as the default UV of
+    /// an element face, see `ModelElement::getRotatedMatrix` and `BlockElement.uvsByFace`
+    fn vanilla_uv(dir: Direction, p: Vec3) -> [u16; 2] {
+        let uv = match dir {
+            Direction::Down => vec2(p.x, 1.0 - p.z),
+            Direction::Up => vec2(p.x, p.z),
+            Direction::North => vec2(1.0 - p.x, 1.0 - p.y),
+            Direction::South => vec2(p.x, 1.0 - p.y),
+            Direction::West => vec2(p.z, 1.0 - p.y),
+            Direction::East => vec2(1.0 - p.z, 1.0 - p.y),
+        } * 16.0;
+
+        [uv.x.round() as u16, uv.y.round() as u16]
+    }
+
+    /// The UV of a vertex within [SPRITE]
+    fn sprite_uv(vertex: &BlockMeshVertex) -> [u16; 2] {
+        [
+            vertex.tex_coords[0] - SPRITE.0 .0,
+            vertex.tex_coords[1] - SPRITE.0 .1,
+        ]
+    }
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            actual.abs_diff_eq(expected, 1.0e-5),
+            "{actual} isn't close to {expected}"
+        );
+    }
+
+    //From vanilla's block/stairs.json
+    const STAIRS: [&str; 2] = [
+        r#"{
+            "from": [0, 0, 0], "to": [16, 8, 16],
+            "faces": {
+                "down": {"uv": [0, 0, 16, 16], "texture": "minecraft:block/stone", "cullface": "down"},
+                "up": {"uv": [0, 0, 16, 16], "texture": "minecraft:block/stone"},
+                "north": {"uv": [0, 8, 16, 16], "texture": "minecraft:block/stone", "cullface": "north"},
+                "south": {"uv": [0, 8, 16, 16], "texture": "minecraft:block/stone", "cullface": "south"},
+                "west": {"uv": [0, 8, 16, 16], "texture": "minecraft:block/stone", "cullface": "west"},
+                "east": {"uv": [0, 8, 16, 16], "texture": "minecraft:block/stone", "cullface": "east"}
             }
-        });
-        Ok(result)
+        }"#,
+        r#"{
+            "from": [8, 8, 0], "to": [16, 16, 16],
+            "faces": {
+                "up": {"uv": [8, 0, 16, 16], "texture": "minecraft:block/stone", "cullface": "up"},
+                "north": {"uv": [0, 0, 8, 8], "texture": "minecraft:block/stone", "cullface": "north"},
+                "south": {"uv": [8, 0, 16, 8], "texture": "minecraft:block/stone", "cullface": "south"},
+                "west": {"uv": [0, 0, 16, 8], "texture": "minecraft:block/stone"},
+                "east": {"uv": [0, 0, 16, 8], "texture": "minecraft:block/stone", "cullface": "east"}
+            }
+        }"#,
+    ];
+
+    #[test]
+    fn unrotated_faces_match_vanilla() {
+        for json in STAIRS {
+            for (_, face) in bake(&element(json), &variant(r#"{"model": "block/stairs"}"#)).faces {
+                let dir = Direction::from_normal(face.normal);
+
+                for vertex in &face.vertices {
+                    assert_eq!(
+                        sprite_uv(vertex),
+                        vanilla_uv(dir, vertex.position),
+                        "{dir:?} face at {}",
+                        vertex.position
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uv_lock_keeps_textures_aligned_with_the_world() {
+        //The rotations of vanilla's stairs, and a sideways one like the blockstates of some mods
+        for rotation in [
+            r#""y": 90"#,
+            r#""y": 180"#,
+            r#""y": 270"#,
+            r#""x": 180, "y": 90"#,
+            r#""x": 90"#,
+            r#""x": 90, "y": 270"#,
+        ] {
+            let variant = variant(&format!(
+                r#"{{"model": "block/stairs", {rotation}, "uvlock": true}}"#
+            ));
+
+            for json in STAIRS {
+                for (_, face) in bake(&element(json), &variant).faces {
+                    let dir = Direction::from_normal(face.normal);
+
+                    for vertex in &face.vertices {
+                        assert_eq!(
+                            sprite_uv(vertex),
+                            vanilla_uv(dir, vertex.position),
+                            "{dir:?} face at {} rotated by {rotation}",
+                            vertex.position
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn textures_turn_with_the_block_without_uv_lock() {
+        let variant = variant(r#"{"model": "block/stairs", "y": 90}"#);
+        let baked = bake(&element(STAIRS[1]), &variant);
+
+        //The top of the step now runs along the x axis, but its texture doesn't
+        let (_, top) = baked
+            .faces
+            .iter()
+            .find(|(_, face)| face.normal == Vec3::Y)
+            .unwrap();
+
+        assert!(top
+            .vertices
+            .iter()
+            .any(|vertex| sprite_uv(vertex) != vanilla_uv(Direction::Up, vertex.position)));
+    }
+
+    //From vanilla's block/cross.json
+
+    fn cross(angle: f32, rescale: bool) -> schemas::models::Element {
+        element(&format!(
+            r#"{{
+                "from": [0.8, 0, 8], "to": [15.2, 16, 8],
+                "rotation": {{"origin": [8, 8, 8], "axis": "y", "angle": {angle}, "rescale": {rescale}}},
+                "faces": {{"north": {{"uv": [0, 0, 16, 16], "texture": "minecraft:block/poppy"}}}}
+            }}"#
+        ))
+    }
+
+    #[test]
+    fn rescales_rotated_elements_across_the_block() {
+        let variant = variant(r#"{"model": "block/cross"}"#);
+        let bottom_corners = |element| {
+            let (_, face) = bake(&element, &variant).faces.remove(0);
+
+            let mut corners = face
+                .vertices
+                .map(|vertex| vertex.position)
+                .into_iter()
+                .filter(|position| position.y == 0.0)
+                .collect::<Vec<_>>();
+            corners.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+            (corners, face.normal)
+        };
+
+        //Reaches the corners of the block, like the flowers in vanilla
+        let (corners, normal) = bottom_corners(cross(45.0, true));
+        assert_eq!(corners.len(), 2);
+        assert_close(corners[0], vec3(0.8, 0.0, 15.2) / 16.0);
+        assert_close(corners[1], vec3(15.2, 0.0, 0.8) / 16.0);
+        assert_close(normal, vec3(-1.0, 0.0, -1.0).normalize());
+
+        //Only stretched along the axes the rotation is around
+        let (corners, _) = bottom_corners(cross(22.5, true));
+        let offset = 7.2 * 22.5f32.to_radians().tan();
+        assert_close(corners[0], vec3(0.8, 0.0, 8.0 + offset) / 16.0);
+        assert_close(corners[1], vec3(15.2, 0.0, 8.0 - offset) / 16.0);
+
+        let (corners, _) = bottom_corners(cross(45.0, false));
+        let offset = 7.2 * 45.0f32.to_radians().cos();
+        assert_close(corners[0], vec3(8.0 - offset, 0.0, 8.0 + offset) / 16.0);
+        assert_close(corners[1], vec3(8.0 + offset, 0.0, 8.0 - offset) / 16.0);
+    }
+
+    /// An element with a face on every side, culled by the neighbour on that side
+    fn culled_box(from: [f32; 3], to: [f32; 3], rotation: &str) -> schemas::models::Element {
+        let faces = ["down", "up", "north", "south", "west", "east"]
+            .map(|side| {
+                format!(r#""{side}": {{"texture": "minecraft:block/stone", "cullface": "{side}"}}"#)
+            })
+            .join(",");
+
+        element(&format!(
+            r#"{{"from": {from:?}, "to": {to:?}, {rotation} "faces": {{{faces}}}}}"#
+        ))
+    }
+
+    fn cull_faces(baked: &BakedElement) -> Vec<Option<Direction>> {
+        baked
+            .faces
+            .iter()
+            .map(|(cull_face, _)| *cull_face)
+            .collect()
+    }
+
+    const NO_ROTATION: &str = r#"{"model": "block/cube"}"#;
+
+    #[test]
+    fn full_opaque_faces_cull_their_neighbour() {
+        let cube = bake(&culled_box([0.0; 3], [16.0; 3], ""), &variant(NO_ROTATION));
+        assert_eq!(cube.cull, 0b111111);
+        assert!(cube.full_cube);
+
+        //Like the bottom of a slab, which only covers the side it sits on
+        let slab = bake(
+            &culled_box([0.0; 3], [16.0, 8.0, 16.0], ""),
+            &variant(NO_ROTATION),
+        );
+        assert_eq!(slab.cull, 1 << Direction::Down as u8);
+        assert!(!slab.full_cube);
+
+        //Turns with the blockstate, along with the cullfaces
+        let wall = bake(
+            &culled_box([0.0; 3], [16.0, 16.0, 8.0], ""),
+            &variant(r#"{"model": "block/wall", "y": 90}"#),
+        );
+        assert_eq!(wall.cull, 1 << Direction::East as u8);
+        assert_eq!(
+            cull_faces(&wall),
+            [
+                Direction::West,
+                Direction::North,
+                Direction::East,
+                Direction::South,
+                Direction::Up,
+                Direction::Down
+            ]
+            .map(Some)
+        );
+    }
+
+    #[test]
+    fn inset_faces_keep_their_cullface() {
+        //Like a cactus, whose sides are inset but still hidden by the blocks next to them
+        let inset = bake(
+            &culled_box([1.0, 0.0, 1.0], [15.0, 16.0, 15.0], ""),
+            &variant(NO_ROTATION),
+        );
+
+        assert_eq!(
+            cull_faces(&inset),
+            [
+                Direction::South,
+                Direction::West,
+                Direction::North,
+                Direction::East,
+                Direction::Up,
+                Direction::Down
+            ]
+            .map(Some)
+        );
+        //None of them covers a whole side
+        assert_eq!(inset.cull, 0);
+    }
+
+    #[test]
+    fn see_through_faces_dont_cull() {
+        //Like leaves or glass
+        let leaves = bake_textured(
+            &culled_box([0.0; 3], [16.0; 3], ""),
+            &variant(NO_ROTATION),
+            false,
+        );
+
+        assert_eq!(leaves.cull, 0);
+        assert_eq!(cull_faces(&leaves).len(), 6);
+        assert!(leaves.full_cube);
+    }
+
+    #[test]
+    fn rotated_elements_dont_cull() {
+        for angle in [22.5, 45.0, -22.5] {
+            let rotated = bake(
+                &culled_box(
+                    [0.0; 3],
+                    [16.0; 3],
+                    &format!(
+                        r#""rotation": {{"origin": [8, 8, 8], "axis": "y", "angle": {angle}}},"#
+                    ),
+                ),
+                &variant(NO_ROTATION),
+            );
+
+            assert_eq!(rotated.cull, 0, "rotated by {angle}");
+            assert!(!rotated.full_cube);
+        }
     }
 }
@@ -0,0 +1,124 @@
+//! Blockstates written the way vanilla writes them, e.g. `minecraft:oak_stairs[facing=east,half=top]`
+
+use minecraft_assets::schemas::blockstates::multipart::StateValue;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockStateParseError {
+    /// The properties were opened with `[` but never closed
+    UnclosedProperties(String),
+    /// A property which isn't written as `name=value`
+    InvalidProperty(String),
+    /// The block isn't known to the [BlockManager](crate::mc::BlockManager)
+    UnknownBlock(String),
+    /// The block exists, but none of its variants match the properties
+    UnknownState(String),
+}
+
+/// A blockstate string split into the name of the block and its properties
+#[derive(Debug)]
+pub struct ParsedBlockState<'a> {
+    pub block: &'a str,
+    pub properties: Vec<(&'a str, StateValue)>,
+}
+
+impl<'a> ParsedBlockState<'a> {
+    pub fn parse(state: &'a str) -> Result<Self, BlockStateParseError> {
+        let state = state.trim();
+
+        let Some((block, properties)) = state.split_once('[') else {
+            return Ok(Self {
+                block: state,
+                properties: vec![],
+            });
+        };
+
+        let properties = properties
+            .strip_suffix(']')
+            .ok_or_else(|| BlockStateParseError::UnclosedProperties(state.into()))?;
+
+        Ok(Self {
+            block,
+            properties: parse_properties(properties)?,
+        })
+    }
+}
+
+/// Parses properties written as `facing=east,half=top`
+pub fn parse_properties(properties: &str) -> Result<Vec<(&str, StateValue)>, BlockStateParseError> {
+    properties
+        .split(',')
+        .filter(|property| !property.is_empty())
+        .map(|property| {
+            let (name, value) = property
+                .split_once('=')
+                .filter(|(name, value)| !name.is_empty() && !value.is_empty())
+                .ok_or_else(|| BlockStateParseError::InvalidProperty(property.into()))?;
+
+            Ok((name.trim(), parse_state_value(value.trim())))
+        })
+        .collect()
+}
+
+pub fn parse_state_value(value: &str) -> StateValue {
+    match value {
+        "true" => StateValue::Bool(true),
+        "false" => StateValue::Bool(false),
+        other => StateValue::String(other.into()),
+    }
+}
+
+pub fn format_state_value(value: &StateValue) -> &str {
+    match value {
+        StateValue::Bool(true) => "true",
+        StateValue::Bool(false) => "false",
+        StateValue::String(string) => string,
+    }
+}
+
+/// A [BlockVariantFormatter](crate::mc::BlockVariantFormatter) which writes blockstates like vanilla does
+pub fn vanilla_format(block: &str, variant: Option<&str>) -> String {
+    match variant {
+        Some(variant) if !variant.is_empty() => format!("{block}[{variant}]"),
+        _ => block.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(state: &str) -> String {
+        let parsed = ParsedBlockState::parse(state).unwrap();
+
+        let variant = parsed
+            .properties
+            .iter()
+            .map(|(name, value)| format!("{name}={}", format_state_value(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        vanilla_format(parsed.block, Some(&variant))
+    }
+
+    #[test]
+    fn parses_and_formats() {
+        assert_eq!(round_trip("minecraft:stone"), "minecraft:stone");
+        assert_eq!(round_trip("minecraft:stone[]"), "minecraft:stone");
+        assert_eq!(
+            round_trip("minecraft:oak_stairs[facing=east,half=top,waterlogged=false]"),
+            "minecraft:oak_stairs[facing=east,half=top,waterlogged=false]"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_properties() {
+        assert_eq!(
+            ParsedBlockState::parse("minecraft:oak_stairs[facing=east").unwrap_err(),
+            BlockStateParseError::UnclosedProperties("minecraft:oak_stairs[facing=east".into())
+        );
+        assert_eq!(
+            ParsedBlockState::parse("minecraft:oak_stairs[facing]").unwrap_err(),
+            BlockStateParseError::InvalidProperty("facing".into())
+        );
+    }
+}
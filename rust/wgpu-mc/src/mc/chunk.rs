@@ -6,18 +6,21 @@
 //!
 //! Minecraft splits chunks into 16-block tall pieces called chunk sections, for
 //! rendering purposes.
-use arrayvec::ArrayVec;
-use glam::{ivec3, vec3, IVec2, IVec3, Vec3Swizzles};
+use glam::{ivec3, vec2, vec3, IVec2, IVec3, Vec2, Vec3, Vec3Swizzles};
 use range_alloc::RangeAllocator;
-use std::collections::HashMap;
+use serde_derive::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::ops::{Not, Range};
+use std::ops::Range;
 use std::sync::Arc;
 
 use crate::mc::block::{BlockModelFace, ChunkBlockState, ModelMesh};
 use crate::mc::direction::Direction;
-use crate::mc::BlockManager;
+use crate::mc::tint::{Biome, BiomeColorCache, TintSource};
+use crate::mc::visibility::{find_reachable_sections, SectionVisibility, VisibilityBuilder};
+use crate::mc::{position_seed, BlockManager};
 use crate::render::pipeline::Vertex;
+use crate::texture::UV;
 use crate::WmRenderer;
 
 pub const CHUNK_WIDTH: usize = 16;
@@ -47,6 +50,52 @@ impl LightLevel {
     }
 }
 
+/// How block faces are lit when a section is baked
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LightingMode {
+    /// Minecraft's smooth lighting, light and ambient occlusion are blended from the blocks around each vertex
+    #[default]
+    Smooth,
+    /// Every vertex of a face gets the light of the block in front of it and no ambient occlusion
+    Flat,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Fluid {
+    Water,
+    Lava,
+}
+
+impl Fluid {
+    pub fn render_layer(&self) -> RenderLayer {
+        match self {
+            Fluid::Water => RenderLayer::Transparent,
+            Fluid::Lava => RenderLayer::Solid,
+        }
+    }
+}
+
+/// The fluid occupying a block, mirroring Minecraft's `FluidState`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FluidState {
+    pub fluid: Fluid,
+    /// 8 for source blocks, 1 to 7 for flowing fluid, decreasing the further it is from the source
+    pub level: u8,
+    /// Whether the fluid is falling down, e.g. a waterfall
+    pub falling: bool,
+}
+
+impl FluidState {
+    /// The height of the fluid's surface within the block, from 0 to 1
+    pub fn height(&self) -> f32 {
+        if self.falling {
+            8.0 / 9.0
+        } else {
+            self.level as f32 / 9.0
+        }
+    }
+}
+
 /// Return a [ChunkBlockState] within the provided world coordinates.
 pub trait BlockStateProvider {
     fn get_state(&self, pos: IVec3) -> ChunkBlockState;
@@ -55,103 +104,481 @@ pub trait BlockStateProvider {
 
     fn is_section_empty(&self, rel_pos: IVec3) -> bool;
 
-    fn get_block_color(&self, pos: IVec3, tint_index: i32) -> u32;
+    /// The tint of a block which isn't tinted by biome in core, or of any block if [BlockStateProvider::get_biome]
+    /// returns `None`, packed as `r | g << 8 | b << 16`. Takes world coordinates, unlike the other methods.
+    fn get_block_color(&self, _pos: IVec3, _tint_index: i32) -> u32 {
+        0xffffffff
+    }
+
+    /// The biome at the provided coordinates, which tints grass, foliage and water in core. The columns within
+    /// the biome blend radius around the section are also asked for.
+    fn get_biome(&self, _pos: IVec3) -> Option<Biome> {
+        None
+    }
+
+    /// The fluid at the provided coordinates, if any. Waterlogged blocks should also return their water here.
+    fn get_fluid_state(&self, _pos: IVec3) -> Option<FluidState> {
+        None
+    }
 }
 
-#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+/// Which pass of the terrain a baked quad belongs to. Shaderpack pipelines which draw terrain
+/// choose which of these layers they render, see [PipelineConfig](crate::render::shaderpack::PipelineConfig)
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RenderLayer {
     Solid = 0,
     Cutout = 1,
     Transparent = 2,
 }
 
+impl RenderLayer {
+    pub const COUNT: usize = 3;
+
+    pub const ALL: [RenderLayer; Self::COUNT] = [
+        RenderLayer::Solid,
+        RenderLayer::Cutout,
+        RenderLayer::Transparent,
+    ];
+}
+
 #[derive(Clone)]
 pub struct SectionRanges {
     pub vertex_range: Range<u32>,
     pub index_range: Range<u32>,
 }
 
+/// How far the camera has to move, in blocks, before translucent geometry is sorted again
+pub const TRANSLUCENT_SORT_DISTANCE: f32 = 1.0;
+
+/// Fragmentation above which [SectionStorage::needs_compaction] asks for the chunk buffer to be compacted
+pub const CHUNK_BUFFER_COMPACTION_THRESHOLD: f32 = 0.5;
+
+/// A range of the chunk buffer, in 4 byte words, which has to be copied when the buffer is relocated,
+/// along with where it starts in the new buffer
+pub type ChunkBufferMove = (Range<u32>, u32);
+
+/// How full and fragmented the chunk buffer is, see [SectionStorage::stats]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChunkBufferStats {
+    /// Size of the buffer in bytes
+    pub capacity: u64,
+    /// Bytes taken up by the geometry of loaded sections
+    pub used: u64,
+    /// The largest allocation in bytes which fits without growing or compacting the buffer
+    pub largest_free_range: u64,
+}
+
+impl ChunkBufferStats {
+    /// How much of the buffer is in use, from 0 to 1
+    pub fn occupancy(&self) -> f32 {
+        if self.capacity == 0 {
+            return 0.0;
+        }
+
+        self.used as f32 / self.capacity as f32
+    }
+
+    /// How much of the free space is split off from the largest free range, from 0 when all free space is
+    /// contiguous to almost 1 when it's scattered in tiny gaps
+    pub fn fragmentation(&self) -> f32 {
+        let free = self.capacity - self.used;
+
+        if free == 0 {
+            return 0.0;
+        }
+
+        1.0 - self.largest_free_range as f32 / free as f32
+    }
+}
+
 ///The struct representing a Chunk section, with various render layers, split into sections
 pub struct SectionStorage {
     storage: HashMap<IVec3, Section>,
     allocator: RangeAllocator<u32>,
     width: i32,
+    /// Sections with translucent geometry which have been replaced since the last sort
+    unsorted: HashSet<IVec3>,
+    last_sort_position: Option<Vec3>,
+    /// Size of the chunk buffer in 4 byte words
+    capacity: u32,
+    /// The chunk buffer never grows past this many words
+    max_capacity: u32,
+    /// Words freed since fragmentation was last checked
+    freed_since_check: u32,
+    /// Copies which bring the chunk buffer in line with the allocator after it grew or was compacted
+    relocation: Option<Vec<ChunkBufferMove>>,
+    /// Bumped whenever a section is added, removed or moved, see [SectionStorage::generation]
+    generation: u64,
+    camera_section: Option<IVec3>,
+    /// Sections which can be seen from the camera's section, or `None` if they couldn't be found
+    reachable: Option<HashSet<IVec3>>,
+    /// The camera section and generation [SectionStorage::reachable] was found for
+    reachable_from: Option<(IVec3, u64)>,
+    /// Sections which have to be rebaked because blocks in or next to them changed
+    dirty: HashSet<IVec3>,
 }
 impl SectionStorage {
-    pub fn new(range: u32) -> Self {
+    /// Creates storage for a chunk buffer of `capacity` 4 byte words, which may grow up to `max_capacity` words
+    pub fn new(capacity: u32, max_capacity: u32) -> Self {
         SectionStorage {
             storage: HashMap::new(),
             width: 0,
-            allocator: RangeAllocator::new(0..range),
+            allocator: RangeAllocator::new(0..capacity),
+            unsorted: HashSet::new(),
+            last_sort_position: None,
+            capacity,
+            max_capacity: max_capacity.max(capacity),
+            freed_since_check: 0,
+            relocation: None,
+            generation: 0,
+            camera_section: None,
+            reachable: None,
+            reachable_from: None,
+            dirty: HashSet::new(),
         }
     }
     pub fn clear(&mut self) {
         self.allocator.reset();
         self.storage.clear();
+        self.unsorted.clear();
+        self.dirty.clear();
+        self.freed_since_check = 0;
+        self.relocation = None;
+        self.generation += 1;
     }
     pub fn set_width(&mut self, w: i32) {
         self.width = w;
     }
     pub fn trim(&mut self, pos: IVec2) {
-        let mut to_remove = vec![];
-        for (k, section) in &self.storage {
-            let dist = (k.xz() - pos).abs();
-            let radius = self.width + 2; //temp fix until proper sync
-            if dist.x > radius || dist.y > radius {
-                to_remove.push(*k);
-                for layer in &section.layers {
-                    if let Some(l) = layer.as_ref() {
-                        self.allocator.free_range(l.vertex_range.clone());
-                        self.allocator.free_range(l.index_range.clone());
-                    }
-                }
-            }
+        let radius = self.width + 2; //temp fix until proper sync
+
+        let to_remove: Vec<IVec3> = self
+            .storage
+            .keys()
+            .filter(|k| {
+                let dist = (k.xz() - pos).abs();
+                dist.x > radius || dist.y > radius
+            })
+            .copied()
+            .collect();
+
+        if !to_remove.is_empty() {
+            self.generation += 1;
         }
+
         to_remove.iter().for_each(|pos| {
-            self.storage.remove(pos);
+            if let Some(section) = self.storage.remove(pos) {
+                self.free_section(&section);
+            }
+            self.unsorted.remove(pos);
         });
     }
-    pub fn replace(&mut self, pos: IVec3, baked_layers: &[BakedLayer]) -> Section {
-        if let Some(previous_section) = self.storage.get(&pos) {
-            for layer in &previous_section.layers {
-                if let Some(l) = layer.as_ref() {
-                    self.allocator.free_range(l.vertex_range.clone());
-                    self.allocator.free_range(l.index_range.clone());
-                }
-            }
+    fn free_section(&mut self, section: &Section) {
+        for range in section.ranges() {
+            self.freed_since_check += range.end - range.start;
+            self.allocator.free_range(range);
         }
-        let section = Section {
-            layers: baked_layers
-                .iter()
-                .map(|layer| {
-                    if !layer.indices.is_empty() {
-                        Some(SectionRanges {
-                            vertex_range: self
-                                .allocator
-                                .allocate_range(layer.vertices.len() as u32 / 4)
-                                .unwrap(),
-                            index_range: self
-                                .allocator
-                                .allocate_range(layer.indices.len() as u32 / 4)
-                                .unwrap(),
-                        })
-                    } else {
-                        None
-                    }
+    }
+    /// Replaces the geometry of a section, returning where each of its layers has to be written in the chunk
+    /// buffer. If the buffer had to grow or be compacted to make room, [SectionStorage::take_relocation] must be
+    /// applied before writing.
+    pub fn replace(&mut self, pos: IVec3, baked_section: &BakedSection) -> Section {
+        let baked_layers = &baked_section.layers;
+
+        if let Some(previous_section) = self.storage.remove(&pos) {
+            self.free_section(&previous_section);
+        }
+
+        let lengths: Vec<Option<(u32, u32)>> = baked_layers
+            .iter()
+            .map(|layer| {
+                (!layer.indices.is_empty()).then(|| {
+                    (
+                        layer.vertices.len() as u32 / 4,
+                        layer.indices.len() as u32 / 4,
+                    )
                 })
-                .collect(),
+            })
+            .collect();
+
+        let layers = self.allocate_layers(&lengths).unwrap_or_else(|| {
+            log::warn!("Chunk buffer is full, section {pos} won't be rendered");
+            vec![None; lengths.len()]
+        });
+
+        let section = Section {
+            layers,
+            translucent_centroids: baked_layers
+                .get(RenderLayer::Transparent as usize)
+                .filter(|layer| !layer.quad_centroids.is_empty())
+                .map(|layer| Arc::new(layer.quad_centroids.clone())),
+            visibility: baked_section.visibility,
         };
+        if section.translucent_centroids.is_some() {
+            self.unsorted.insert(pos);
+        } else {
+            self.unsorted.remove(&pos);
+        }
         self.storage.insert(pos, section.clone());
+        self.generation += 1;
         section
     }
+    /// Allocates the vertex and index ranges of each layer, growing the buffer as needed. Once it can't grow any
+    /// further, the buffer is compacted once before giving up.
+    fn allocate_layers(
+        &mut self,
+        lengths: &[Option<(u32, u32)>],
+    ) -> Option<Vec<Option<SectionRanges>>> {
+        let needed = lengths
+            .iter()
+            .flatten()
+            .map(|(vertices, indices)| vertices + indices)
+            .sum();
+        let mut compacted = false;
+
+        loop {
+            let mut layers = Vec::with_capacity(lengths.len());
+
+            for length in lengths {
+                let Some((vertices, indices)) = length else {
+                    layers.push(None);
+                    continue;
+                };
+
+                match (
+                    self.allocator.allocate_range(*vertices),
+                    self.allocator.allocate_range(*indices),
+                ) {
+                    (Ok(vertex_range), Ok(index_range)) => layers.push(Some(SectionRanges {
+                        vertex_range,
+                        index_range,
+                    })),
+                    (vertex_range, index_range) => {
+                        [vertex_range, index_range]
+                            .into_iter()
+                            .flatten()
+                            .for_each(|range| self.allocator.free_range(range));
+                        break;
+                    }
+                }
+            }
+
+            if layers.len() == lengths.len() {
+                return Some(layers);
+            }
+
+            layers.iter().flatten().for_each(|ranges| {
+                self.allocator.free_range(ranges.vertex_range.clone());
+                self.allocator.free_range(ranges.index_range.clone());
+            });
+
+            if self.capacity < self.max_capacity {
+                self.grow(needed);
+            } else if !compacted {
+                self.compact();
+                compacted = true;
+            } else {
+                return None;
+            }
+        }
+    }
+    /// Grows the buffer to fit at least `needed` more words, usually by doubling it
+    fn grow(&mut self, needed: u32) {
+        let capacity = self
+            .capacity
+            .saturating_mul(2)
+            .max(self.capacity.saturating_add(needed))
+            .min(self.max_capacity);
+
+        self.allocator.grow_to(capacity);
+        self.capacity = capacity;
+
+        //Growing doesn't move anything, so the geometry is copied to the same place in the bigger buffer. If the
+        //buffer was already compacted, those moves still apply
+        if self.relocation.is_none() {
+            self.relocation = Some(
+                self.storage
+                    .values()
+                    .flat_map(Section::ranges)
+                    .map(|range| {
+                        let start = range.start;
+                        (range, start)
+                    })
+                    .collect(),
+            );
+        }
+    }
+    /// Packs the geometry of every section together at the start of the buffer, leaving all free space in one
+    /// range. The copies this takes are returned by [SectionStorage::take_relocation].
+    pub fn compact(&mut self) {
+        self.allocator = RangeAllocator::new(0..self.capacity);
+        self.freed_since_check = 0;
+
+        let mut moves = Vec::new();
+
+        for section in self.storage.values_mut() {
+            for ranges in section.layers.iter_mut().flatten() {
+                for range in [&mut ranges.vertex_range, &mut ranges.index_range] {
+                    //Everything fit before, so it still fits when packed together
+                    let new_range = self
+                        .allocator
+                        .allocate_range(range.end - range.start)
+                        .unwrap();
+
+                    moves.push((range.clone(), new_range.start));
+                    *range = new_range;
+                }
+            }
+        }
+
+        self.relocation = Some(moves);
+        self.generation += 1;
+    }
+    /// Whether enough has been freed since the last check to leave the buffer badly fragmented. Cheap enough to
+    /// call every frame.
+    pub fn needs_compaction(&mut self) -> bool {
+        if self.freed_since_check < self.capacity / 4 {
+            return false;
+        }
+
+        self.freed_since_check = 0;
+        self.stats().fragmentation() > CHUNK_BUFFER_COMPACTION_THRESHOLD
+    }
+    /// The copies needed to move the chunk buffer's contents into a new buffer of [SectionStorage::capacity], if it
+    /// grew or was compacted since the last call.
+    pub fn take_relocation(&mut self) -> Option<Vec<ChunkBufferMove>> {
+        self.relocation.take()
+    }
+    /// Size of the chunk buffer in 4 byte words
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+    pub fn stats(&self) -> ChunkBufferStats {
+        let mut ranges: Vec<Range<u32>> = self.storage.values().flat_map(Section::ranges).collect();
+        ranges.sort_unstable_by_key(|range| range.start);
+
+        let mut used = 0;
+        let mut largest_free_range = 0;
+        let mut end = 0;
+
+        for range in ranges {
+            largest_free_range = largest_free_range.max(range.start - end);
+            used += range.end - range.start;
+            end = range.end;
+        }
+
+        largest_free_range = largest_free_range.max(self.capacity - end);
+
+        ChunkBufferStats {
+            capacity: self.capacity as u64 * 4,
+            used: used as u64 * 4,
+            largest_free_range: largest_free_range as u64 * 4,
+        }
+    }
+    pub fn get(&self, pos: &IVec3) -> Option<&Section> {
+        self.storage.get(pos)
+    }
     pub fn iter(&self) -> std::collections::hash_map::Iter<IVec3, Section> {
         self.storage.iter()
     }
+    /// Sets the section the camera is in, which cave culling starts from
+    pub fn set_camera_section(&mut self, pos: IVec3) {
+        self.camera_section = Some(pos);
+    }
+    /// Marks every section whose geometry depends on the block at `pos`, in world space, as needing a rebake.
+    /// Besides the block's own section, that's any neighbouring section within one block of it, since faces
+    /// are culled, shaded and lit using the blocks around them.
+    pub fn mark_block_dirty(&mut self, pos: IVec3) {
+        let min = (pos - 1) >> 4;
+        let max = (pos + 1) >> 4;
+
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    self.dirty.insert(ivec3(x, y, z));
+                }
+            }
+        }
+    }
+    /// Takes the sections marked by [SectionStorage::mark_block_dirty] since the last call, leaving out ones
+    /// which aren't loaded
+    pub fn take_dirty_sections(&mut self) -> Vec<IVec3> {
+        let storage = &self.storage;
+
+        self.dirty
+            .drain()
+            .filter(|pos| storage.contains_key(pos))
+            .collect()
+    }
+    /// The section the camera is in, if it has been set
+    pub fn camera_section(&self) -> Option<IVec3> {
+        self.camera_section
+    }
+    /// Finds the sections which can be seen from the camera's section again, if the camera moved into another
+    /// section or any section changed since they were last found
+    pub fn update_reachable_sections(&mut self) {
+        let Some(camera_section) = self.camera_section else {
+            return;
+        };
+
+        if self.reachable_from == Some((camera_section, self.generation)) {
+            return;
+        }
+
+        self.reachable = find_reachable_sections(self, camera_section, self.width);
+        self.reachable_from = Some((camera_section, self.generation));
+    }
+    /// Whether a section can be seen from the camera's section. Everything is reachable until
+    /// [SectionStorage::update_reachable_sections] finds otherwise.
+    pub fn is_reachable(&self, pos: &IVec3) -> bool {
+        self.reachable
+            .as_ref()
+            .is_none_or(|reachable| reachable.contains(pos))
+    }
+    /// The camera section and generation the reachable sections were last found for
+    pub fn reachable_from(&self) -> Option<(IVec3, u64)> {
+        self.reachable_from
+    }
+    /// Changes whenever the set of sections or where their geometry lives in the chunk buffer changes, so that
+    /// anything derived from the storage knows when to rebuild
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the translucent sections which need to be sorted again for the camera's position, which is
+    /// in world space. If the camera hasn't moved far enough, only sections which were replaced since the
+    /// last sort are returned.
+    pub fn translucent_sort_jobs(&mut self, camera_pos: Vec3) -> Vec<(IVec3, Arc<Vec<Vec3>>)> {
+        let moved = self.last_sort_position.is_none_or(|last| {
+            last.distance_squared(camera_pos)
+                > TRANSLUCENT_SORT_DISTANCE * TRANSLUCENT_SORT_DISTANCE
+        });
+
+        if moved {
+            self.last_sort_position = Some(camera_pos);
+        }
+
+        let jobs = self
+            .storage
+            .iter()
+            .filter(|(pos, _)| moved || self.unsorted.contains(*pos))
+            .filter_map(|(pos, section)| Some((*pos, section.translucent_centroids.clone()?)))
+            .collect();
+
+        self.unsorted.clear();
+
+        jobs
+    }
 }
 
 #[derive(Clone)]
 pub struct Section {
     pub layers: Vec<Option<SectionRanges>>,
+    /// The centers of each quad in the translucent layer, in bake order, used to sort them back to front
+    pub translucent_centroids: Option<Arc<Vec<Vec3>>>,
+    /// Which faces of the section can see each other, used for cave culling
+    pub visibility: SectionVisibility,
 }
 
 impl Default for Section {
@@ -162,12 +589,46 @@ impl Default for Section {
 
 impl Section {
     pub fn new() -> Self {
-        Self { layers: Vec::new() }
+        Self {
+            layers: Vec::new(),
+            translucent_centroids: None,
+            visibility: SectionVisibility::ALL,
+        }
+    }
+
+    /// Every range of the chunk buffer taken up by this section's geometry
+    pub fn ranges(&self) -> impl Iterator<Item = Range<u32>> + '_ {
+        self.layers
+            .iter()
+            .flatten()
+            .flat_map(|ranges| [ranges.vertex_range.clone(), ranges.index_range.clone()])
     }
 }
 
+/// Creates the index data for a layer with its quads ordered back to front, as seen from `camera_pos`,
+/// which is relative to the section's origin
+pub fn sort_translucent_indices(centroids: &[Vec3], camera_pos: Vec3) -> Vec<u8> {
+    let mut quads = centroids
+        .iter()
+        .enumerate()
+        .map(|(index, centroid)| (index as u32, centroid.distance_squared(camera_pos)))
+        .collect::<Vec<_>>();
+
+    quads.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+
+    quads
+        .iter()
+        .flat_map(|(quad, _)| QUAD_INDICES.map(|index| index + quad * 4))
+        .flat_map(u32::to_ne_bytes)
+        .collect()
+}
+
 #[inline]
-fn get_block(block_manager: &BlockManager, state: ChunkBlockState) -> Option<Arc<ModelMesh>> {
+fn get_block(
+    block_manager: &BlockManager,
+    state: ChunkBlockState,
+    seed: i64,
+) -> Option<Arc<ModelMesh>> {
     let key = match state {
         ChunkBlockState::Air => return None,
         ChunkBlockState::State(key) => key,
@@ -177,34 +638,61 @@ fn get_block(block_manager: &BlockManager, state: ChunkBlockState) -> Option<Arc
         .blocks
         .get_index(key.block as usize)?
         .1
-        .get_model(key.augment, 0)
+        .get_model(key.augment, seed)
+}
+
+/// The mesh of a neighbouring block, for culling and lighting. The models of a variant all share
+/// the same shape, so any of them will do
+#[inline]
+fn get_block_shape(block_manager: &BlockManager, state: ChunkBlockState) -> Option<Arc<ModelMesh>> {
+    get_block(block_manager, state, 0)
 }
 
 pub fn bake_section<Provider: BlockStateProvider>(pos: IVec3, wm: &WmRenderer, bsp: &Provider) {
     let bm = wm.mc.block_manager.read();
+    let lighting_mode = *wm.mc.lighting_mode.read();
+    let biome_blend_radius = *wm.mc.biome_blend_radius.read();
 
-    let baked_section = bake_layers(pos, &bm, bsp);
+    let baked_section = bake_layers(pos, &bm, bsp, lighting_mode, biome_blend_radius);
 
     wm.chunk_update_queue.0.send((pos, baked_section)).unwrap();
 }
 
+const QUAD_INDICES: [u32; 6] = [1, 3, 0, 2, 3, 1];
+
 #[derive(Clone, Default)]
 pub struct BakedLayer {
     pub vertices: Vec<u8>,
     pub indices: Vec<u8>,
+    /// The center of every quad in the layer, relative to the section's origin
+    pub quad_centroids: Vec<Vec3>,
+}
+
+/// The geometry of a section along with what's needed to cull it
+#[derive(Clone, Default)]
+pub struct BakedSection {
+    pub layers: Vec<BakedLayer>,
+    pub visibility: SectionVisibility,
 }
 
-fn bake_layers<Provider: BlockStateProvider>(
+pub(crate) fn bake_layers<Provider: BlockStateProvider>(
     section_pos: IVec3,
     block_manager: &BlockManager,
     state_provider: &Provider,
-) -> Vec<BakedLayer> {
-    let mut layers = vec![BakedLayer::default(); 3];
+    lighting_mode: LightingMode,
+    biome_blend_radius: u32,
+) -> BakedSection {
+    let mut layers = vec![BakedLayer::default(); RenderLayer::COUNT];
+    let mut visibility = VisibilityBuilder::new();
 
     let section_offset = 16 * section_pos;
+    let biome_colors = BiomeColorCache::default();
 
     if state_provider.is_section_empty(ivec3(0, 0, 0)) {
-        return layers;
+        return BakedSection {
+            layers,
+            visibility: SectionVisibility::ALL,
+        };
     }
 
     for block_index in 0..16 * 16 * 16 {
@@ -214,123 +702,95 @@ fn bake_layers<Provider: BlockStateProvider>(
 
         let block_state: ChunkBlockState = state_provider.get_state(pos);
 
-        if let Some(model_mesh) = get_block(block_manager, block_state) {
-            const INDICES: [u32; 6] = [1, 3, 0, 2, 3, 1];
-            let mut add_quad =
-                |face: &BlockModelFace, _light_level: LightLevel, dir: Direction, color: u32| {
-                    let baked_layer = &mut layers[RenderLayer::Solid as usize];
-                    let vec_index = baked_layer.vertices.len() / Vertex::VERTEX_LENGTH;
-
-                    let dir_vec = dir.to_vec();
-
-                    baked_layer.vertices.extend(
-                        (0..4)
-                            .map(|vert_index| {
-                                let model_vertex = face.vertices[vert_index as usize];
-
-                                let (b1, b2, b3, light_level) = if model_mesh.any.is_empty() {
-                                    let vertex_biases = ivec3(
-                                        if model_vertex.position.x as i32 == 0 {
-                                            -1
-                                        } else {
-                                            1
-                                        },
-                                        if model_vertex.position.y as i32 == 0 {
-                                            -1
-                                        } else {
-                                            1
-                                        },
-                                        if model_vertex.position.z as i32 == 0 {
-                                            -1
-                                        } else {
-                                            1
-                                        },
-                                    );
-
-                                    let axis = dir_vec - vertex_biases; //equivalent to -(vertex_biases - dir_vec)
-
-                                    let mut axes: ArrayVec<IVec3, 2> = ArrayVec::new_const();
-
-                                    if axis.x != 0 {
-                                        axes.push(ivec3(axis.x, 0, 0));
-                                    }
-
-                                    if axis.y != 0 {
-                                        axes.push(ivec3(0, axis.y, 0));
-                                    }
-
-                                    if axis.z != 0 {
-                                        axes.push(ivec3(0, 0, axis.z));
-                                    }
-
-                                    let p1 = vertex_biases + pos;
-                                    let p2 = p1 + axes[0];
-                                    let p3 = p1 + axes[1];
-
-                                    let b1 = state_provider.get_state(p1).is_air().not() as u8;
-                                    let b2 = state_provider.get_state(p2).is_air().not() as u8;
-                                    let b3 = state_provider.get_state(p3).is_air().not() as u8;
-
-                                    let l1 = state_provider.get_light_level(p1);
-                                    let l2 = state_provider.get_light_level(p2);
-                                    let l3 = state_provider.get_light_level(p3);
-                                    let l4 = state_provider.get_light_level(pos + dir_vec);
-
-                                    let average_sky = ((l1.get_sky_level()
-                                        + l2.get_sky_level()
-                                        + l3.get_sky_level()
-                                        + l4.get_sky_level())
-                                        as f32
-                                        / 4.0)
-                                        as u8;
-                                    let average_block = ((l1.get_block_level()
-                                        + l2.get_block_level()
-                                        + l3.get_block_level()
-                                        + l4.get_block_level())
-                                        as f32
-                                        / 4.0)
-                                        as u8;
-
-                                    let light_level =
-                                        LightLevel::from_sky_and_block(average_sky, average_block);
-
-                                    (b1, b2, b3, light_level)
-                                } else {
-                                    (0, 0, 0, state_provider.get_light_level(pos))
-                                };
-
-                                Vertex {
-                                    position: [
-                                        fpos.x + model_vertex.position[0],
-                                        fpos.y + model_vertex.position[1],
-                                        fpos.z + model_vertex.position[2],
-                                    ],
-                                    uv: model_vertex.tex_coords,
-                                    normal: face.normal.to_array(),
-                                    color,
-                                    uv_offset: 0,
-                                    lightmap_coords: light_level.byte,
-                                    ao: 3 - (b1 + b2 + b3),
-                                }
-                            })
-                            .flat_map(Vertex::compressed),
-                    );
-                    baked_layer.indices.extend(
-                        INDICES
-                            .iter()
-                            .flat_map(|index| (index + (vec_index as u32)).to_ne_bytes()),
-                    );
-                };
+        if is_opaque_full_cube(block_manager, block_state) {
+            visibility.mark_opaque(pos);
+        }
+
+        let tint_source = match block_state {
+            ChunkBlockState::State(key) => block_manager.tint_source(key),
+            ChunkBlockState::Air => None,
+        };
+
+        let tint = |tint_index: i32| {
+            if tint_index == -1 {
+                return 0xffffffff;
+            }
+
+            tint_color(
+                block_manager,
+                state_provider,
+                tint_source,
+                pos,
+                section_offset,
+                tint_index,
+                biome_blend_radius,
+                &biome_colors,
+            )
+        };
+
+        if let Some(model_mesh) = get_block(
+            block_manager,
+            block_state,
+            position_seed(pos + section_offset),
+        ) {
+            let full_cube = model_mesh.full_cube;
+
+            let mut add_quad = |face: &BlockModelFace, dir: Direction, color: u32| {
+                let lighting = face_lighting(
+                    pos,
+                    face,
+                    dir,
+                    full_cube,
+                    lighting_mode,
+                    block_manager,
+                    state_provider,
+                );
+
+                let baked_layer = &mut layers[model_mesh.layer as usize];
+                let vec_index = baked_layer.vertices.len() / Vertex::VERTEX_LENGTH;
 
+                baked_layer.vertices.extend(
+                    (0..4)
+                        .map(|vert_index| {
+                            let model_vertex = face.vertices[vert_index];
+
+                            Vertex {
+                                position: [
+                                    fpos.x + model_vertex.position[0],
+                                    fpos.y + model_vertex.position[1],
+                                    fpos.z + model_vertex.position[2],
+                                ],
+                                uv: model_vertex.tex_coords,
+                                normal: face.normal.to_array(),
+                                color,
+                                uv_offset: face.animation_uv_offset,
+                                lightmap_coords: lighting.light[vert_index].byte,
+                                ao: (lighting.brightness[vert_index] * 255.0).round() as u8,
+                            }
+                        })
+                        .flat_map(Vertex::compressed),
+                );
+                baked_layer.indices.extend(
+                    QUAD_INDICES
+                        .iter()
+                        .flat_map(|index| (index + (vec_index as u32)).to_ne_bytes()),
+                );
+                baked_layer.quad_centroids.push(
+                    fpos + face
+                        .vertices
+                        .iter()
+                        .map(|vertex| vertex.position)
+                        .sum::<Vec3>()
+                        / 4.0,
+                );
+            };
+
+            //Faces are culled by their `cullface`, but lit from the direction they're pointing in
             let mut add_face = |face: &BlockModelFace, dir: Direction| {
-                let color = if face.tint_index != -1 {
-                    state_provider.get_block_color(pos + section_offset, face.tint_index)
-                } else {
-                    0xffffffff
-                };
+                let color = tint(face.tint_index);
 
                 let cull = if let Some(mesh) =
-                    get_block(block_manager, state_provider.get_state(pos + dir.to_vec()))
+                    get_block_shape(block_manager, state_provider.get_state(pos + dir.to_vec()))
                 {
                     (mesh.cull >> dir.opposite() as u8) & 1 == 1
                 } else {
@@ -338,9 +798,7 @@ fn bake_layers<Provider: BlockStateProvider>(
                 };
 
                 if !cull {
-                    let light_level: LightLevel =
-                        state_provider.get_light_level(pos + dir.to_vec());
-                    add_quad(face, light_level, dir, color);
+                    add_quad(face, Direction::from_normal(face.normal), color);
                 }
             };
 
@@ -363,17 +821,1392 @@ fn bake_layers<Provider: BlockStateProvider>(
                 add_face(face, Direction::South);
             });
             model_mesh.any.iter().for_each(|face| {
-                let light_level: LightLevel = state_provider.get_light_level(pos);
+                let color = tint(face.tint_index);
+
+                add_quad(face, Direction::from_normal(face.normal), color);
+            });
+        }
+
+        if let Some(fluid_state) = state_provider.get_fluid_state(pos) {
+            if let Some(sprites) = block_manager.fluids.get(&fluid_state.fluid) {
+                bake_fluid(
+                    pos,
+                    section_offset,
+                    fluid_state,
+                    sprites,
+                    block_manager,
+                    state_provider,
+                    biome_blend_radius,
+                    &biome_colors,
+                    &mut layers[fluid_state.fluid.render_layer() as usize],
+                );
+            }
+        }
+    }
+
+    BakedSection {
+        layers,
+        visibility: visibility.build(),
+    }
+}
+
+/// The colour of a tinted face, blended from the biomes around it if the block is tinted in core and the
+/// provider knows about biomes
+#[allow(clippy::too_many_arguments)]
+fn tint_color<Provider: BlockStateProvider>(
+    block_manager: &BlockManager,
+    state_provider: &Provider,
+    tint_source: Option<TintSource>,
+    pos: IVec3,
+    section_offset: IVec3,
+    tint_index: i32,
+    biome_blend_radius: u32,
+    biome_colors: &BiomeColorCache,
+) -> u32 {
+    tint_source
+        .and_then(|source| {
+            block_manager.biome_tints.blended_color(
+                source,
+                pos,
+                biome_blend_radius,
+                state_provider,
+                biome_colors,
+            )
+        })
+        .unwrap_or_else(|| state_provider.get_block_color(pos + section_offset, tint_index))
+}
+
+/// The [ModelMesh::cull] bits of a block with six full faces
+const FULL_CUBE: u8 = 0b111111;
+
+/// Approximation of `BlockState::isOpaqueFullCube`. Full cubes outside of the solid layer, like glass,
+/// let light through
+fn is_opaque_full_cube(block_manager: &BlockManager, state: ChunkBlockState) -> bool {
+    get_block_shape(block_manager, state)
+        .is_some_and(|mesh| mesh.cull == FULL_CUBE && mesh.layer == RenderLayer::Solid)
+}
+
+/// See [ModelMesh::full_cube]
+fn is_full_cube(block_manager: &BlockManager, state: ChunkBlockState) -> bool {
+    get_block_shape(block_manager, state).is_some_and(|mesh| mesh.full_cube)
+}
+
+/// Whether a face lies flat on the side of the block it points out of, in which case it is lit by the
+/// neighbouring block rather than the block itself
+fn is_on_boundary(face: &BlockModelFace, dir: Direction) -> bool {
+    let axis = dir.to_vec().as_vec3();
+    let boundary = if axis.element_sum() > 0.0 { 1.0 } else { 0.0 };
+
+    face.vertices
+        .iter()
+        .all(|vertex| (axis.abs().dot(vertex.position) - boundary).abs() < 1.0e-4)
+}
+
+/// The light level and brightness of each vertex of a face
+struct FaceLighting {
+    light: [LightLevel; 4],
+    brightness: [f32; 4],
+}
+
+/// Lights a face smoothly or flat depending on the lighting mode and the model, and shades it by the
+/// direction it's lit from. See `BlockModelRenderer::render`
+fn face_lighting<Provider: BlockStateProvider>(
+    pos: IVec3,
+    face: &BlockModelFace,
+    dir: Direction,
+    full_cube: bool,
+    lighting_mode: LightingMode,
+    block_manager: &BlockManager,
+    state_provider: &Provider,
+) -> FaceLighting {
+    let mut lighting = if lighting_mode == LightingMode::Smooth && face.ambient_occlusion {
+        smooth_lighting(pos, face, dir, full_cube, block_manager, state_provider)
+    } else {
+        flat_lighting(pos, face, dir, full_cube, state_provider)
+    };
+
+    if face.shade {
+        lighting.brightness = lighting
+            .brightness
+            .map(|brightness| brightness * dir.shade());
+    }
+
+    lighting
+}
+
+/// See `BlockModelRenderer::renderFlat`
+fn flat_lighting<Provider: BlockStateProvider>(
+    pos: IVec3,
+    face: &BlockModelFace,
+    dir: Direction,
+    full_cube: bool,
+    state_provider: &Provider,
+) -> FaceLighting {
+    let light = if full_cube || is_on_boundary(face, dir) {
+        state_provider.get_light_level(pos + dir.to_vec())
+    } else {
+        state_provider.get_light_level(pos)
+    };
+
+    FaceLighting {
+        light: [light; 4],
+        brightness: [1.0; 4],
+    }
+}
+
+/// The blocks sampled around a face for smooth lighting, as two pairs of opposite directions.
+/// Same order as `BlockModelRenderer.NeighborData`
+fn smooth_lighting_neighbours(dir: Direction) -> [Direction; 4] {
+    use Direction::*;
+
+    match dir {
+        Down => [West, East, North, South],
+        Up => [East, West, North, South],
+        North => [Up, Down, East, West],
+        South => [West, East, Down, Up],
+        West => [Up, Down, North, South],
+        East => [Down, Up, North, South],
+    }
+}
+
+/// Averages the light of the blocks around a corner with the light in front of the face. Opaque blocks
+/// have no light of their own, so they take on the light in front of the face instead of darkening
+/// the corner. See `AmbientOcclusionCalculator::getAmbientOcclusionBrightness`
+fn blend_corner_light(lights: [LightLevel; 3], center: LightLevel) -> Vec2 {
+    lights
+        .into_iter()
+        .map(|light| if light.byte == 0 { center } else { light })
+        .chain([center])
+        .map(|light| vec2(light.get_sky_level() as f32, light.get_block_level() as f32))
+        .sum::<Vec2>()
+        / 4.0
+}
+
+/// Smooth lighting and ambient occlusion, following `BlockModelRenderer.AmbientOcclusionCalculator`.
+/// The corner values are blended across the face by the position of each vertex, which gives the exact
+/// corner values for full faces and an interpolated value for smaller ones
+fn smooth_lighting<Provider: BlockStateProvider>(
+    pos: IVec3,
+    face: &BlockModelFace,
+    dir: Direction,
+    full_cube: bool,
+    block_manager: &BlockManager,
+    state_provider: &Provider,
+) -> FaceLighting {
+    let on_boundary = full_cube || is_on_boundary(face, dir);
+    let origin = if on_boundary { pos + dir.to_vec() } else { pos };
+
+    //Light goes through anything which isn't opaque, but the shape alone darkens the corners
+    let opaque = |pos: IVec3| is_opaque_full_cube(block_manager, state_provider.get_state(pos));
+    //`AbstractBlockState::getAmbientOcclusionLightLevel`
+    let sample = |pos: IVec3| {
+        (
+            state_provider.get_light_level(pos),
+            if is_full_cube(block_manager, state_provider.get_state(pos)) {
+                0.2
+            } else {
+                1.0
+            },
+        )
+    };
+
+    let neighbours = smooth_lighting_neighbours(dir);
+    let sides = neighbours.map(|side| sample(origin + side.to_vec()));
+    //Light only reaches the diagonal block if one of the two sides next to it is see-through
+    let clear = neighbours.map(|side| !opaque(origin + side.to_vec() + dir.to_vec()));
+
+    let center_light = if on_boundary || !opaque(pos + dir.to_vec()) {
+        state_provider.get_light_level(pos + dir.to_vec())
+    } else {
+        state_provider.get_light_level(pos)
+    };
+    let center_brightness = sample(origin).1;
+
+    //Indexed by the side out of the first pair of neighbours, then the side out of the second pair
+    let corners = [0, 1].map(|a| {
+        [2, 3].map(|b| {
+            let corner = if clear[a] || clear[b] {
+                sample(origin + neighbours[a].to_vec() + neighbours[b].to_vec())
+            } else {
+                sides[a]
+            };
+
+            (
+                blend_corner_light([sides[b].0, sides[a].0, corner.0], center_light),
+                (sides[a].1 + sides[b].1 + corner.1 + center_brightness) / 4.0,
+            )
+        })
+    });
+
+    //How close a vertex is to the given side of the block, from 0 to 1
+    let closeness = |side: Direction, position: Vec3| {
+        let axis = side.to_vec().as_vec3();
+        let along = axis.abs().dot(position).clamp(0.0, 1.0);
+
+        if axis.element_sum() > 0.0 {
+            along
+        } else {
+            1.0 - along
+        }
+    };
+
+    let blended = face.vertices.map(|vertex| {
+        let a = closeness(neighbours[0], vertex.position);
+        let b = closeness(neighbours[2], vertex.position);
+
+        [
+            (0, 0, a * b),
+            (0, 1, a * (1.0 - b)),
+            (1, 0, (1.0 - a) * b),
+            (1, 1, (1.0 - a) * (1.0 - b)),
+        ]
+        .into_iter()
+        .fold((Vec2::ZERO, 0.0), |(light, brightness), (a, b, weight)| {
+            (
+                light + corners[a][b].0 * weight,
+                brightness + corners[a][b].1 * weight,
+            )
+        })
+    });
+
+    FaceLighting {
+        light: blended.map(|(light, _)| {
+            let light = light.round();
+            LightLevel::from_sky_and_block(light.x as u8, light.y as u8)
+        }),
+        brightness: blended.map(|(_, brightness)| brightness),
+    }
+}
+
+/// The atlas sprites a fluid is rendered with
+#[derive(Clone, Copy, Debug)]
+pub struct FluidSprites {
+    pub still: FluidSprite,
+    pub flow: FluidSprite,
+}
+
+/// The UV of a sprite's first frame and its animation index, see
+/// [Atlas::animated_texture_offsets](crate::render::atlas::Atlas::animated_texture_offsets)
+#[derive(Clone, Copy, Debug)]
+pub struct FluidSprite {
+    pub uv: UV,
+    pub animation: u32,
+}
+
+impl FluidSprite {
+    /// Map coordinates from 0 to 1 onto the first frame of the sprite, along with its animation index
+    fn frame_uv(self, u: f32, v: f32) -> ([u16; 2], u32) {
+        let size = (self.uv.1 .0 - self.uv.0 .0) as f32;
+
+        (
+            [
+                self.uv.0 .0 + (u * size).round() as u16,
+                self.uv.0 .1 + (v * size).round() as u16,
+            ],
+            self.animation,
+        )
+    }
+}
+
+fn push_quad(baked_layer: &mut BakedLayer, vertices: [Vertex; 4]) {
+    let vec_index = baked_layer.vertices.len() / Vertex::VERTEX_LENGTH;
+
+    baked_layer.quad_centroids.push(
+        vertices
+            .iter()
+            .map(|vertex| Vec3::from_array(vertex.position))
+            .sum::<Vec3>()
+            / 4.0,
+    );
+    baked_layer
+        .vertices
+        .extend(vertices.into_iter().flat_map(Vertex::compressed));
+    baked_layer.indices.extend(
+        QUAD_INDICES
+            .iter()
+            .flat_map(|index| (index + (vec_index as u32)).to_ne_bytes()),
+    );
+}
+
+/// Approximation of `BlockState::isSolid`, any block with at least one full face counts
+fn is_solid(block_manager: &BlockManager, state: ChunkBlockState) -> bool {
+    get_block_shape(block_manager, state).is_some_and(|mesh| mesh.cull != 0)
+}
+
+/// The height of `fluid` in a neighbouring block. -1 means the block is solid, which excludes it from
+/// the corner height averaging. See `FluidRenderer::getFluidHeight`
+fn fluid_height<Provider: BlockStateProvider>(
+    block_manager: &BlockManager,
+    state_provider: &Provider,
+    fluid: Fluid,
+    pos: IVec3,
+) -> f32 {
+    match state_provider.get_fluid_state(pos) {
+        Some(fluid_state) if fluid_state.fluid == fluid => {
+            let above = state_provider.get_fluid_state(pos + Direction::Up.to_vec());
+
+            if above.is_some_and(|above| above.fluid == fluid) {
+                1.0
+            } else {
+                fluid_state.height()
+            }
+        }
+        _ => {
+            if is_solid(block_manager, state_provider.get_state(pos)) {
+                -1.0
+            } else {
+                0.0
+            }
+        }
+    }
+}
+
+/// Averages the heights of the blocks sharing a corner of the fluid surface, weighting almost full blocks
+/// more heavily. See `FluidRenderer::calculateFluidHeight`
+fn corner_height<Provider: BlockStateProvider>(
+    block_manager: &BlockManager,
+    state_provider: &Provider,
+    fluid: Fluid,
+    origin_height: f32,
+    north_south_height: f32,
+    east_west_height: f32,
+    diagonal_pos: IVec3,
+) -> f32 {
+    if east_west_height >= 1.0 || north_south_height >= 1.0 {
+        return 1.0;
+    }
+
+    let mut total = 0.0;
+    let mut weight = 0.0;
+
+    let mut add_height = |height: f32| {
+        if height >= 0.8 {
+            total += height * 10.0;
+            weight += 10.0;
+        } else if height >= 0.0 {
+            total += height;
+            weight += 1.0;
+        }
+    };
 
-                let color = if face.tint_index != -1 {
-                    state_provider.get_block_color(pos + section_offset, face.tint_index)
+    if east_west_height > 0.0 || north_south_height > 0.0 {
+        let diagonal_height = fluid_height(block_manager, state_provider, fluid, diagonal_pos);
+
+        if diagonal_height >= 1.0 {
+            return 1.0;
+        }
+
+        add_height(diagonal_height);
+    }
+
+    add_height(origin_height);
+    add_height(north_south_height);
+    add_height(east_west_height);
+
+    total / weight
+}
+
+/// The horizontal direction the fluid is flowing in, not normalized. See `FlowableFluid::getVelocity`
+fn flow_direction<Provider: BlockStateProvider>(
+    block_manager: &BlockManager,
+    state_provider: &Provider,
+    fluid: Fluid,
+    pos: IVec3,
+    own_height: f32,
+) -> Vec2 {
+    let mut flow = Vec2::ZERO;
+
+    for dir in [
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ] {
+        let neighbour = pos + dir.to_vec();
+
+        let neighbour_height = match state_provider.get_fluid_state(neighbour) {
+            Some(fluid_state) if fluid_state.fluid == fluid => fluid_state.height(),
+            Some(_) => continue,
+            None => 0.0,
+        };
+
+        let difference = if neighbour_height == 0.0 {
+            if is_solid(block_manager, state_provider.get_state(neighbour)) {
+                continue;
+            }
+
+            match state_provider.get_fluid_state(neighbour + Direction::Down.to_vec()) {
+                Some(below) if below.fluid == fluid => own_height - (below.height() - 8.0 / 9.0),
+                _ => continue,
+            }
+        } else {
+            own_height - neighbour_height
+        };
+
+        flow += dir.to_vec().xz().as_vec2() * difference;
+    }
+
+    flow
+}
+
+/// Bakes the mesh of a single fluid block, following Minecraft's `FluidRenderer`
+#[allow(clippy::too_many_arguments)]
+fn bake_fluid<Provider: BlockStateProvider>(
+    pos: IVec3,
+    section_offset: IVec3,
+    fluid_state: FluidState,
+    sprites: &FluidSprites,
+    block_manager: &BlockManager,
+    state_provider: &Provider,
+    biome_blend_radius: u32,
+    biome_colors: &BiomeColorCache,
+    baked_layer: &mut BakedLayer,
+) {
+    let fluid = fluid_state.fluid;
+
+    let is_same_fluid = |dir: Direction| {
+        state_provider
+            .get_fluid_state(pos + dir.to_vec())
+            .is_some_and(|neighbour| neighbour.fluid == fluid)
+    };
+
+    let is_covered = |dir: Direction| {
+        get_block_shape(block_manager, state_provider.get_state(pos + dir.to_vec()))
+            .is_some_and(|mesh| (mesh.cull >> dir.opposite() as u8) & 1 == 1)
+    };
+
+    let color = match fluid {
+        Fluid::Water => tint_color(
+            block_manager,
+            state_provider,
+            Some(TintSource::Water),
+            pos,
+            section_offset,
+            0,
+            biome_blend_radius,
+            biome_colors,
+        ),
+        Fluid::Lava => 0xffffffff,
+    };
+
+    let height = |offset: IVec3| fluid_height(block_manager, state_provider, fluid, pos + offset);
+
+    let own_height = height(IVec3::ZERO);
+
+    //Heights of the north-west, south-west, south-east and north-east corners
+    let [nw, sw, se, ne] = if own_height >= 1.0 {
+        [1.0; 4]
+    } else {
+        let north = height(Direction::North.to_vec());
+        let south = height(Direction::South.to_vec());
+        let west = height(Direction::West.to_vec());
+        let east = height(Direction::East.to_vec());
+
+        let corner = |north_south: f32, east_west: f32, diagonal: IVec3| {
+            corner_height(
+                block_manager,
+                state_provider,
+                fluid,
+                own_height,
+                north_south,
+                east_west,
+                pos + diagonal,
+            )
+        };
+
+        [
+            corner(north, west, ivec3(-1, 0, -1)),
+            corner(south, west, ivec3(-1, 0, 1)),
+            corner(south, east, ivec3(1, 0, 1)),
+            corner(north, east, ivec3(1, 0, -1)),
+        ]
+    };
+
+    let fpos = pos.as_vec3();
+
+    let vertex = |offset: Vec3,
+                  (uv, uv_offset): ([u16; 2], u32),
+                  normal: Direction,
+                  light_level: LightLevel| Vertex {
+        position: (fpos + offset).to_array(),
+        uv,
+        normal: normal.to_vec().as_vec3().to_array(),
+        color,
+        uv_offset,
+        lightmap_coords: light_level.byte,
+        ao: (normal.shade() * 255.0).round() as u8,
+    };
+
+    if !is_same_fluid(Direction::Up) && !is_covered(Direction::Up) {
+        let light_level = state_provider.get_light_level(pos + Direction::Up.to_vec());
+
+        let flow = flow_direction(block_manager, state_provider, fluid, pos, own_height);
+
+        //Same vertex order as the up face of a block model: north-west, south-west, south-east, north-east
+        let uvs = if flow == Vec2::ZERO {
+            [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)]
+                .map(|(u, v)| sprites.still.frame_uv(u, v))
+        } else {
+            let angle = flow.y.atan2(flow.x) - std::f32::consts::FRAC_PI_2;
+            let sin = angle.sin() * 0.25;
+            let cos = angle.cos() * 0.25;
+
+            [
+                (-cos - sin, -cos + sin),
+                (-cos + sin, cos + sin),
+                (cos + sin, cos - sin),
+                (cos - sin, -cos - sin),
+            ]
+            .map(|(u, v)| sprites.flow.frame_uv(0.5 + u, 0.5 + v))
+        };
+
+        let vertices = [
+            vertex(vec3(0.0, nw, 0.0), uvs[0], Direction::Up, light_level),
+            vertex(vec3(0.0, sw, 1.0), uvs[1], Direction::Up, light_level),
+            vertex(vec3(1.0, se, 1.0), uvs[2], Direction::Up, light_level),
+            vertex(vec3(1.0, ne, 0.0), uvs[3], Direction::Up, light_level),
+        ];
+
+        push_quad(baked_layer, vertices);
+
+        //Visible from below the surface as well
+        let [a, b, c, d] = vertices.map(|vertex| Vertex {
+            normal: Direction::Down.to_vec().as_vec3().to_array(),
+            ..vertex
+        });
+        push_quad(baked_layer, [d, c, b, a]);
+    }
+
+    if !is_same_fluid(Direction::Down) && !is_covered(Direction::Down) {
+        let light_level = state_provider.get_light_level(pos + Direction::Down.to_vec());
+        let uv = |u, v| sprites.still.frame_uv(u, v);
+
+        push_quad(
+            baked_layer,
+            [
+                vertex(
+                    vec3(0.0, 0.0, 0.0),
+                    uv(0.0, 0.0),
+                    Direction::Down,
+                    light_level,
+                ),
+                vertex(
+                    vec3(1.0, 0.0, 0.0),
+                    uv(1.0, 0.0),
+                    Direction::Down,
+                    light_level,
+                ),
+                vertex(
+                    vec3(1.0, 0.0, 1.0),
+                    uv(1.0, 1.0),
+                    Direction::Down,
+                    light_level,
+                ),
+                vertex(
+                    vec3(0.0, 0.0, 1.0),
+                    uv(0.0, 1.0),
+                    Direction::Down,
+                    light_level,
+                ),
+            ],
+        );
+    }
+
+    //Each side goes from the bottom of its first corner, up to both corners and back down, matching
+    //the vertex order of the block model faces
+    let sides = [
+        (
+            Direction::North,
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+            nw,
+            ne,
+        ),
+        (
+            Direction::South,
+            vec3(1.0, 0.0, 1.0),
+            vec3(0.0, 0.0, 1.0),
+            se,
+            sw,
+        ),
+        (
+            Direction::West,
+            vec3(0.0, 0.0, 1.0),
+            vec3(0.0, 0.0, 0.0),
+            sw,
+            nw,
+        ),
+        (
+            Direction::East,
+            vec3(1.0, 0.0, 0.0),
+            vec3(1.0, 0.0, 1.0),
+            ne,
+            se,
+        ),
+    ];
+
+    for (dir, first, second, first_height, second_height) in sides {
+        if is_same_fluid(dir) || is_covered(dir) {
+            continue;
+        }
+
+        let light_level = state_provider.get_light_level(pos + dir.to_vec());
+        let uv = |u, v| sprites.flow.frame_uv(u, v);
+
+        push_quad(
+            baked_layer,
+            [
+                vertex(first, uv(0.0, 0.5), dir, light_level),
+                vertex(
+                    first + vec3(0.0, first_height, 0.0),
+                    uv(0.0, (1.0 - first_height) * 0.5),
+                    dir,
+                    light_level,
+                ),
+                vertex(
+                    second + vec3(0.0, second_height, 0.0),
+                    uv(0.5, (1.0 - second_height) * 0.5),
+                    dir,
+                    light_level,
+                ),
+                vertex(second, uv(0.5, 0.5), dir, light_level),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::mc::block::{BlockMeshVertex, BlockstateKey};
+    use crate::mc::registry::BlockRegistry;
+    use crate::mc::tint::BiomeTints;
+    use crate::mc::{Block, WeightedModel};
+
+    /// Stands in for the chunk buffer, remembering which section and word of its ranges each word holds
+    struct ChunkBuffer(Vec<Option<(IVec3, u32)>>);
+
+    impl ChunkBuffer {
+        fn new(storage: &SectionStorage) -> Self {
+            Self(vec![None; storage.capacity() as usize])
+        }
+
+        /// Does what [WmRenderer::submit_chunk_updates] does with a baked section
+        fn upload(&mut self, storage: &mut SectionStorage, pos: IVec3, baked: &BakedSection) {
+            let section = storage.replace(pos, baked);
+
+            if let Some(moves) = storage.take_relocation() {
+                self.relocate(storage, &moves);
+            }
+
+            self.write(pos, &section);
+        }
+
+        fn write(&mut self, pos: IVec3, section: &Section) {
+            let mut word = 0;
+
+            for range in section.ranges() {
+                for index in range {
+                    self.0[index as usize] = Some((pos, word));
+                    word += 1;
+                }
+            }
+        }
+
+        /// Copies each move's source into a buffer of the storage's capacity, checking that the source holds the
+        /// words the storage had there before
+        fn relocate(&mut self, storage: &SectionStorage, moves: &[ChunkBufferMove]) {
+            let mut relocated = vec![None; storage.capacity() as usize];
+
+            for (source, destination) in moves {
+                assert!(
+                    source.end as usize <= self.0.len(),
+                    "{source:?} is out of bounds"
+                );
+
+                for (offset, index) in source.clone().enumerate() {
+                    let word = self.0[index as usize];
+                    assert!(word.is_some(), "{source:?} doesn't hold any geometry");
+
+                    relocated[*destination as usize + offset] = word;
+                }
+            }
+
+            self.0 = relocated;
+        }
+
+        /// Checks that no two sections overlap, and that each one's ranges hold its own words
+        fn assert_consistent(&self, storage: &SectionStorage) {
+            let mut ranges: Vec<Range<u32>> =
+                storage.iter().flat_map(|(_, s)| s.ranges()).collect();
+            ranges.sort_unstable_by_key(|range| range.start);
+
+            for pair in ranges.windows(2) {
+                assert!(pair[0].end <= pair[1].start, "{pair:?} overlap");
+            }
+
+            assert!(ranges
+                .last()
+                .is_none_or(|last| last.end <= storage.capacity()));
+
+            for (pos, section) in storage.iter() {
+                let mut word = 0;
+
+                for range in section.ranges() {
+                    for index in range {
+                        assert_eq!(self.0[index as usize], Some((*pos, word)), "at {index}");
+                        word += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// A section with a single solid layer taking up `words` words of vertices and of indices
+    fn baked(words: usize) -> BakedSection {
+        let mut layers = vec![BakedLayer::default(); RenderLayer::COUNT];
+        layers[RenderLayer::Solid as usize] = BakedLayer {
+            vertices: vec![0; words * 4],
+            indices: vec![0; words * 4],
+            quad_centroids: Vec::new(),
+        };
+
+        BakedSection {
+            layers,
+            visibility: SectionVisibility::ALL,
+        }
+    }
+
+    #[test]
+    fn replace_grow_and_relocate() {
+        let mut storage = SectionStorage::new(64, 1024);
+        let mut buffer = ChunkBuffer::new(&storage);
+
+        for x in 0..3 {
+            buffer.upload(&mut storage, ivec3(x, 0, 0), &baked(8));
+        }
+        buffer.assert_consistent(&storage);
+
+        //Doesn't fit in the space left or the ranges it frees
+        buffer.upload(&mut storage, ivec3(1, 0, 0), &baked(24));
+        buffer.assert_consistent(&storage);
+
+        assert!(storage.capacity() > 64);
+        assert_eq!(buffer.0.len(), storage.capacity() as usize);
+    }
+
+    #[test]
+    fn compact_after_untaken_grow() {
+        let mut storage = SectionStorage::new(64, 96);
+        let mut buffer = ChunkBuffer::new(&storage);
+
+        for x in 0..8 {
+            buffer.upload(&mut storage, ivec3(x, 0, 0), &baked(4));
+        }
+
+        //Leaves the free space in holes too small for anything bigger
+        for x in (0..8).step_by(2) {
+            buffer.upload(&mut storage, ivec3(x, 0, 0), &BakedSection::default());
+        }
+        buffer.assert_consistent(&storage);
+
+        //Growing to the largest capacity still leaves too little room at the end, so the buffer is compacted
+        //before the relocation from growing is taken
+        let section = storage.replace(ivec3(8, 0, 0), &baked(24));
+        assert!(section.layers.iter().any(Option::is_some));
+        assert_eq!(storage.capacity(), 96);
+
+        let moves = storage.take_relocation().unwrap();
+        buffer.relocate(&storage, &moves);
+        buffer.write(ivec3(8, 0, 0), &section);
+        buffer.assert_consistent(&storage);
+
+        assert!(storage.take_relocation().is_none());
+    }
+
+    #[test]
+    fn reuse_freed_range() {
+        let mut storage = SectionStorage::new(64, 64);
+        let mut buffer = ChunkBuffer::new(&storage);
+
+        //Fills the buffer
+        for x in [0, 10, 1, 2] {
+            buffer.upload(&mut storage, ivec3(x, 0, 0), &baked(8));
+        }
+
+        let mut freed: Vec<_> = storage.get(&ivec3(10, 0, 0)).unwrap().ranges().collect();
+        freed.sort_unstable_by_key(|range| range.start);
+
+        //Unloads the section far from the camera
+        storage.trim(IVec2::ZERO);
+        assert!(storage.get(&ivec3(10, 0, 0)).is_none());
+
+        //The only space left is what was freed
+        buffer.upload(&mut storage, ivec3(3, 0, 0), &baked(8));
+        buffer.assert_consistent(&storage);
+
+        let mut reused: Vec<_> = storage.get(&ivec3(3, 0, 0)).unwrap().ranges().collect();
+        reused.sort_unstable_by_key(|range| range.start);
+
+        assert_eq!(reused, freed);
+        assert_eq!(storage.capacity(), 64);
+    }
+
+    /// A section with a single translucent quad at its center
+    fn translucent() -> BakedSection {
+        let mut section = baked(0);
+        section.layers[RenderLayer::Transparent as usize] = BakedLayer {
+            vertices: vec![0; 4 * 4 * 4],
+            indices: vec![0; 6 * 4],
+            quad_centroids: vec![Vec3::splat(8.0)],
+        };
+
+        section
+    }
+
+    #[test]
+    fn sorts_quads_back_to_front() {
+        let centroids = [
+            vec3(0.0, 0.0, 0.0),
+            vec3(10.0, 0.0, 0.0),
+            vec3(5.0, 0.0, 0.0),
+        ];
+
+        let indices: Vec<u32> = sort_translucent_indices(&centroids, vec3(-1.0, 0.0, 0.0))
+            .chunks_exact(4)
+            .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()))
+            .collect();
+
+        assert_eq!(
+            indices,
+            [5, 7, 4, 6, 7, 5, 9, 11, 8, 10, 11, 9, 1, 3, 0, 2, 3, 1]
+        );
+    }
+
+    #[test]
+    fn sorts_again_once_camera_moved() {
+        let mut storage = SectionStorage::new(64, 64);
+        storage.replace(ivec3(0, 0, 0), &translucent());
+        storage.replace(ivec3(1, 0, 0), &baked(8));
+
+        let sorted = |jobs: Vec<(IVec3, Arc<Vec<Vec3>>)>| -> Vec<IVec3> {
+            jobs.into_iter().map(|(pos, _)| pos).collect()
+        };
+
+        assert_eq!(
+            sorted(storage.translucent_sort_jobs(Vec3::ZERO)),
+            [IVec3::ZERO]
+        );
+        assert!(storage
+            .translucent_sort_jobs(vec3(0.5, 0.5, 0.0))
+            .is_empty());
+
+        //Measured from where it was last sorted, not from the last call
+        assert_eq!(
+            sorted(storage.translucent_sort_jobs(vec3(0.9, 0.6, 0.0))),
+            [IVec3::ZERO]
+        );
+
+        //Rebaked sections are sorted even if the camera stands still
+        storage.replace(ivec3(0, 0, 0), &translucent());
+        assert_eq!(
+            sorted(storage.translucent_sort_jobs(vec3(0.9, 0.6, 0.0))),
+            [IVec3::ZERO]
+        );
+        assert!(storage
+            .translucent_sort_jobs(vec3(0.9, 0.6, 0.0))
+            .is_empty());
+    }
+
+    fn dirty_sections(storage: &mut SectionStorage, blocks: &[IVec3]) -> HashSet<IVec3> {
+        blocks.iter().for_each(|pos| storage.mark_block_dirty(*pos));
+
+        storage.take_dirty_sections().into_iter().collect()
+    }
+
+    /// Storage with the sections within one of the origin loaded
+    fn loaded_around_origin() -> SectionStorage {
+        let mut storage = SectionStorage::new(64, 64);
+
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    storage.replace(ivec3(x, y, z), &BakedSection::default());
+                }
+            }
+        }
+
+        storage
+    }
+
+    #[test]
+    fn marks_neighbours_of_blocks_on_the_edge() {
+        let mut storage = loaded_around_origin();
+
+        assert_eq!(
+            dirty_sections(&mut storage, &[ivec3(8, 8, 8)]),
+            HashSet::from([IVec3::ZERO])
+        );
+        assert_eq!(
+            dirty_sections(&mut storage, &[ivec3(0, 8, 8)]),
+            HashSet::from([IVec3::ZERO, ivec3(-1, 0, 0)])
+        );
+        assert_eq!(
+            dirty_sections(&mut storage, &[ivec3(15, 15, 8)]),
+            HashSet::from([IVec3::ZERO, ivec3(1, 0, 0), ivec3(0, 1, 0), ivec3(1, 1, 0)])
+        );
+
+        //In world space, so negative blocks belong to the sections below the origin
+        assert_eq!(
+            dirty_sections(&mut storage, &[ivec3(-1, -1, -1)]),
+            (0..8)
+                .map(|i| ivec3(i & 1, (i >> 1) & 1, i >> 2) - 1)
+                .collect()
+        );
+    }
+
+    #[test]
+    fn marks_every_section_around_a_corner() {
+        let mut storage = loaded_around_origin();
+
+        assert_eq!(
+            dirty_sections(&mut storage, &[IVec3::ZERO]),
+            (0..8)
+                .map(|i| -ivec3(i & 1, (i >> 1) & 1, i >> 2))
+                .collect()
+        );
+    }
+
+    #[test]
+    fn coalesces_updates() {
+        let mut storage = loaded_around_origin();
+
+        for pos in [
+            ivec3(8, 8, 8),
+            ivec3(9, 8, 8),
+            ivec3(0, 8, 8),
+            ivec3(0, 8, 8),
+        ] {
+            storage.mark_block_dirty(pos);
+        }
+
+        let mut dirty = storage.take_dirty_sections();
+        dirty.sort_unstable_by_key(|pos| pos.to_array());
+
+        assert_eq!(dirty, [ivec3(-1, 0, 0), IVec3::ZERO]);
+        assert!(storage.take_dirty_sections().is_empty());
+    }
+
+    #[test]
+    fn skips_sections_which_arent_loaded() {
+        let mut storage = loaded_around_origin();
+
+        assert!(dirty_sections(&mut storage, &[ivec3(100, 8, 8)]).is_empty());
+        assert_eq!(
+            dirty_sections(&mut storage, &[ivec3(31, 8, 8)]),
+            HashSet::from([ivec3(1, 0, 0)])
+        );
+    }
+
+    const STONE: ChunkBlockState = ChunkBlockState::State(BlockstateKey {
+        block: 0,
+        augment: 0,
+    });
+
+    const LEAVES: ChunkBlockState = ChunkBlockState::State(BlockstateKey {
+        block: 1,
+        augment: 0,
+    });
+
+    /// A handful of blocks and fluids in air, lit by the sky unless given another light level
+    #[derive(Default)]
+    struct World {
+        blocks: HashMap<IVec3, ChunkBlockState>,
+        fluids: HashMap<IVec3, FluidState>,
+        light: HashMap<IVec3, LightLevel>,
+    }
+
+    impl BlockStateProvider for World {
+        fn get_state(&self, pos: IVec3) -> ChunkBlockState {
+            self.blocks
+                .get(&pos)
+                .copied()
+                .unwrap_or(ChunkBlockState::Air)
+        }
+
+        fn get_light_level(&self, pos: IVec3) -> LightLevel {
+            self.light
+                .get(&pos)
+                .copied()
+                .unwrap_or(LightLevel::from_sky_and_block(15, 0))
+        }
+
+        fn is_section_empty(&self, _rel_pos: IVec3) -> bool {
+            false
+        }
+
+        fn get_fluid_state(&self, pos: IVec3) -> Option<FluidState> {
+            self.fluids.get(&pos).copied()
+        }
+    }
+
+    fn cube(cull: u8, layer: RenderLayer) -> Block {
+        let mesh = ModelMesh {
+            north: Vec::new(),
+            south: Vec::new(),
+            west: Vec::new(),
+            east: Vec::new(),
+            up: Vec::new(),
+            down: Vec::new(),
+            any: Vec::new(),
+            cull,
+            full_cube: true,
+            layer,
+        };
+
+        Block::Variants(IndexMap::from([(
+            Vec::new(),
+            vec![WeightedModel {
+                mesh: Arc::new(mesh),
+                weight: 1,
+            }],
+        )]))
+    }
+
+    /// Knows [STONE], a full opaque cube, and [LEAVES], a full cube with see-through textures
+    fn block_manager() -> BlockManager {
+        BlockManager {
+            blocks: IndexMap::from([
+                (
+                    "minecraft:stone".to_string(),
+                    cube(0b111111, RenderLayer::Solid),
+                ),
+                (
+                    "minecraft:oak_leaves".to_string(),
+                    cube(0, RenderLayer::Cutout),
+                ),
+            ]),
+            fluids: HashMap::new(),
+            biome_tints: BiomeTints::new(),
+            registry: BlockRegistry::default(),
+        }
+    }
+
+    fn fluid(fluid: Fluid, level: u8) -> FluidState {
+        FluidState {
+            fluid,
+            level,
+            falling: false,
+        }
+    }
+
+    fn water(level: u8) -> FluidState {
+        fluid(Fluid::Water, level)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{actual} isn't close to {expected}"
+        );
+    }
+
+    #[test]
+    fn fluid_heights() {
+        let block_manager = block_manager();
+        let world = World {
+            blocks: HashMap::from([(ivec3(0, 0, 1), STONE)]),
+            fluids: HashMap::from([
+                (ivec3(0, 0, 0), water(8)),
+                (ivec3(1, 0, 0), water(4)),
+                (ivec3(1, 1, 0), water(8)),
+                (ivec3(2, 0, 0), water(3)),
+                (
+                    ivec3(3, 0, 0),
+                    FluidState {
+                        falling: true,
+                        ..water(1)
+                    },
+                ),
+                (ivec3(4, 0, 0), fluid(Fluid::Lava, 8)),
+            ]),
+            ..Default::default()
+        };
+
+        let height = |pos| fluid_height(&block_manager, &world, Fluid::Water, pos);
+
+        assert_close(height(ivec3(0, 0, 0)), 8.0 / 9.0);
+        //Fluid above fills the block
+        assert_close(height(ivec3(1, 0, 0)), 1.0);
+        assert_close(height(ivec3(2, 0, 0)), 3.0 / 9.0);
+        assert_close(height(ivec3(3, 0, 0)), 8.0 / 9.0);
+        //Other fluids count as empty, solid blocks are left out of the average
+        assert_close(height(ivec3(4, 0, 0)), 0.0);
+        assert_close(height(ivec3(0, 0, -1)), 0.0);
+        assert_close(height(ivec3(0, 0, 1)), -1.0);
+    }
+
+    #[test]
+    fn corner_heights() {
+        let block_manager = block_manager();
+        let world = World {
+            blocks: HashMap::new(),
+            fluids: HashMap::from([(ivec3(5, 0, 5), water(8)), (ivec3(5, 1, 5), water(8))]),
+            ..Default::default()
+        };
+
+        let corner = |origin, north_south, east_west, diagonal| {
+            corner_height(
+                &block_manager,
+                &world,
+                Fluid::Water,
+                origin,
+                north_south,
+                east_west,
+                diagonal,
+            )
+        };
+
+        let source = 8.0 / 9.0;
+
+        //A lone source block, weighted 10 against the two empty neighbours
+        assert_close(corner(source, 0.0, 0.0, IVec3::ZERO), 20.0 / 27.0);
+        //A solid neighbour is left out
+        assert_close(corner(source, -1.0, 0.0, IVec3::ZERO), 80.0 / 99.0);
+        //Flowing fluid next to a source, with the empty diagonal counted as well
+        assert_close(corner(4.0 / 9.0, 0.0, source, IVec3::ZERO), 84.0 / 117.0);
+        //Any full neighbour, including the diagonal, fills the corner
+        assert_close(corner(4.0 / 9.0, 1.0, 0.0, IVec3::ZERO), 1.0);
+        assert_close(corner(4.0 / 9.0, source, 0.0, ivec3(5, 0, 5)), 1.0);
+    }
+
+    #[test]
+    fn flows_towards_lower_neighbours() {
+        let block_manager = block_manager();
+        let world = World {
+            blocks: HashMap::from([(ivec3(0, 0, -1), STONE)]),
+            fluids: HashMap::from([
+                (ivec3(0, 0, 0), water(8)),
+                (ivec3(1, 0, 0), water(7)),
+                //Falls down past the empty block to the west
+                (ivec3(-1, -1, 0), water(8)),
+                (ivec3(0, 0, 1), fluid(Fluid::Lava, 8)),
+            ]),
+            ..Default::default()
+        };
+
+        let flow = flow_direction(&block_manager, &world, Fluid::Water, IVec3::ZERO, 8.0 / 9.0);
+
+        assert_close(flow.x, 1.0 / 9.0 - 8.0 / 9.0);
+        assert_close(flow.y, 0.0);
+
+        let world = World {
+            blocks: HashMap::new(),
+            fluids: HashMap::from([(ivec3(0, 0, 0), water(6)), (ivec3(0, 0, 1), water(3))]),
+            ..Default::default()
+        };
+
+        let flow = flow_direction(&block_manager, &world, Fluid::Water, IVec3::ZERO, 6.0 / 9.0);
+
+        //Empty neighbours with nothing below them don't pull the fluid
+        assert_close(flow.x, 0.0);
+        assert_close(flow.y, 3.0 / 9.0);
+    }
+
+    /// Which face of the block at the origin each quad of a layer belongs to
+    fn fluid_faces(world: &World) -> Vec<Direction> {
+        let sprite = FluidSprite {
+            uv: ((0, 0), (16, 16)),
+            animation: 0,
+        };
+        let mut layer = BakedLayer::default();
+
+        bake_fluid(
+            IVec3::ZERO,
+            IVec3::ZERO,
+            world.fluids[&IVec3::ZERO],
+            &FluidSprites {
+                still: sprite,
+                flow: sprite,
+            },
+            &block_manager(),
+            world,
+            0,
+            &BiomeColorCache::default(),
+            &mut layer,
+        );
+
+        let mut faces: Vec<Direction> = layer
+            .quad_centroids
+            .iter()
+            .map(|centroid| {
+                if centroid.x == 0.0 {
+                    Direction::West
+                } else if centroid.x == 1.0 {
+                    Direction::East
+                } else if centroid.z == 0.0 {
+                    Direction::North
+                } else if centroid.z == 1.0 {
+                    Direction::South
+                } else if centroid.y == 0.0 {
+                    Direction::Down
                 } else {
-                    0xffffffff
-                };
+                    Direction::Up
+                }
+            })
+            .collect();
+        faces.sort_unstable_by_key(|dir| *dir as u8);
 
-                add_quad(face, light_level, Direction::Up, color);
-            });
+        faces
+    }
+
+    #[test]
+    fn culls_fluid_sides() {
+        use Direction::*;
+
+        let lava = fluid(Fluid::Lava, 8);
+
+        let lone = World {
+            blocks: HashMap::new(),
+            fluids: HashMap::from([(IVec3::ZERO, lava)]),
+            ..Default::default()
+        };
+        //The surface is drawn from below as well
+        assert_eq!(fluid_faces(&lone), [West, East, Down, Up, Up, North, South]);
+
+        //Covered by a solid block, next to the same fluid, and the surface is hidden under more of it
+        let covered = World {
+            blocks: HashMap::from([(ivec3(0, -1, 0), STONE)]),
+            fluids: HashMap::from([
+                (IVec3::ZERO, lava),
+                (ivec3(1, 0, 0), lava),
+                (ivec3(0, 1, 0), lava),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(fluid_faces(&covered), [West, North, South]);
+
+        //Other fluids don't hide each other
+        let mixed = World {
+            blocks: HashMap::new(),
+            fluids: HashMap::from([(IVec3::ZERO, lava), (ivec3(0, 0, -1), water(8))]),
+            ..Default::default()
+        };
+        assert_eq!(
+            fluid_faces(&mixed),
+            [West, East, Down, Up, Up, North, South]
+        );
+    }
+
+    /// A face on the top or bottom of the block, with its vertices in the order north-west, south-west,
+    /// south-east, north-east
+    fn horizontal_face(y: f32, ambient_occlusion: bool, shade: bool) -> BlockModelFace {
+        BlockModelFace {
+            vertices: [
+                vec3(0.0, y, 0.0),
+                vec3(0.0, y, 1.0),
+                vec3(1.0, y, 1.0),
+                vec3(1.0, y, 0.0),
+            ]
+            .map(|position| BlockMeshVertex {
+                position,
+                tex_coords: [0, 0],
+            }),
+            normal: vec3(0.0, y * 2.0 - 1.0, 0.0),
+            tint_index: -1,
+            animation_uv_offset: 0,
+            shade,
+            ambient_occlusion,
+        }
+    }
+
+    /// Lights a face of a full cube at the origin
+    fn light_face(world: &World, face: &BlockModelFace, dir: Direction) -> FaceLighting {
+        face_lighting(
+            IVec3::ZERO,
+            face,
+            dir,
+            true,
+            LightingMode::Smooth,
+            &block_manager(),
+            world,
+        )
+    }
+
+    fn assert_brightness(lighting: &FaceLighting, expected: [f32; 4]) {
+        for (actual, expected) in lighting.brightness.into_iter().zip(expected) {
+            assert_close(actual, expected);
         }
     }
-    layers
+
+    //Expected values were worked out from BlockModelRenderer.AmbientOcclusionCalculator
+
+    #[test]
+    fn full_cubes_darken_corners() {
+        let world = World {
+            blocks: HashMap::from([(ivec3(1, 1, 0), STONE), (ivec3(0, 1, 1), LEAVES)]),
+            light: HashMap::from([(ivec3(1, 1, 0), LightLevel::from_sky_and_block(0, 0))]),
+            ..Default::default()
+        };
+
+        let lighting = light_face(&world, &horizontal_face(1.0, true, true), Direction::Up);
+
+        //Leaves let light through, but darken the corners like any other full cube
+        assert_brightness(&lighting, [1.0, 0.8, 0.6, 0.8]);
+        //Opaque blocks take on the light in front of the face
+        assert_eq!(
+            lighting.light.map(|light| light.byte),
+            [LightLevel::from_sky_and_block(15, 0).byte; 4]
+        );
+    }
+
+    #[test]
+    fn light_reaches_corners_past_see_through_blocks() {
+        let world = |block| World {
+            blocks: HashMap::from([(ivec3(1, 2, 0), block), (ivec3(0, 2, -1), block)]),
+            light: HashMap::from([(ivec3(1, 1, -1), LightLevel::from_sky_and_block(7, 0))]),
+            ..Default::default()
+        };
+        let north_east_light = |block| {
+            light_face(
+                &world(block),
+                &horizontal_face(1.0, true, true),
+                Direction::Up,
+            )
+            .light[3]
+        };
+
+        assert_eq!(
+            north_east_light(LEAVES).byte,
+            LightLevel::from_sky_and_block(13, 0).byte
+        );
+        //Both sides next to the corner are walled off, so the light of the side is used instead
+        assert_eq!(
+            north_east_light(STONE).byte,
+            LightLevel::from_sky_and_block(15, 0).byte
+        );
+    }
+
+    #[test]
+    fn ambient_occlusion_and_shading_can_be_turned_off() {
+        let world = World {
+            blocks: HashMap::from([(ivec3(1, -1, 0), STONE)]),
+            ..Default::default()
+        };
+        let brightness = |ambient_occlusion, shade| {
+            light_face(
+                &world,
+                &horizontal_face(0.0, ambient_occlusion, shade),
+                Direction::Down,
+            )
+        };
+
+        assert_brightness(&brightness(true, true), [0.5, 0.5, 0.4, 0.4]);
+        assert_brightness(&brightness(true, false), [1.0, 1.0, 0.8, 0.8]);
+        //`"ambientocclusion": false` falls back to flat lighting
+        assert_brightness(&brightness(false, true), [0.5; 4]);
+        assert_brightness(&brightness(false, false), [1.0; 4]);
+    }
 }
@@ -1,4 +1,5 @@
-use glam::{ivec3, IVec3};
+use glam::{ivec3, IVec3, Vec3};
+use minecraft_assets::schemas::models::BlockFace;
 
 static VECTOR: [IVec3; 6] = [
     ivec3(-1, 0, 0),
@@ -18,6 +19,15 @@ pub enum Direction {
     South = 5,
 }
 impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Self::West,
+        Self::East,
+        Self::Down,
+        Self::Up,
+        Self::North,
+        Self::South,
+    ];
+
     pub fn to_vec(&self) -> IVec3 {
         VECTOR[*self as usize]
     }
@@ -32,6 +42,39 @@ impl Direction {
         }
     }
 
+    /// The direction closest to `normal`
+    pub fn from_normal(normal: Vec3) -> Self {
+        let abs = normal.abs();
+
+        if abs.x >= abs.y && abs.x >= abs.z {
+            if normal.x < 0.0 {
+                Self::West
+            } else {
+                Self::East
+            }
+        } else if abs.y >= abs.z {
+            if normal.y < 0.0 {
+                Self::Down
+            } else {
+                Self::Up
+            }
+        } else if normal.z < 0.0 {
+            Self::North
+        } else {
+            Self::South
+        }
+    }
+
+    /// How bright faces pointing this way are shaded, see `ClientWorld::getBrightness`
+    pub fn shade(&self) -> f32 {
+        match self {
+            Self::Down => 0.5,
+            Self::Up => 1.0,
+            Self::North | Self::South => 0.8,
+            Self::West | Self::East => 0.6,
+        }
+    }
+
     pub fn rotate(&self, vec: IVec3) -> IVec3 {
         let x = (match self {
             Direction::West => Direction::Down,
@@ -47,3 +90,16 @@ impl Direction {
         vec.x * x + vec.y * self.to_vec() + vec.z * z
     }
 }
+
+impl From<&BlockFace> for Direction {
+    fn from(face: &BlockFace) -> Self {
+        match face {
+            BlockFace::Down => Self::Down,
+            BlockFace::Up => Self::Up,
+            BlockFace::North => Self::North,
+            BlockFace::South => Self::South,
+            BlockFace::West => Self::West,
+            BlockFace::East => Self::East,
+        }
+    }
+}
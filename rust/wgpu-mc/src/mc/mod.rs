@@ -4,28 +4,48 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use arc_swap::ArcSwap;
-use chunk::SectionStorage;
-use glam::{ivec2, IVec2};
+use chunk::{
+    ChunkBufferMove, Fluid, FluidSprite, FluidSprites, LightingMode, RenderLayer, SectionStorage,
+};
+use glam::{ivec2, IVec2, IVec3};
 use indexmap::map::IndexMap;
 use minecraft_assets::schemas;
 use minecraft_assets::schemas::blockstates::multipart::StateValue;
+use minecraft_assets::schemas::blockstates::ModelProperties;
 use parking_lot::{Mutex, RwLock};
+use rayon::prelude::*;
 
 use crate::mc::entity::{BundledEntityInstances, Entity};
 use crate::mc::resource::ResourceProvider;
-use crate::render::atlas::{Atlas, TextureManager};
+use crate::render::atlas::{Atlas, TextureManager, DEFAULT_MIP_LEVELS};
+use crate::render::atlas_source::{atlas_name, AtlasBuilder, AtlasReport, VANILLA_ATLASES};
+use crate::render::indirect::SectionDraws;
 use crate::render::pipeline::BLOCK_ATLAS;
 use crate::util::BindableBuffer;
 use crate::{Display, WmRenderer};
 
-use self::block::ModelMesh;
+use self::block::{BlockstateKey, MeshBakeError, ModelCache, ModelMesh};
+use self::blockstate::{
+    format_state_value, parse_state_value, BlockStateParseError, ParsedBlockState,
+};
+use self::registry::{BlockRegistry, RegistryError};
 use self::resource::ResourcePath;
+use self::tint::{BiomeTints, TintSource, DEFAULT_BIOME_BLEND_RADIUS};
 
 pub mod block;
+pub mod blockstate;
 pub mod chunk;
 pub mod direction;
 pub mod entity;
+pub mod registry;
 pub mod resource;
+pub mod scheduler;
+pub mod tint;
+pub mod visibility;
+/// The errors which came up while baking each block, see [MinecraftState::bake_blocks]. Blocks which baked
+/// without errors are left out
+pub type BakeReport = IndexMap<String, Vec<MeshBakeError>>;
+
 /// Take in a block name (not a [ResourcePath]!) and optionally a variant state key, e.g. "facing=north" and format it some way
 /// for example, `minecraft:anvil[facing=north]` or `Block{minecraft:anvil}[facing=north]`
 pub type BlockVariantFormatter = dyn Fn(&str, Option<&str>) -> String;
@@ -34,20 +54,136 @@ pub struct BlockManager {
     /// This maps block state keys to either a [VariantMesh] or a [Multipart] struct. How the keys are formatted
     /// is defined by the user of wgpu-mc. For example `Block{minecraft:anvil}[facing=west]` or `minecraft:anvil#facing=west`
     pub blocks: IndexMap<String, Block>,
+    /// Fluids aren't described by block models, so their sprites are kept here. Filled in by [MinecraftState::bake_blocks]
+    pub fluids: HashMap<Fluid, FluidSprites>,
+    pub biome_tints: BiomeTints,
+    /// Empty unless the vanilla registry was loaded with [MinecraftState::load_block_registry]
+    pub registry: BlockRegistry,
+}
+
+impl BlockManager {
+    /// Formats the block and variant of a key, e.g. with [blockstate::vanilla_format]. Returns `None` if the
+    /// key doesn't belong to a known blockstate. The variant is only as specific as the blockstate file needs it
+    /// to be, properties which don't change the model are left out.
+    pub fn format_key(
+        &self,
+        key: BlockstateKey,
+        formatter: &BlockVariantFormatter,
+    ) -> Option<String> {
+        let (name, block) = self.blocks.get_index(key.block as usize)?;
+
+        let variant = match block {
+            Block::Variants(variants) => variants
+                .get_index(key.augment as usize)?
+                .0
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, format_state_value(value)))
+                .collect::<Vec<String>>()
+                .join(","),
+            Block::Multipart(multipart) => multipart
+                .keys
+                .read()
+                .get_index(key.augment as usize)?
+                .0
+                .clone(),
+        };
+
+        Some(formatter(name, Some(&variant)))
+    }
+
+    /// The value of a property of a blockstate, if its key knows it. Keys of `variants` blockstates only know the
+    /// properties their blockstate file varies on, like [BlockManager::format_key]
+    pub fn property(&self, key: BlockstateKey, property: &str) -> Option<String> {
+        let variant = self.format_key(key, &|_: &str, variant: Option<&str>| {
+            variant.unwrap_or_default().into()
+        })?;
+
+        variant
+            .split(',')
+            .find_map(|pair| pair.strip_prefix(property)?.strip_prefix('='))
+            .map(Into::into)
+    }
+
+    /// Where the colour of a blockstate's tinted faces comes from, see [BiomeTints::set_source]
+    pub fn tint_source(&self, key: BlockstateKey) -> Option<TintSource> {
+        self.biome_tints
+            .block_source(key.block)?
+            .resolve(|property| self.property(key, property))
+    }
+}
+
+/// Seed used to pick the model variant of a block, same as Minecraft's `MathHelper::hashCode(Vec3i)`
+pub fn position_seed(pos: IVec3) -> i64 {
+    let seed = (pos.x.wrapping_mul(3129871) as i64)
+        ^ (pos.z as i64).wrapping_mul(116129781)
+        ^ pos.y as i64;
+
+    seed.wrapping_mul(seed)
+        .wrapping_mul(42317861)
+        .wrapping_add(seed.wrapping_mul(11))
+        >> 16
+}
+
+/// The first `nextLong()` of a `java.util.Random` created with `seed`, which is how Minecraft's
+/// `CheckedRandom` rolls for a weighted model
+fn java_random_next_long(seed: i64) -> i64 {
+    const MULTIPLIER: i64 = 0x5DEECE66D;
+    const MASK: i64 = (1 << 48) - 1;
+
+    let mut state = (seed ^ MULTIPLIER) & MASK;
+    let mut next_int = || {
+        state = state.wrapping_mul(MULTIPLIER).wrapping_add(0xB) & MASK;
+        (state >> 16) as i32
+    };
+
+    let high = next_int() as i64;
+    let low = next_int() as i64;
+
+    (high << 32).wrapping_add(low)
+}
+
+/// One of the models a blockstate variant picks from, along with how likely it is to be picked
+#[derive(Debug)]
+pub struct WeightedModel {
+    pub mesh: Arc<ModelMesh>,
+    pub weight: u32,
+}
+
+/// Picks a model out of the weighted list like `WeightedBakedModel`
+fn pick_weighted_model(models: &[WeightedModel], seed: i64) -> Option<&Arc<ModelMesh>> {
+    let total_weight: i32 = models.iter().map(|model| model.weight as i32).sum();
+
+    if models.len() <= 1 || total_weight <= 0 {
+        return models.first().map(|model| &model.mesh);
+    }
+
+    let mut index = (java_random_next_long(seed) as i32).wrapping_abs() % total_weight;
+
+    models
+        .iter()
+        .find(|model| {
+            index -= model.weight as i32;
+            index < 0
+        })
+        .or(models.first())
+        .map(|model| &model.mesh)
 }
 
 #[derive(Debug)]
 pub enum Block {
     Multipart(Multipart),
-    Variants(IndexMap<Vec<(String, StateValue)>, Vec<Arc<ModelMesh>>>),
+    Variants(IndexMap<Vec<(String, StateValue)>, Vec<WeightedModel>>),
 }
 
 impl Block {
-    pub fn get_model(&self, key: u16, _seed: u8) -> Option<Arc<ModelMesh>> {
+    /// Get the mesh of a blockstate. `seed` picks between the weighted models of a variant, use
+    /// [position_seed] so that a block always looks the same in the same spot
+    pub fn get_model(&self, key: u16, seed: i64) -> Option<Arc<ModelMesh>> {
         Some(match &self {
             Block::Multipart(multipart) => multipart.keys.read().get_index(key as usize)?.1.clone(),
-            //TODO, random variant selection through weight and seed
-            Block::Variants(variants) => variants.get_index(key as usize)?.1[0].clone(),
+            Block::Variants(variants) => {
+                pick_weighted_model(variants.get_index(key as usize)?.1, seed)?.clone()
+            }
         })
     }
 
@@ -56,29 +192,14 @@ impl Block {
         key: impl IntoIterator<Item = (&'a str, &'a StateValue)> + Clone,
         resource_provider: &dyn ResourceProvider,
         block_atlas: &Atlas,
-        //TODO use this
-        _seed: u8,
+        seed: i64,
     ) -> Option<(Arc<ModelMesh>, u16)> {
         let key_map: HashMap<&str, &StateValue> = key.clone().into_iter().collect();
 
         let key_string = key
             .clone()
             .into_iter()
-            .map(|(key, value)| {
-                format!(
-                    "{}={}",
-                    key,
-                    match value {
-                        StateValue::Bool(bool) =>
-                            if *bool {
-                                "true"
-                            } else {
-                                "false"
-                            },
-                        StateValue::String(string) => string,
-                    }
-                )
-            })
+            .map(|(key, value)| format!("{}={}", key, format_state_value(value)))
             .collect::<Vec<String>>()
             .join(",");
 
@@ -112,7 +233,7 @@ impl Block {
                             )
                         })?;
 
-                Some((full.1 .1[0].clone(), full.0 as u16))
+                Some((pick_weighted_model(full.1 .1, seed)?.clone(), full.0 as u16))
             }
         }
     }
@@ -122,6 +243,10 @@ impl Block {
 pub struct Multipart {
     pub cases: Vec<schemas::blockstates::multipart::Case>,
     pub keys: RwLock<IndexMap<String, Arc<ModelMesh>>>,
+    /// Multipart meshes are baked lazily, so the layer they render in is kept around until then
+    pub layer: RenderLayer,
+    /// The models resolved while the block was baked, which its meshes are made of
+    pub model_cache: Arc<ModelCache>,
 }
 
 impl Multipart {
@@ -144,8 +269,13 @@ impl Multipart {
             apply_variants.into_iter().flatten(),
             resource_provider,
             block_atlas,
+            &self.model_cache,
+            self.layer,
         )
-        .unwrap();
+        .unwrap_or_else(|error| {
+            log::warn!("Couldn't bake multipart model, using the missing model instead: {error:?}");
+            ModelMesh::missing(block_atlas, resource_provider)
+        });
 
         Arc::new(mesh)
     }
@@ -181,12 +311,22 @@ pub struct RenderEffectsData {
     pub dimension_fog_color: [f32; 4],
 }
 
+/// Size of the chunk buffer in bytes before it has to grow
+pub const INITIAL_CHUNK_BUFFER_SIZE: u64 = 64 * 1024 * 1024;
+
+const CHUNK_BUFFER_USAGES: wgpu::BufferUsages = wgpu::BufferUsages::COPY_DST
+    .union(wgpu::BufferUsages::COPY_SRC)
+    .union(wgpu::BufferUsages::VERTEX)
+    .union(wgpu::BufferUsages::STORAGE)
+    .union(wgpu::BufferUsages::INDEX);
+
 pub struct Scene {
     pub section_storage: RwLock<SectionStorage>,
     pub camera_section_pos: RwLock<IVec2>,
-    pub chunk_buffer: Arc<BindableBuffer>,
-
-    pub indirect_buffer: Arc<wgpu::Buffer>,
+    /// Vertex and index data of every section. Replaced with a new buffer when it grows or is compacted
+    pub chunk_buffer: ArcSwap<BindableBuffer>,
+    /// Per-section draw data for pipelines using `@geo_terrain_indirect`
+    pub section_draws: Mutex<SectionDraws>,
 
     pub entity_instances: Mutex<HashMap<String, BundledEntityInstances>>,
     pub sky_state: ArcSwap<SkyState>,
@@ -198,26 +338,25 @@ pub struct Scene {
 
 impl Scene {
     pub fn new(wm: &WmRenderer, framebuffer_size: wgpu::Extent3d) -> Self {
-        let indirect_buffer = wm.gpu.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: 4 * 5 * 10000,
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::INDIRECT,
-            mapped_at_creation: false,
-        });
-        let buffer_size = 100000000u64;
+        let limits = wm.gpu.device.limits();
+        let max_buffer_size = limits
+            .max_buffer_size
+            .min(limits.max_storage_buffer_binding_size as u64);
+        let buffer_size = INITIAL_CHUNK_BUFFER_SIZE.min(max_buffer_size);
+
         Self {
-            section_storage: RwLock::new(SectionStorage::new((buffer_size / 4) as u32)),
+            section_storage: RwLock::new(SectionStorage::new(
+                (buffer_size / 4) as u32,
+                (max_buffer_size / 4).min(u32::MAX as u64) as u32,
+            )),
             camera_section_pos: RwLock::new(ivec2(0, 0)),
-            chunk_buffer: Arc::new(BindableBuffer::new_deferred(
+            chunk_buffer: ArcSwap::new(Arc::new(BindableBuffer::new_deferred(
                 wm,
                 buffer_size,
-                wgpu::BufferUsages::COPY_DST
-                    | wgpu::BufferUsages::VERTEX
-                    | wgpu::BufferUsages::STORAGE
-                    | wgpu::BufferUsages::INDEX,
+                CHUNK_BUFFER_USAGES,
                 "ssbo",
-            )),
-            indirect_buffer: Arc::new(indirect_buffer),
+            ))),
+            section_draws: Mutex::new(SectionDraws::new(wm)),
 
             entity_instances: Default::default(),
             sky_state: Default::default(),
@@ -256,11 +395,120 @@ impl Scene {
             view_formats: &[],
         })
     }
+
+    /// Moves the chunk geometry into a new buffer of `capacity` 4 byte words. Each range of the old buffer is
+    /// copied on the GPU to where the allocator placed it, see [SectionStorage::take_relocation].
+    pub fn relocate_chunk_buffer(&self, wm: &WmRenderer, capacity: u32, moves: &[ChunkBufferMove]) {
+        let old_buffer = self.chunk_buffer.load_full();
+        let new_buffer =
+            BindableBuffer::new_deferred(wm, capacity as u64 * 4, CHUNK_BUFFER_USAGES, "ssbo");
+
+        let mut encoder = wm
+            .gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("chunk buffer relocation"),
+            });
+
+        for (from, to) in moves {
+            encoder.copy_buffer_to_buffer(
+                &old_buffer.buffer,
+                from.start as u64 * 4,
+                &new_buffer.buffer,
+                *to as u64 * 4,
+                (from.end - from.start) as u64 * 4,
+            );
+        }
+
+        wm.gpu.queue.submit([encoder.finish()]);
+        self.chunk_buffer.store(Arc::new(new_buffer));
+    }
+}
+
+fn load_blockstates(
+    resource_provider: &dyn ResourceProvider,
+    path: &ResourcePath,
+) -> Result<schemas::BlockStates, MeshBakeError> {
+    serde_json::from_str(
+        &resource_provider
+            .get_string(path)
+            .ok_or_else(|| MeshBakeError::UnresolvedResourcePath(path.clone()))?,
+    )
+    .map_err(MeshBakeError::JsonError)
+}
+
+/// Bakes every variant of a block with `bake`, using `missing` for the models which fail to bake
+fn bake_block(
+    blockstates: &schemas::BlockStates,
+    layer: RenderLayer,
+    bake: impl Fn(&ModelProperties) -> Result<ModelMesh, MeshBakeError>,
+    model_cache: &Arc<ModelCache>,
+    missing: &Arc<ModelMesh>,
+) -> (Block, Vec<MeshBakeError>) {
+    let mut errors = Vec::new();
+
+    let block = match blockstates {
+        schemas::BlockStates::Variants { variants } => {
+            let meshes: IndexMap<Vec<(String, StateValue)>, Vec<WeightedModel>> = variants
+                .iter()
+                .map(|(variant_id, variant)| {
+                    let key_iter = if !variant_id.is_empty() {
+                        variant_id
+                            .split(',')
+                            .filter_map(|kv_pair| {
+                                let mut split = kv_pair.split('=');
+                                if kv_pair.is_empty() {
+                                    return None;
+                                }
+
+                                Some((
+                                    split.next()?.to_string(),
+                                    match split.next()? {
+                                        "true" => StateValue::Bool(true),
+                                        "false" => StateValue::Bool(false),
+                                        other => StateValue::String(other.into()),
+                                    },
+                                ))
+                            })
+                            .collect::<Vec<_>>()
+                    } else {
+                        vec![]
+                    };
+
+                    (
+                        key_iter,
+                        variant
+                            .models()
+                            .iter()
+                            .map(|variation| WeightedModel {
+                                mesh: bake(variation).map(Arc::new).unwrap_or_else(|error| {
+                                    errors.push(error);
+                                    missing.clone()
+                                }),
+                                weight: variation.weight,
+                            })
+                            .collect::<Vec<WeightedModel>>(),
+                    )
+                })
+                .collect();
+
+            Block::Variants(meshes)
+        }
+        schemas::BlockStates::Multipart { cases } => Block::Multipart(Multipart {
+            cases: cases.clone(),
+            keys: RwLock::new(IndexMap::new()),
+            layer,
+            model_cache: model_cache.clone(),
+        }),
+    };
+
+    (block, errors)
 }
 
 /// Minecraft-specific state and data structures go in here
 pub struct MinecraftState {
-    pub block_manager: RwLock<BlockManager>,
+    /// Shared with the [BakeScheduler](scheduler::BakeScheduler)'s workers
+    pub block_manager: Arc<RwLock<BlockManager>>,
 
     pub entity_models: RwLock<HashMap<String, Arc<Entity>>>,
 
@@ -269,6 +517,13 @@ pub struct MinecraftState {
 
     pub animated_block_buffer: ArcSwap<Option<wgpu::Buffer>>,
     pub animated_block_bind_group: ArcSwap<Option<wgpu::BindGroup>>,
+
+    /// Used for sections baked from now on, already baked sections keep their lighting until rebaked
+    pub lighting_mode: RwLock<LightingMode>,
+    /// How many columns around a block its biome tint is blended over, 0 disables blending
+    pub biome_blend_radius: RwLock<u32>,
+    /// How many mip levels the block atlas has, applied when the atlases are created by [WmRenderer::init]
+    pub mip_levels: RwLock<u32>,
 }
 
 impl MinecraftState {
@@ -279,22 +534,167 @@ impl MinecraftState {
 
             texture_manager: TextureManager::new(wgpu_state),
 
-            block_manager: RwLock::new(BlockManager {
+            block_manager: Arc::new(RwLock::new(BlockManager {
                 blocks: IndexMap::new(),
-            }),
+                fluids: HashMap::new(),
+                biome_tints: BiomeTints::new(),
+                registry: BlockRegistry::default(),
+            })),
             resource_provider,
 
             animated_block_buffer: ArcSwap::new(Arc::new(None)),
             animated_block_bind_group: ArcSwap::new(Arc::new(None)),
+            lighting_mode: RwLock::new(LightingMode::default()),
+            biome_blend_radius: RwLock::new(DEFAULT_BIOME_BLEND_RADIUS),
+            mip_levels: RwLock::new(DEFAULT_MIP_LEVELS),
         }
     }
 
-    /// Bake blocks from their blockstates
+    /// Looks up the key of a blockstate written like vanilla does, e.g.
+    /// `minecraft:oak_stairs[facing=east,half=top,waterlogged=false]`. Blocks are named the way they were passed to
+    /// [MinecraftState::bake_blocks]. Properties which the blockstate file doesn't vary on are ignored.
+    pub fn parse_block_state(&self, state: &str) -> Result<BlockstateKey, BlockStateParseError> {
+        let parsed = ParsedBlockState::parse(state)?;
+
+        let block_manager = self.block_manager.read();
+        let atlases = self.texture_manager.atlases.read();
+
+        let (index, _, block) = block_manager
+            .blocks
+            .get_full(parsed.block)
+            .ok_or_else(|| BlockStateParseError::UnknownBlock(parsed.block.into()))?;
+
+        let (_, augment) = block
+            .get_model_by_key(
+                parsed.properties.iter().map(|(name, value)| (*name, value)),
+                &*self.resource_provider,
+                &atlases[BLOCK_ATLAS],
+                0,
+            )
+            .ok_or_else(|| BlockStateParseError::UnknownState(state.into()))?;
+
+        Ok(BlockstateKey {
+            block: index as u16,
+            augment,
+        })
+    }
+
+    /// Fills the atlases from their definitions in `atlases/*.json`, so that every sprite has a place in its atlas
+    /// before anything is baked. The block atlas comes from `minecraft:atlases/blocks.json`, and the others are named
+    /// after their definition, e.g. `minecraft:atlases/chests`. Should be called after [WmRenderer::init] and before
+    /// [MinecraftState::bake_blocks].
+    pub fn load_atlases(&self, wm: &WmRenderer) -> AtlasReport {
+        let resource_provider = &*self.resource_provider;
+        let textures = resource_provider.list_resources("textures");
+
+        let mut definitions = resource_provider
+            .list_resources("atlases")
+            .into_iter()
+            .filter(|definition| definition.path().ends_with(".json"))
+            .collect::<Vec<_>>();
+
+        if definitions.is_empty() {
+            definitions = VANILLA_ATLASES
+                .iter()
+                .map(|&name| ResourcePath::from(name).prepend("atlases/").append(".json"))
+                .collect();
+        }
+
+        let mut report = AtlasReport::new();
+
+        for definition in definitions {
+            let name = atlas_name(&definition);
+
+            let builder = match AtlasBuilder::load(resource_provider, &definition, &textures) {
+                Ok(builder) => builder,
+                Err(error) => {
+                    report.insert(name, vec![error]);
+                    continue;
+                }
+            };
+
+            let mut atlases = self.texture_manager.atlases.write();
+            let atlas = atlases
+                .entry(name.clone())
+                .or_insert_with(|| Atlas::new(&wm.gpu, true));
+
+            let errors = builder.build(atlas, resource_provider);
+            atlas.upload(wm);
+
+            if !errors.is_empty() {
+                report.insert(name, errors);
+            }
+        }
+
+        report
+    }
+
+    /// Bakes every block in the data generator's `blocks.json` report, and maps the global palette ID of each of
+    /// their states to a [BlockstateKey], see [BlockRegistry::state_key]. `layer` picks the [RenderLayer] of each
+    /// block by name.
+    pub fn load_block_registry(
+        &self,
+        wm: &WmRenderer,
+        report_path: &ResourcePath,
+        layer: impl Fn(&str) -> RenderLayer,
+    ) -> Result<BakeReport, RegistryError> {
+        let blocks = BlockRegistry::load_report(&*self.resource_provider, report_path)?;
+
+        let blockstate_paths = blocks
+            .keys()
+            .map(|name| (name, ResourcePath::from(name).blockstate_file()))
+            .collect::<Vec<_>>();
+
+        let report = self.bake_blocks(
+            wm,
+            blockstate_paths
+                .iter()
+                .map(|(name, path)| (name, path, layer(name))),
+        );
+
+        let registry = {
+            let block_manager = self.block_manager.read();
+            let atlases = self.texture_manager.atlases.read();
+
+            BlockRegistry::from_report(blocks, |name, state| {
+                let (index, _, wm_block) = block_manager.blocks.get_full(name)?;
+
+                let properties = state
+                    .properties
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), parse_state_value(value)))
+                    .collect::<Vec<_>>();
+
+                wm_block
+                    .get_model_by_key(
+                        properties.iter().map(|(name, value)| (*name, value)),
+                        &*self.resource_provider,
+                        &atlases[BLOCK_ATLAS],
+                        0,
+                    )
+                    .map(|(_, augment)| BlockstateKey {
+                        block: index as u16,
+                        augment,
+                    })
+            })
+        };
+
+        self.block_manager.write().registry = registry;
+
+        Ok(report)
+    }
+
+    /// Bake blocks from their blockstates. Each block is given the [RenderLayer] which its quads will be
+    /// baked into, e.g. [RenderLayer::Cutout] for leaves or [RenderLayer::Transparent] for stained glass.
+    ///
+    /// Models which fail to bake are replaced with [ModelMesh::missing], and a block whose blockstate file
+    /// can't be loaded renders as the missing model in every state. The errors are returned by block name.
     ///
     /// # Example
     ///
     ///```ignore
     /// # use wgpu_mc::mc::MinecraftState;
+    /// # use wgpu_mc::mc::chunk::RenderLayer;
     /// # use wgpu_mc::mc::resource::ResourcePath;
     /// # use wgpu_mc::WmRenderer;
     ///
@@ -303,88 +703,311 @@ impl MinecraftState {
     ///
     /// minecraft_state.bake_blocks(
     ///     &wm,
-    ///     [("minecraft:anvil", &ResourcePath("minecraft:blockstates/anvil.json".into()))]
+    ///     [(
+    ///         "minecraft:anvil",
+    ///         &ResourcePath("minecraft:blockstates/anvil.json".into()),
+    ///         RenderLayer::Solid,
+    ///     )]
     /// );
     /// ```
     pub fn bake_blocks<'a>(
         &self,
         wm: &WmRenderer,
-        block_states: impl IntoIterator<Item = (impl AsRef<str>, &'a ResourcePath)>,
-    ) {
+        block_states: impl IntoIterator<Item = (impl AsRef<str>, &'a ResourcePath, RenderLayer)>,
+    ) -> BakeReport {
         let mut block_manager = self.block_manager.write();
         let atlases = self.texture_manager.atlases.read();
         let block_atlas = atlases.get(BLOCK_ATLAS).unwrap();
 
-        //Figure out which block models there are
-        block_states
+        let resource_provider = &*self.resource_provider;
+        let model_cache = Arc::new(ModelCache::default());
+        let missing = Arc::new(ModelMesh::missing(block_atlas, resource_provider));
+        let mut report = BakeReport::new();
+
+        let block_states = block_states
             .into_iter()
-            .for_each(|(block_name, block_state)| {
-                let blockstates: schemas::BlockStates =
-                    serde_json::from_str(&self.resource_provider.get_string(block_state).unwrap())
-                        .unwrap();
-
-                let block = match &blockstates {
-                    schemas::BlockStates::Variants { variants } => {
-                        let meshes: IndexMap<Vec<(String, StateValue)>, Vec<Arc<ModelMesh>>> =
-                            variants
-                                .iter()
-                                .map(|(variant_id, variant)| {
-                                    let key_iter = if !variant_id.is_empty() {
-                                        variant_id
-                                            .split(',')
-                                            .filter_map(|kv_pair| {
-                                                let mut split = kv_pair.split('=');
-                                                if kv_pair.is_empty() {
-                                                    return None;
-                                                }
-
-                                                Some((
-                                                    split.next().unwrap().to_string(),
-                                                    match split.next().unwrap() {
-                                                        "true" => StateValue::Bool(true),
-                                                        "false" => StateValue::Bool(false),
-                                                        other => StateValue::String(other.into()),
-                                                    },
-                                                ))
-                                            })
-                                            .collect::<Vec<_>>()
-                                    } else {
-                                        vec![]
-                                    };
-
-                                    (
-                                        key_iter,
-                                        variant
-                                            .models()
-                                            .iter()
-                                            .map(|variation| {
-                                                Arc::new(
-                                                    ModelMesh::bake(
-                                                        std::slice::from_ref(variation),
-                                                        &*self.resource_provider,
-                                                        block_atlas,
-                                                    )
-                                                    .unwrap(),
-                                                )
-                                            })
-                                            .collect::<Vec<Arc<ModelMesh>>>(),
-                                    )
-                                })
-                                .collect();
-
-                        Block::Variants(meshes)
-                    }
-                    schemas::BlockStates::Multipart { cases } => Block::Multipart(Multipart {
-                        cases: cases.clone(),
-                        keys: RwLock::new(IndexMap::new()),
-                    }),
+            .map(|(block_name, block_state, layer)| {
+                (String::from(block_name.as_ref()), block_state, layer)
+            })
+            .collect::<Vec<_>>();
+
+        //Parse the blockstates and resolve their models first, so that the textures can be allocated in one pass
+        let blockstates = block_states
+            .par_iter()
+            .map(|(_, block_state, _)| {
+                let blockstates = load_blockstates(resource_provider, block_state);
+
+                //Models which fail to resolve are reported once they're baked
+                let resolve = |model_properties: &ModelProperties| {
+                    let _ = model_cache
+                        .resolve(&ModelCache::model_path(model_properties), resource_provider);
                 };
 
-                block_manager
-                    .blocks
-                    .insert(String::from(block_name.as_ref()), block);
+                match &blockstates {
+                    Ok(schemas::BlockStates::Variants { variants }) => variants
+                        .iter()
+                        .flat_map(|(_, variant)| variant.models())
+                        .for_each(resolve),
+                    Ok(schemas::BlockStates::Multipart { cases }) => cases
+                        .iter()
+                        .flat_map(|case| case.apply.models())
+                        .for_each(resolve),
+                    Err(_) => {}
+                }
+
+                blockstates
+            })
+            .collect::<Vec<_>>();
+
+        let mut textures = {
+            let uv_map = block_atlas.uv_map.read();
+
+            model_cache
+                .textures()
+                .into_iter()
+                .filter(|path| !uv_map.contains_key(path))
+                .collect::<Vec<_>>()
+        };
+        //Keeps the layout of the atlas the same between runs
+        textures.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let textures = textures
+            .par_iter()
+            .filter_map(|path| {
+                resource_provider
+                    .get_bytes(&path.texture_file())
+                    .map(|bytes| (path, bytes))
+            })
+            .collect::<Vec<_>>();
+
+        textures.iter().for_each(|(path, bytes)| {
+            //One at a time, so that a broken texture doesn't keep the rest out of the atlas. The error comes up
+            //again when a model using it is baked
+            let _ = block_atlas.allocate([(*path, bytes)], resource_provider);
+        });
+
+        let blocks = blockstates
+            .into_par_iter()
+            .zip(block_states.par_iter())
+            .map(|(blockstates, (_, _, layer))| match blockstates {
+                Ok(blockstates) => bake_block(
+                    &blockstates,
+                    *layer,
+                    |variation| {
+                        ModelMesh::bake(
+                            std::slice::from_ref(variation),
+                            resource_provider,
+                            block_atlas,
+                            &model_cache,
+                            *layer,
+                        )
+                    },
+                    &model_cache,
+                    &missing,
+                ),
+                //An empty variant key matches every state
+                Err(error) => (
+                    Block::Variants(IndexMap::from([(
+                        vec![],
+                        vec![WeightedModel {
+                            mesh: missing.clone(),
+                            weight: 1,
+                        }],
+                    )])),
+                    vec![error],
+                ),
+            })
+            .collect::<Vec<_>>();
+
+        block_states
+            .into_iter()
+            .zip(blocks)
+            .for_each(|((block_name, _, _), (block, errors))| {
+                block_manager.blocks.insert(block_name.clone(), block);
+
+                if !errors.is_empty() {
+                    report.insert(block_name, errors);
+                }
             });
 
+        block_manager.fluids = [(Fluid::Water, "water"), (Fluid::Lava, "lava")]
+            .into_iter()
+            .filter_map(|(fluid, name)| {
+                let still = ResourcePath::from(format!("minecraft:block/{name}_still"));
+                let flow = ResourcePath::from(format!("minecraft:block/{name}_flow"));
+
+                let textures = [&still, &flow]
+                    .into_iter()
+                    .filter(|path| !block_atlas.uv_map.read().contains_key(*path))
+                    .map(|path| {
+                        self.resource_provider
+                            .get_bytes(&path.texture_file())
+                            .map(|bytes| (path, bytes))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+
+                block_atlas
+                    .allocate(
+                        textures.iter().map(|(path, bytes)| (*path, bytes)),
+                        &*self.resource_provider,
+                    )
+                    .ok()?;
+
+                let uv_map = block_atlas.uv_map.read();
+                let animations = block_atlas.animated_texture_offsets.read();
+
+                let sprite = |path: &ResourcePath| {
+                    Some(FluidSprite {
+                        uv: *uv_map.get(path)?,
+                        animation: animations.get(path).copied().unwrap_or(0),
+                    })
+                };
+
+                Some((
+                    fluid,
+                    FluidSprites {
+                        still: sprite(&still)?,
+                        flow: sprite(&flow)?,
+                    },
+                ))
+            })
+            .collect();
+
+        let block_manager = &mut *block_manager;
+        block_manager.biome_tints.reload(&*self.resource_provider);
+        block_manager
+            .biome_tints
+            .index_blocks(block_manager.blocks.keys());
+
         block_atlas.upload(wm);
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::ivec3;
+
+    use super::block::load_model;
+    use super::*;
+
+    fn mesh() -> ModelMesh {
+        ModelMesh {
+            north: Vec::new(),
+            south: Vec::new(),
+            west: Vec::new(),
+            east: Vec::new(),
+            up: Vec::new(),
+            down: Vec::new(),
+            any: Vec::new(),
+            cull: 0,
+            full_cube: false,
+            layer: RenderLayer::Solid,
+        }
+    }
+
+    fn model(weight: u32) -> WeightedModel {
+        WeightedModel {
+            mesh: Arc::new(mesh()),
+            weight,
+        }
+    }
+
+    struct Files(HashMap<&'static str, &'static str>);
+
+    impl ResourceProvider for Files {
+        fn get_bytes(&self, id: &ResourcePath) -> Option<Vec<u8>> {
+            self.0.get(&id.0[..]).map(|file| file.as_bytes().to_vec())
+        }
+    }
+
+    //Expected values were taken from vanilla's MathHelper.hashCode and java.util.Random
+
+    #[test]
+    fn position_seed_matches_vanilla() {
+        assert_eq!(position_seed(IVec3::ZERO), 0);
+        assert_eq!(position_seed(ivec3(1, 64, -3)), -62687207132611);
+        assert_eq!(position_seed(ivec3(-100, 70, 250)), -135864797935757);
+    }
+
+    #[test]
+    fn next_long_matches_vanilla() {
+        assert_eq!(java_random_next_long(42), -5025562857975149833);
+        assert_eq!(java_random_next_long(0), -4962768465676381896);
+    }
+
+    #[test]
+    fn weighted_pick_matches_vanilla() {
+        let models = [model(1), model(1)];
+
+        let picked = |pos| pick_weighted_model(&models, position_seed(pos)).unwrap();
+
+        assert!(Arc::ptr_eq(picked(ivec3(0, 64, 3)), &models[1].mesh));
+        assert!(Arc::ptr_eq(picked(ivec3(3, 64, 3)), &models[0].mesh));
+    }
+
+    #[test]
+    fn broken_models_bake_as_missing() {
+        let files = Files(HashMap::from([
+            (
+                "test:blockstates/block.json",
+                r#"{"variants": {
+                    "facing=north": {"model": "test:block/fine"},
+                    "facing=east": {"model": "test:block/orphan"},
+                    "facing=south": {"model": "test:block/untextured"}
+                }}"#,
+            ),
+            (
+                "test:models/block/fine.json",
+                r#"{"textures": {"all": "test:block/stone"}}"#,
+            ),
+            (
+                "test:models/block/orphan.json",
+                r#"{"parent": "test:block/nowhere"}"#,
+            ),
+            (
+                "test:models/block/untextured.json",
+                r#"{"textures": {"all": "test:block/nothing"}}"#,
+            ),
+            ("test:textures/block/stone.png", ""),
+        ]));
+
+        let blockstates = load_blockstates(&files, &"test:blockstates/block.json".into()).unwrap();
+        let model_cache = Arc::new(ModelCache::default());
+        let missing = Arc::new(mesh());
+
+        let (block, errors) = bake_block(
+            &blockstates,
+            RenderLayer::Solid,
+            |variation| load_model(variation, &files, &model_cache, |_| false).map(|_| mesh()),
+            &model_cache,
+            &missing,
+        );
+
+        let Block::Variants(variants) = block else {
+            panic!("expected variants");
+        };
+        let is_missing = |facing: &str| {
+            let key = vec![("facing".into(), StateValue::String(facing.into()))];
+            Arc::ptr_eq(&variants[&key][0].mesh, &missing)
+        };
+
+        assert!(!is_missing("north"));
+        assert!(is_missing("east"));
+        assert!(is_missing("south"));
+
+        let mut errors = errors
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+        errors.sort();
+        assert_eq!(
+            errors,
+            [
+                "couldn't find test:models/block/nowhere.json",
+                "couldn't find the texture test:block/nothing",
+            ]
+        );
     }
 }
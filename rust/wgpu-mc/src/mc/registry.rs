@@ -0,0 +1,266 @@
+//! The vanilla block registry, read from the `reports/blocks.json` which the data generator writes with
+//! `java -DbundlerMainClass=net.minecraft.data.Main -jar server.jar --reports`
+
+use std::fmt::{Display, Formatter};
+
+use linked_hash_map::LinkedHashMap;
+use serde_derive::Deserialize;
+
+use crate::mc::block::BlockstateKey;
+use crate::mc::resource::{ResourcePath, ResourceProvider};
+
+/// A block in `blocks.json`
+#[derive(Debug, Deserialize)]
+pub struct BlockReport {
+    /// The values each property can take, empty if the block has no properties
+    #[serde(default)]
+    pub properties: LinkedHashMap<String, Vec<String>>,
+    pub states: Vec<StateReport>,
+}
+
+impl BlockReport {
+    pub fn default_state(&self) -> Option<&StateReport> {
+        self.states
+            .iter()
+            .find(|state| state.default)
+            .or(self.states.first())
+    }
+}
+
+/// A blockstate in `blocks.json`
+#[derive(Debug, Deserialize)]
+pub struct StateReport {
+    /// The ID of the state in the global palette
+    pub id: u32,
+    #[serde(default)]
+    pub properties: LinkedHashMap<String, String>,
+    #[serde(default)]
+    pub default: bool,
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    UnresolvedResourcePath(ResourcePath),
+    JsonError(serde_json::Error),
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnresolvedResourcePath(path) => {
+                write!(f, "couldn't find the block report {path}")
+            }
+            Self::JsonError(error) => write!(f, "invalid block report: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::UnresolvedResourcePath(_) => None,
+            Self::JsonError(error) => Some(error),
+        }
+    }
+}
+
+/// Every block with its properties and states, along with the [BlockstateKey] of each global palette ID. Filled in
+/// by [MinecraftState::load_block_registry](crate::mc::MinecraftState::load_block_registry)
+#[derive(Debug, Default)]
+pub struct BlockRegistry {
+    /// Blocks by name, in the order they were registered
+    pub blocks: LinkedHashMap<String, BlockReport>,
+    state_keys: Vec<Option<BlockstateKey>>,
+}
+
+impl BlockRegistry {
+    pub fn load_report(
+        resource_provider: &dyn ResourceProvider,
+        path: &ResourcePath,
+    ) -> Result<LinkedHashMap<String, BlockReport>, RegistryError> {
+        serde_json::from_str(
+            &resource_provider
+                .get_string(path)
+                .ok_or_else(|| RegistryError::UnresolvedResourcePath(path.clone()))?,
+        )
+        .map_err(RegistryError::JsonError)
+    }
+
+    pub fn new(
+        blocks: LinkedHashMap<String, BlockReport>,
+        state_keys: Vec<Option<BlockstateKey>>,
+    ) -> Self {
+        Self { blocks, state_keys }
+    }
+
+    /// Maps the global palette ID of every state in the report to the key `state_key` gives it
+    pub fn from_report(
+        blocks: LinkedHashMap<String, BlockReport>,
+        mut state_key: impl FnMut(&str, &StateReport) -> Option<BlockstateKey>,
+    ) -> Self {
+        let mut state_keys = Vec::new();
+
+        for (name, block) in blocks.iter() {
+            for state in &block.states {
+                let id = state.id as usize;
+
+                if id >= state_keys.len() {
+                    state_keys.resize(id + 1, None);
+                }
+
+                state_keys[id] = state_key(name, state);
+            }
+        }
+
+        Self::new(blocks, state_keys)
+    }
+
+    /// The key of a global palette ID, or `None` if the ID is unknown or its block has no model
+    pub fn state_key(&self, id: u32) -> Option<BlockstateKey> {
+        self.state_keys.get(id as usize).copied().flatten()
+    }
+
+    /// The number of global palette IDs
+    pub fn state_count(&self) -> usize {
+        self.state_keys.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Cut down from the report of a vanilla server
+    const BLOCKS_JSON: &str = r#"{
+        "minecraft:air": {
+            "definition": {"type": "minecraft:air", "properties": {}},
+            "states": [{"default": true, "id": 0}]
+        },
+        "minecraft:stone": {
+            "definition": {"type": "minecraft:block", "properties": {}},
+            "states": [{"default": true, "id": 1}]
+        },
+        "minecraft:oak_slab": {
+            "definition": {"type": "minecraft:slab", "properties": {}},
+            "properties": {
+                "type": ["top", "bottom", "double"],
+                "waterlogged": ["true", "false"]
+            },
+            "states": [
+                {"id": 11, "properties": {"type": "top", "waterlogged": "true"}},
+                {"id": 12, "properties": {"type": "top", "waterlogged": "false"}},
+                {"id": 13, "properties": {"type": "bottom", "waterlogged": "true"}},
+                {"default": true, "id": 14, "properties": {"type": "bottom", "waterlogged": "false"}},
+                {"id": 15, "properties": {"type": "double", "waterlogged": "true"}},
+                {"id": 16, "properties": {"type": "double", "waterlogged": "false"}}
+            ]
+        }
+    }"#;
+
+    struct Report;
+
+    impl ResourceProvider for Report {
+        fn get_bytes(&self, id: &ResourcePath) -> Option<Vec<u8>> {
+            (id.0 == "minecraft:reports/blocks.json").then(|| BLOCKS_JSON.as_bytes().to_vec())
+        }
+    }
+
+    fn load() -> LinkedHashMap<String, BlockReport> {
+        BlockRegistry::load_report(&Report, &"minecraft:reports/blocks.json".into()).unwrap()
+    }
+
+    #[test]
+    fn parses_the_report() {
+        let blocks = load();
+
+        assert_eq!(
+            blocks.keys().collect::<Vec<_>>(),
+            ["minecraft:air", "minecraft:stone", "minecraft:oak_slab"]
+        );
+
+        let slab = &blocks["minecraft:oak_slab"];
+        assert_eq!(
+            slab.properties.keys().collect::<Vec<_>>(),
+            ["type", "waterlogged"]
+        );
+        assert_eq!(slab.properties["type"], ["top", "bottom", "double"]);
+        assert_eq!(slab.states.len(), 6);
+        assert_eq!(slab.states[1].properties["waterlogged"], "false");
+
+        assert!(blocks["minecraft:stone"].properties.is_empty());
+        assert!(blocks["minecraft:stone"].states[0].properties.is_empty());
+    }
+
+    #[test]
+    fn finds_the_default_state() {
+        let blocks = load();
+
+        let slab = blocks["minecraft:oak_slab"].default_state().unwrap();
+        assert_eq!(slab.id, 14);
+        assert_eq!(slab.properties["type"], "bottom");
+
+        assert_eq!(blocks["minecraft:stone"].default_state().unwrap().id, 1);
+
+        //Falls back to the first state if none is marked
+        let unmarked: BlockReport =
+            serde_json::from_str(r#"{"states": [{"id": 3}, {"id": 4}]}"#).unwrap();
+        assert_eq!(unmarked.default_state().unwrap().id, 3);
+    }
+
+    #[test]
+    fn maps_ids_to_keys() {
+        //Like the block manager, which has no model for air and doesn't tell waterlogged slabs apart
+        let blocks = HashMap::from([("minecraft:stone", 0), ("minecraft:oak_slab", 1)]);
+        let types = ["top", "bottom", "double"];
+
+        let registry = BlockRegistry::from_report(load(), |name, state| {
+            let augment = match state.properties.get("type") {
+                Some(slab_type) => types.iter().position(|t| t == slab_type)? as u16,
+                None => 0,
+            };
+
+            Some(BlockstateKey {
+                block: *blocks.get(name)?,
+                augment,
+            })
+        });
+
+        assert_eq!(registry.state_count(), 17);
+        assert_eq!(registry.state_key(0), None);
+        assert_eq!(registry.state_key(1), Some(BlockstateKey::from((0, 0))));
+        //IDs of blocks which aren't in the report
+        assert_eq!(registry.state_key(5), None);
+        assert_eq!(registry.state_key(11), Some(BlockstateKey::from((1, 0))));
+        assert_eq!(registry.state_key(14), Some(BlockstateKey::from((1, 1))));
+        assert_eq!(registry.state_key(16), Some(BlockstateKey::from((1, 2))));
+        assert_eq!(registry.state_key(17), None);
+    }
+
+    #[test]
+    fn reports_missing_and_invalid_files() {
+        let missing = BlockRegistry::load_report(&Report, &"minecraft:reports/items.json".into());
+        assert!(matches!(
+            missing,
+            Err(RegistryError::UnresolvedResourcePath(_))
+        ));
+        assert_eq!(
+            missing.unwrap_err().to_string(),
+            "couldn't find the block report minecraft:reports/items.json"
+        );
+
+        struct Invalid;
+
+        impl ResourceProvider for Invalid {
+            fn get_bytes(&self, _id: &ResourcePath) -> Option<Vec<u8>> {
+                Some(b"{\"minecraft:stone\": {}}".to_vec())
+            }
+        }
+
+        assert!(matches!(
+            BlockRegistry::load_report(&Invalid, &"minecraft:reports/blocks.json".into()),
+            Err(RegistryError::JsonError(_))
+        ));
+    }
+}
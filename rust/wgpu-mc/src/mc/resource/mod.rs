@@ -0,0 +1,230 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+pub mod pack;
+
+/// Describes a minecraft resource, like "minecraft:stone". Useful in combination with
+/// [ResourceProvider], which gets you the actual resource.
+///
+/// The string is always `namespace:path`. Constructing one directly doesn't check that, so prefer
+/// [ResourcePath::parse] or [ResourcePath::new] for anything that didn't come from vanilla.
+#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+pub struct ResourcePath(pub String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourcePathError {
+    /// Namespaces may only contain `a-z`, `0-9`, `_`, `-` and `.`
+    InvalidNamespace(String),
+    /// Paths may only contain `a-z`, `0-9`, `_`, `-`, `.` and `/`
+    InvalidPath(String),
+}
+
+impl Display for ResourcePathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidNamespace(namespace) => write!(
+                f,
+                "invalid namespace {namespace:?}, only a-z, 0-9, _, - and . are allowed"
+            ),
+            Self::InvalidPath(path) => write!(
+                f,
+                "invalid path {path:?}, only a-z, 0-9, _, -, . and / are allowed"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResourcePathError {}
+
+pub const DEFAULT_NAMESPACE: &str = "minecraft";
+
+fn is_valid_namespace(namespace: &str) -> bool {
+    namespace
+        .bytes()
+        .all(|c| matches!(c, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.'))
+}
+
+fn is_valid_path(path: &str) -> bool {
+    path.bytes()
+        .all(|c| matches!(c, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b'/'))
+}
+
+/// Splits on the first `:` like vanilla's `Identifier`, so that `stone` and `:stone` are both `minecraft:stone`
+fn split(string: &str) -> (&str, &str) {
+    match string.split_once(':') {
+        Some((namespace, path)) if !namespace.is_empty() => (namespace, path),
+        Some((_, path)) => (DEFAULT_NAMESPACE, path),
+        None => (DEFAULT_NAMESPACE, string),
+    }
+}
+
+impl ResourcePath {
+    pub fn new(namespace: &str, path: &str) -> Result<Self, ResourcePathError> {
+        if !is_valid_namespace(namespace) {
+            return Err(ResourcePathError::InvalidNamespace(namespace.into()));
+        }
+
+        if !is_valid_path(path) {
+            return Err(ResourcePathError::InvalidPath(path.into()));
+        }
+
+        Ok(Self(format!("{namespace}:{path}")))
+    }
+
+    /// Parses `namespace:path`, where the namespace defaults to `minecraft` if it's left out
+    pub fn parse(string: &str) -> Result<Self, ResourcePathError> {
+        let (namespace, path) = split(string);
+
+        Self::new(namespace, path)
+    }
+
+    pub fn namespace(&self) -> &str {
+        split(&self.0).0
+    }
+
+    pub fn path(&self) -> &str {
+        split(&self.0).1
+    }
+
+    pub fn append(&self, a: &str) -> Self {
+        Self(format!("{}{}", self.0, a))
+    }
+
+    /// Prepends to the path, e.g. `minecraft:block/stone` to `minecraft:textures/block/stone`
+    pub fn prepend(&self, a: &str) -> Self {
+        Self(format!("{}:{}{}", self.namespace(), a, self.path()))
+    }
+
+    /// The JSON file of a model, e.g. `minecraft:block/stone` to `minecraft:models/block/stone.json`
+    pub fn model_file(&self) -> Self {
+        self.prepend("models/").append(".json")
+    }
+
+    /// The image of a texture, e.g. `minecraft:block/stone` to `minecraft:textures/block/stone.png`
+    pub fn texture_file(&self) -> Self {
+        self.prepend("textures/").append(".png")
+    }
+
+    /// The `.mcmeta` next to the image of a texture, which describes its animation
+    pub fn texture_meta_file(&self) -> Self {
+        self.texture_file().append(".mcmeta")
+    }
+
+    /// The blockstate definition of a block, e.g. `minecraft:stone` to `minecraft:blockstates/stone.json`
+    pub fn blockstate_file(&self) -> Self {
+        self.prepend("blockstates/").append(".json")
+    }
+}
+
+impl Display for ResourcePath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for ResourcePath {
+    type Err = ResourcePathError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Self::parse(string)
+    }
+}
+
+/// Adds the default namespace if it's missing, but doesn't validate anything. Use [ResourcePath::parse] for
+/// untrusted input.
+impl From<&str> for ResourcePath {
+    fn from(string: &str) -> Self {
+        let (namespace, path) = split(string);
+
+        Self(format!("{namespace}:{path}"))
+    }
+}
+
+impl From<&String> for ResourcePath {
+    fn from(string: &String) -> Self {
+        Self::from(&string[..])
+    }
+}
+
+impl From<String> for ResourcePath {
+    fn from(string: String) -> Self {
+        Self::from(&string[..])
+    }
+}
+
+impl From<(&str, &str)> for ResourcePath {
+    fn from((namespace, path): (&str, &str)) -> Self {
+        Self(format!("{namespace}:{path}"))
+    }
+}
+
+/// Used to easily get resources from a [ResourcePath].
+pub trait ResourceProvider: Send + Sync {
+    fn get_bytes(&self, id: &ResourcePath) -> Option<Vec<u8>>;
+
+    fn get_string(&self, id: &ResourcePath) -> Option<String> {
+        String::from_utf8(self.get_bytes(id)?).ok()
+    }
+
+    /// Every copy of a resource, lowest priority first. Vanilla merges some resources across packs instead of
+    /// overriding them, like the atlas definitions in `atlases/`.
+    fn get_all_bytes(&self, id: &ResourcePath) -> Vec<Vec<u8>> {
+        self.get_bytes(id).into_iter().collect()
+    }
+
+    /// Every resource under `directory` in every namespace, e.g. `textures` lists
+    /// `minecraft:textures/block/stone.png`. Providers which can't list their resources return nothing.
+    fn list_resources(&self, _directory: &str) -> Vec<ResourcePath> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_namespaces() {
+        let stone = ResourcePath::parse("stone").unwrap();
+        assert_eq!(stone.namespace(), "minecraft");
+        assert_eq!(stone.path(), "stone");
+
+        assert_eq!(ResourcePath::parse(":stone").unwrap(), stone);
+        assert_eq!(
+            ResourcePath::parse("wgpu_mc:shaders/terrain.wgsl")
+                .unwrap()
+                .namespace(),
+            "wgpu_mc"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_characters() {
+        assert_eq!(
+            ResourcePath::parse("Minecraft:stone"),
+            Err(ResourcePathError::InvalidNamespace("Minecraft".into()))
+        );
+        assert_eq!(
+            ResourcePath::parse("minecraft:block:stone"),
+            Err(ResourcePathError::InvalidPath("block:stone".into()))
+        );
+        assert_eq!(
+            ResourcePath::parse("Minecraft:stone")
+                .unwrap_err()
+                .to_string(),
+            r#"invalid namespace "Minecraft", only a-z, 0-9, _, - and . are allowed"#
+        );
+    }
+
+    #[test]
+    fn builds_file_paths() {
+        let stone = ResourcePath::from("block/stone");
+
+        assert_eq!(stone.model_file().0, "minecraft:models/block/stone.json");
+        assert_eq!(stone.texture_file().0, "minecraft:textures/block/stone.png");
+        assert_eq!(
+            stone.texture_meta_file().0,
+            "minecraft:textures/block/stone.png.mcmeta"
+        );
+    }
+}
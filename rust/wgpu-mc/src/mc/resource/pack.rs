@@ -0,0 +1,718 @@
+//! Resource packs, read either from a directory or a zip file such as `client.jar`, and stacked in priority order
+//! the same way vanilla does.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+use parking_lot::Mutex;
+use regex::Regex;
+use serde_derive::Deserialize;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::mc::resource::{ResourcePath, ResourceProvider};
+
+#[derive(Debug)]
+pub enum PackError {
+    Io(std::io::Error),
+    Zip(ZipError),
+    /// The `pack.mcmeta` isn't valid json or doesn't match the expected format
+    InvalidMetadata(serde_json::Error),
+    /// One of the patterns in the `filter` block isn't a valid regex
+    InvalidFilter(regex::Error),
+}
+
+impl From<std::io::Error> for PackError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ZipError> for PackError {
+    fn from(error: ZipError) -> Self {
+        Self::Zip(error)
+    }
+}
+
+/// Where the files of a [ResourcePack] come from. Paths are relative to the root of the pack and always use `/`,
+/// e.g. `assets/minecraft/textures/block/stone.png`.
+pub trait PackSource: Send + Sync {
+    fn read(&self, path: &str) -> Option<Vec<u8>>;
+
+    /// Every file beneath `directory`, recursively
+    fn list(&self, directory: &str) -> Vec<String>;
+}
+
+/// Whether a path stays inside the pack
+fn is_contained(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// A pack which has been extracted to a directory
+#[derive(Debug)]
+pub struct DirectoryPack {
+    root: PathBuf,
+}
+
+impl DirectoryPack {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl PackSource for DirectoryPack {
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        let path = Path::new(path);
+
+        // Don't let a resource path escape the pack
+        if !is_contained(path) {
+            return None;
+        }
+
+        fs::read(self.root.join(path)).ok()
+    }
+
+    fn list(&self, directory: &str) -> Vec<String> {
+        fn walk(directory: &Path, relative: &str, files: &mut Vec<String>) {
+            let Ok(entries) = fs::read_dir(directory) else {
+                return;
+            };
+
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let Some(name) = name.to_str() else {
+                    continue;
+                };
+
+                let relative = format!("{relative}/{name}");
+
+                match entry.file_type() {
+                    Ok(file_type) if file_type.is_dir() => walk(&entry.path(), &relative, files),
+                    Ok(_) => files.push(relative),
+                    Err(_) => {}
+                }
+            }
+        }
+
+        let mut files = Vec::new();
+
+        if is_contained(Path::new(directory)) {
+            walk(&self.root.join(directory), directory, &mut files);
+        }
+
+        files
+    }
+}
+
+/// A pack read straight out of a zip file, which includes `client.jar`
+pub struct ZipPack {
+    archive: Mutex<ZipArchive<BufReader<File>>>,
+}
+
+impl ZipPack {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PackError> {
+        let file = BufReader::new(File::open(path)?);
+
+        Ok(Self {
+            archive: Mutex::new(ZipArchive::new(file)?),
+        })
+    }
+}
+
+impl PackSource for ZipPack {
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        let mut archive = self.archive.lock();
+        let mut file = archive.by_name(path).ok()?;
+
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes).ok()?;
+
+        Some(bytes)
+    }
+
+    fn list(&self, directory: &str) -> Vec<String> {
+        let prefix = format!("{directory}/");
+
+        self.archive
+            .lock()
+            .file_names()
+            .filter(|name| name.starts_with(&prefix) && !name.ends_with('/'))
+            .map(String::from)
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PackMetadata {
+    filter: Option<FilterSection>,
+    overlays: Option<OverlaySection>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FilterSection {
+    block: Vec<FilterPattern>,
+}
+
+/// Matches resources by regexes on their namespace and path, as written in `pack.mcmeta` filters and atlas `filter`
+/// sources
+#[derive(Debug, Deserialize)]
+pub struct FilterPattern {
+    pub namespace: Option<String>,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OverlaySection {
+    entries: Vec<OverlayEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OverlayEntry {
+    formats: PackFormats,
+    directory: String,
+}
+
+/// The pack formats an overlay applies to, which may be written as a single format, `[min, max]` or
+/// `{ "min_inclusive": min, "max_inclusive": max }`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PackFormats {
+    Single(u32),
+    Range([u32; 2]),
+    Bounds {
+        min_inclusive: u32,
+        max_inclusive: u32,
+    },
+}
+
+impl PackFormats {
+    fn contains(&self, pack_format: u32) -> bool {
+        let (min, max) = match *self {
+            PackFormats::Single(format) => (format, format),
+            PackFormats::Range([min, max]) => (min, max),
+            PackFormats::Bounds {
+                min_inclusive,
+                max_inclusive,
+            } => (min_inclusive, max_inclusive),
+        };
+
+        (min..=max).contains(&pack_format)
+    }
+}
+
+/// An entry of a `filter` block. A missing pattern matches everything, and patterns have to match the whole
+/// namespace or path, like Java's `Matcher::matches`.
+#[derive(Debug)]
+pub(crate) struct ResourceFilter {
+    namespace: Option<Regex>,
+    path: Option<Regex>,
+}
+
+impl ResourceFilter {
+    pub(crate) fn new(pattern: &FilterPattern) -> Result<Self, regex::Error> {
+        let compile = |pattern: &Option<String>| {
+            pattern
+                .as_ref()
+                .map(|pattern| Regex::new(&format!("^(?:{pattern})$")))
+                .transpose()
+        };
+
+        Ok(Self {
+            namespace: compile(&pattern.namespace)?,
+            path: compile(&pattern.path)?,
+        })
+    }
+
+    pub(crate) fn matches(&self, namespace: &str, path: &str) -> bool {
+        self.namespace
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(namespace))
+            && self.path.as_ref().is_none_or(|regex| regex.is_match(path))
+    }
+}
+
+/// A [PackSource] along with the overlays and filters from its `pack.mcmeta`. A pack without a `pack.mcmeta` has
+/// neither.
+pub struct ResourcePack {
+    source: Box<dyn PackSource>,
+    /// The overlay directories which apply to the pack format, highest priority first
+    overlays: Vec<String>,
+    filters: Vec<ResourceFilter>,
+}
+
+impl ResourcePack {
+    /// `pack_format` is the resource pack format of the game version being rendered, which decides which overlays
+    /// are used.
+    pub fn new(source: impl PackSource + 'static, pack_format: u32) -> Result<Self, PackError> {
+        let metadata = match source.read("pack.mcmeta") {
+            Some(bytes) => serde_json::from_slice::<PackMetadata>(&bytes)
+                .map_err(PackError::InvalidMetadata)?,
+            None => PackMetadata::default(),
+        };
+
+        let overlays = metadata
+            .overlays
+            .map(|overlays| {
+                overlays
+                    .entries
+                    .into_iter()
+                    .rev()
+                    .filter(|entry| entry.formats.contains(pack_format))
+                    .map(|entry| entry.directory)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let filters = metadata
+            .filter
+            .map(|filter| {
+                filter
+                    .block
+                    .iter()
+                    .map(ResourceFilter::new)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(PackError::InvalidFilter)
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Self {
+            source: Box::new(source),
+            overlays,
+            filters,
+        })
+    }
+
+    fn read(&self, namespace: &str, path: &str) -> Option<Vec<u8>> {
+        let path = format!("assets/{namespace}/{path}");
+
+        self.overlays
+            .iter()
+            .find_map(|overlay| self.source.read(&format!("{overlay}/{path}")))
+            .or_else(|| self.source.read(&path))
+    }
+
+    /// The `(namespace, path)` of every resource under `directory`, in the pack itself and its overlays
+    fn list(&self, directory: &str) -> Vec<(String, String)> {
+        let prefix = format!("{directory}/");
+
+        self.overlays
+            .iter()
+            .map(|overlay| format!("{overlay}/assets"))
+            .chain(["assets".into()])
+            .flat_map(|assets| {
+                let start = assets.len() + 1;
+
+                self.source
+                    .list(&assets)
+                    .into_iter()
+                    .filter_map(|file| {
+                        let (namespace, path) = file[start..].split_once('/')?;
+
+                        path.starts_with(&prefix)
+                            .then(|| (namespace.into(), path.into()))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Whether this pack's `filter` hides the resource in the packs below it
+    fn filters(&self, namespace: &str, path: &str) -> bool {
+        self.filters
+            .iter()
+            .any(|filter| filter.matches(namespace, path))
+    }
+}
+
+/// Resource packs in priority order, where later packs override earlier ones. Vanilla assets should be pushed
+/// first, e.g. from a [ZipPack] of `client.jar`.
+#[derive(Default)]
+pub struct ResourcePackStack {
+    packs: Vec<ResourcePack>,
+}
+
+impl ResourcePackStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pack above every pack already in the stack
+    pub fn push(&mut self, pack: ResourcePack) {
+        self.packs.push(pack);
+    }
+
+    pub fn with(mut self, pack: ResourcePack) -> Self {
+        self.push(pack);
+        self
+    }
+}
+
+impl ResourceProvider for ResourcePackStack {
+    fn get_bytes(&self, id: &ResourcePath) -> Option<Vec<u8>> {
+        let (namespace, path) = (id.namespace(), id.path());
+
+        for pack in self.packs.iter().rev() {
+            if let Some(bytes) = pack.read(namespace, path) {
+                return Some(bytes);
+            }
+
+            if pack.filters(namespace, path) {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    fn get_all_bytes(&self, id: &ResourcePath) -> Vec<Vec<u8>> {
+        let (namespace, path) = (id.namespace(), id.path());
+        let mut copies = Vec::new();
+
+        for pack in self.packs.iter().rev() {
+            copies.extend(pack.read(namespace, path));
+
+            if pack.filters(namespace, path) {
+                break;
+            }
+        }
+
+        copies.reverse();
+        copies
+    }
+
+    fn list_resources(&self, directory: &str) -> Vec<ResourcePath> {
+        let mut resources = BTreeSet::new();
+
+        for pack in &self.packs {
+            resources.retain(|(namespace, path): &(String, String)| !pack.filters(namespace, path));
+            resources.extend(pack.list(directory));
+        }
+
+        resources
+            .into_iter()
+            .map(|(namespace, path)| ResourcePath::from((&namespace[..], &path[..])))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    const PACK_FORMAT: u32 = 40;
+
+    /// A pack held in memory
+    struct Files(HashMap<String, Vec<u8>>);
+
+    impl PackSource for Files {
+        fn read(&self, path: &str) -> Option<Vec<u8>> {
+            self.0.get(path).cloned()
+        }
+
+        fn list(&self, directory: &str) -> Vec<String> {
+            let prefix = format!("{directory}/");
+
+            self.0
+                .keys()
+                .filter(|path| path.starts_with(&prefix))
+                .cloned()
+                .collect()
+        }
+    }
+
+    fn files(files: &[(&str, &str)]) -> Files {
+        Files(
+            files
+                .iter()
+                .map(|(path, contents)| (path.to_string(), contents.as_bytes().to_vec()))
+                .collect(),
+        )
+    }
+
+    fn pack(contents: &[(&str, &str)]) -> ResourcePack {
+        ResourcePack::new(files(contents), PACK_FORMAT).unwrap()
+    }
+
+    fn get(stack: &ResourcePackStack, id: &str) -> Option<String> {
+        stack.get_string(&id.into())
+    }
+
+    fn list(stack: &ResourcePackStack, directory: &str) -> Vec<String> {
+        stack
+            .list_resources(directory)
+            .into_iter()
+            .map(|path| path.0)
+            .collect()
+    }
+
+    #[test]
+    fn later_packs_override_earlier_ones() {
+        let stack = ResourcePackStack::new()
+            .with(pack(&[
+                ("assets/minecraft/textures/stone.png", "vanilla stone"),
+                ("assets/minecraft/textures/dirt.png", "vanilla dirt"),
+                ("assets/minecraft/atlases/blocks.json", "vanilla atlas"),
+            ]))
+            .with(pack(&[
+                ("assets/minecraft/textures/stone.png", "pack stone"),
+                ("assets/minecraft/atlases/blocks.json", "pack atlas"),
+                ("assets/modid/textures/gear.png", "pack gear"),
+            ]));
+
+        assert_eq!(
+            get(&stack, "textures/stone.png").as_deref(),
+            Some("pack stone")
+        );
+        assert_eq!(
+            get(&stack, "textures/dirt.png").as_deref(),
+            Some("vanilla dirt")
+        );
+        assert_eq!(
+            get(&stack, "modid:textures/gear.png").as_deref(),
+            Some("pack gear")
+        );
+        assert_eq!(get(&stack, "textures/gear.png"), None);
+
+        //Lowest priority first, so that later packs can add to what's already there
+        assert_eq!(
+            stack.get_all_bytes(&"atlases/blocks.json".into()),
+            [b"vanilla atlas".to_vec(), b"pack atlas".to_vec()]
+        );
+
+        assert_eq!(
+            list(&stack, "textures"),
+            [
+                "minecraft:textures/dirt.png",
+                "minecraft:textures/stone.png",
+                "modid:textures/gear.png"
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_hide_resources_in_packs_below() {
+        let stack = ResourcePackStack::new()
+            .with(pack(&[
+                ("assets/minecraft/textures/block/stone.png", "vanilla stone"),
+                ("assets/minecraft/textures/block/dirt.png", "vanilla dirt"),
+                ("assets/minecraft/textures/item/apple.png", "vanilla apple"),
+                ("assets/modid/textures/block/gear.png", "mod gear"),
+            ]))
+            .with(pack(&[
+                (
+                    "pack.mcmeta",
+                    r#"{"filter": {"block": [{"namespace": "minecraft", "path": "textures/block/.*"}]}}"#,
+                ),
+                ("assets/minecraft/textures/block/dirt.png", "pack dirt"),
+            ]));
+
+        assert_eq!(get(&stack, "textures/block/stone.png"), None);
+        assert_eq!(
+            get(&stack, "textures/block/dirt.png").as_deref(),
+            Some("pack dirt")
+        );
+        assert_eq!(
+            get(&stack, "textures/item/apple.png").as_deref(),
+            Some("vanilla apple")
+        );
+        assert_eq!(
+            get(&stack, "modid:textures/block/gear.png").as_deref(),
+            Some("mod gear")
+        );
+        //The filter doesn't hide the pack's own resources
+        assert_eq!(
+            stack.get_all_bytes(&"textures/block/dirt.png".into()),
+            [b"pack dirt".to_vec()]
+        );
+        assert_eq!(
+            list(&stack, "textures"),
+            [
+                "minecraft:textures/block/dirt.png",
+                "minecraft:textures/item/apple.png",
+                "modid:textures/block/gear.png"
+            ]
+        );
+
+        //Packs above the filter aren't affected by it
+        let stack = stack.with(pack(&[(
+            "assets/minecraft/textures/block/stone.png",
+            "top stone",
+        )]));
+        assert_eq!(
+            get(&stack, "textures/block/stone.png").as_deref(),
+            Some("top stone")
+        );
+    }
+
+    #[test]
+    fn filter_patterns_match_whole_names() {
+        let filter = |namespace: Option<&str>, path: Option<&str>| {
+            ResourceFilter::new(&FilterPattern {
+                namespace: namespace.map(String::from),
+                path: path.map(String::from),
+            })
+            .unwrap()
+        };
+
+        assert!(!filter(None, Some("block")).matches("minecraft", "textures/block/stone.png"));
+        assert!(filter(None, Some(".*block.*")).matches("minecraft", "textures/block/stone.png"));
+        assert!(filter(Some("mine"), None).matches("mine", "anything"));
+        assert!(!filter(Some("mine"), None).matches("minecraft", "anything"));
+        assert!(filter(None, None).matches("modid", "anything"));
+    }
+
+    #[test]
+    fn overlays_apply_by_pack_format() {
+        let contents = [
+            (
+                "pack.mcmeta",
+                r#"{
+                    "overlays": {
+                        "entries": [
+                            {"formats": [30, 39], "directory": "old"},
+                            {"formats": {"min_inclusive": 40, "max_inclusive": 50}, "directory": "new"},
+                            {"formats": 40, "directory": "exact"}
+                        ]
+                    }
+                }"#,
+            ),
+            ("assets/minecraft/texts/a.txt", "base a"),
+            ("assets/minecraft/texts/b.txt", "base b"),
+            ("old/assets/minecraft/texts/a.txt", "old a"),
+            ("new/assets/minecraft/texts/a.txt", "new a"),
+            ("new/assets/minecraft/texts/b.txt", "new b"),
+            ("new/assets/minecraft/texts/c.txt", "new c"),
+            ("exact/assets/minecraft/texts/a.txt", "exact a"),
+        ];
+        let stack = |pack_format| {
+            ResourcePackStack::new().with(ResourcePack::new(files(&contents), pack_format).unwrap())
+        };
+
+        //Later entries take priority over earlier ones
+        let current = stack(40);
+        assert_eq!(get(&current, "texts/a.txt").as_deref(), Some("exact a"));
+        assert_eq!(get(&current, "texts/b.txt").as_deref(), Some("new b"));
+        assert_eq!(
+            list(&current, "texts"),
+            [
+                "minecraft:texts/a.txt",
+                "minecraft:texts/b.txt",
+                "minecraft:texts/c.txt"
+            ]
+        );
+
+        assert_eq!(get(&stack(45), "texts/a.txt").as_deref(), Some("new a"));
+
+        let old = stack(35);
+        assert_eq!(get(&old, "texts/a.txt").as_deref(), Some("old a"));
+        assert_eq!(get(&old, "texts/b.txt").as_deref(), Some("base b"));
+        assert_eq!(get(&old, "texts/c.txt"), None);
+
+        assert_eq!(get(&stack(60), "texts/a.txt").as_deref(), Some("base a"));
+    }
+
+    #[test]
+    fn rejects_invalid_metadata() {
+        assert!(matches!(
+            ResourcePack::new(files(&[("pack.mcmeta", "{")]), PACK_FORMAT),
+            Err(PackError::InvalidMetadata(_))
+        ));
+        assert!(matches!(
+            ResourcePack::new(
+                files(&[("pack.mcmeta", r#"{"filter": {"block": [{"path": "("}]}}"#)]),
+                PACK_FORMAT
+            ),
+            Err(PackError::InvalidFilter(_))
+        ));
+    }
+
+    /// A directory for a test to write a pack to, removed once the test is done
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("wgpu-mc-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn reads_directory_packs() {
+        let dir = TempDir::new("directory-pack");
+        let root = dir.0.join("pack");
+
+        fs::create_dir_all(root.join("assets/minecraft/textures/block")).unwrap();
+        fs::write(
+            root.join("assets/minecraft/textures/block/stone.png"),
+            "stone",
+        )
+        .unwrap();
+        fs::write(dir.0.join("secret.txt"), "secret").unwrap();
+
+        let pack = DirectoryPack::new(&root);
+
+        assert_eq!(
+            pack.read("assets/minecraft/textures/block/stone.png"),
+            Some(b"stone".to_vec())
+        );
+        assert_eq!(
+            pack.list("assets"),
+            ["assets/minecraft/textures/block/stone.png"]
+        );
+        //Paths can't leave the pack
+        assert_eq!(pack.read("../secret.txt"), None);
+        assert!(pack.list("..").is_empty());
+    }
+
+    #[test]
+    fn reads_zip_packs() {
+        let dir = TempDir::new("zip-pack");
+        let path = dir.0.join("pack.zip");
+
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.add_directory("assets/minecraft/textures/", SimpleFileOptions::default())
+            .unwrap();
+        zip.start_file(
+            "assets/minecraft/textures/stone.png",
+            SimpleFileOptions::default(),
+        )
+        .unwrap();
+        zip.write_all(b"stone").unwrap();
+        zip.start_file("pack.mcmeta", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(br#"{"pack": {"pack_format": 40, "description": ""}}"#)
+            .unwrap();
+        zip.finish().unwrap();
+
+        let zip = ZipPack::open(&path).unwrap();
+        assert_eq!(
+            zip.read("assets/minecraft/textures/stone.png"),
+            Some(b"stone".to_vec())
+        );
+        assert_eq!(zip.read("assets/minecraft/textures/dirt.png"), None);
+        //Directories aren't files
+        assert_eq!(zip.list("assets"), ["assets/minecraft/textures/stone.png"]);
+
+        let stack = ResourcePackStack::new().with(ResourcePack::new(zip, PACK_FORMAT).unwrap());
+        assert_eq!(get(&stack, "textures/stone.png").as_deref(), Some("stone"));
+    }
+}
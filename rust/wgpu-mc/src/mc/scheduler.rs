@@ -0,0 +1,426 @@
+//! Background section baking
+//!
+//! [BakeScheduler] bakes sections on a pool of worker threads, nearest to the camera first. Scheduling a section
+//! again before it was baked replaces the pending bake, and the result of a bake which was superseded or cancelled
+//! while it was running or waiting to be uploaded is thrown away. Finished sections are sent to
+//! [WmRenderer::chunk_update_queue] and uploaded by [WmRenderer::submit_chunk_updates], which checks each one
+//! with [BakeScheduler::take_result].
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use glam::{IVec2, IVec3, Vec3Swizzles};
+use parking_lot::{Mutex, RwLock};
+
+use crate::mc::chunk::{bake_layers, BakedSection, BlockStateProvider};
+use crate::mc::BlockManager;
+use crate::{ChunkUpdateData, WmRenderer};
+
+/// How many bytes of baked sections [WmRenderer::submit_chunk_updates] uploads per frame by default
+pub const DEFAULT_CHUNK_UPLOAD_BUDGET: usize = 16 * 1024 * 1024;
+
+/// Bakes of a higher priority run before any bake of a lower one, regardless of distance
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BakePriority {
+    /// Sections which the player is waiting on, such as after a block was placed or broken
+    High,
+    #[default]
+    Normal,
+}
+
+type BakeJob = Box<dyn FnOnce(&BlockManager) -> BakedSection + Send>;
+
+struct PendingBake {
+    ticket: u64,
+    priority: BakePriority,
+    job: BakeJob,
+}
+
+/// Heap entries are ordered by priority, then by squared horizontal distance to the camera's section
+type QueueEntry = Reverse<(BakePriority, i64, u64, [i32; 3])>;
+
+#[derive(Default)]
+struct BakeQueue {
+    /// May contain entries which were superseded or cancelled, those are skipped when popped
+    heap: BinaryHeap<QueueEntry>,
+    pending: HashMap<IVec3, PendingBake>,
+    /// The ticket of the latest bake of each section which is pending or running
+    latest: HashMap<IVec3, u64>,
+    /// How many baked sections of each section were sent and haven't been taken yet
+    sent: HashMap<IVec3, u32>,
+    /// How many of those were superseded or cancelled, and shouldn't be uploaded
+    stale: HashMap<IVec3, u32>,
+    camera_section: IVec2,
+    next_ticket: u64,
+}
+
+impl BakeQueue {
+    fn entry(&self, pos: IVec3, ticket: u64, priority: BakePriority) -> QueueEntry {
+        let distance = (pos.xz() - self.camera_section)
+            .as_i64vec2()
+            .length_squared();
+
+        Reverse((priority, distance, ticket, pos.to_array()))
+    }
+
+    fn push(&mut self, pos: IVec3, priority: BakePriority, job: BakeJob) {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+
+        let entry = self.entry(pos, ticket, priority);
+        self.heap.push(entry);
+        self.latest.insert(pos, ticket);
+        self.pending.insert(
+            pos,
+            PendingBake {
+                ticket,
+                priority,
+                job,
+            },
+        );
+        self.discard_sent(pos);
+    }
+
+    fn pop(&mut self) -> Option<(IVec3, u64, BakeJob)> {
+        while let Some(Reverse((_, _, ticket, pos))) = self.heap.pop() {
+            let pos = IVec3::from_array(pos);
+
+            if self
+                .pending
+                .get(&pos)
+                .is_some_and(|pending| pending.ticket == ticket)
+            {
+                let pending = self.pending.remove(&pos).unwrap();
+                return Some((pos, ticket, pending.job));
+            }
+        }
+
+        None
+    }
+
+    /// Whether the bake with this ticket is still wanted once it's done, in which case its result is about to be
+    /// sent
+    fn finish(&mut self, pos: IVec3, ticket: u64) -> bool {
+        if self.latest.get(&pos) != Some(&ticket) {
+            return false;
+        }
+
+        self.latest.remove(&pos);
+        *self.sent.entry(pos).or_default() += 1;
+
+        true
+    }
+
+    fn take_result(&mut self, pos: IVec3) -> bool {
+        //Not sent by the scheduler
+        let Some(sent) = self.sent.get_mut(&pos) else {
+            return true;
+        };
+
+        *sent -= 1;
+        if *sent == 0 {
+            self.sent.remove(&pos);
+        }
+
+        match self.stale.get_mut(&pos) {
+            Some(stale) => {
+                *stale -= 1;
+                if *stale == 0 {
+                    self.stale.remove(&pos);
+                }
+
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Marks every result of a section which was sent so far as stale
+    fn discard_sent(&mut self, pos: IVec3) {
+        if let Some(sent) = self.sent.get(&pos) {
+            self.stale.insert(pos, *sent);
+        }
+    }
+
+    fn cancel(&mut self, pos: IVec3) {
+        self.pending.remove(&pos);
+        self.latest.remove(&pos);
+        self.discard_sent(pos);
+    }
+
+    fn retain(&mut self, keep: impl Fn(IVec3) -> bool) {
+        self.pending.retain(|pos, _| keep(*pos));
+        self.latest.retain(|pos, _| keep(*pos));
+
+        let discarded: Vec<IVec3> = self
+            .sent
+            .keys()
+            .filter(|pos| !keep(**pos))
+            .copied()
+            .collect();
+        discarded.into_iter().for_each(|pos| self.discard_sent(pos));
+    }
+
+    fn clear(&mut self) {
+        self.heap.clear();
+        self.pending.clear();
+        self.latest.clear();
+        self.stale = self.sent.clone();
+    }
+
+    fn rebuild_heap(&mut self) {
+        self.heap = self
+            .pending
+            .iter()
+            .map(|(pos, pending)| self.entry(*pos, pending.ticket, pending.priority))
+            .collect();
+    }
+}
+
+pub struct BakeScheduler {
+    queue: Arc<Mutex<BakeQueue>>,
+    pool: rayon::ThreadPool,
+}
+
+impl BakeScheduler {
+    /// Creates a scheduler which bakes on `threads` worker threads
+    pub fn new(threads: usize) -> Self {
+        Self {
+            queue: Arc::new(Mutex::new(BakeQueue::default())),
+            pool: rayon::ThreadPoolBuilder::new()
+                .num_threads(threads.max(1))
+                .thread_name(|index| format!("wgpu-mc section baker {index}"))
+                .build()
+                .unwrap(),
+        }
+    }
+
+    /// Queues a section to be baked in the background, replacing any bake of it which hasn't started yet
+    pub fn schedule<Provider: BlockStateProvider + Send + 'static>(
+        &self,
+        wm: &WmRenderer,
+        pos: IVec3,
+        provider: Provider,
+        priority: BakePriority,
+    ) {
+        let lighting_mode = *wm.mc.lighting_mode.read();
+        let biome_blend_radius = *wm.mc.biome_blend_radius.read();
+
+        self.queue.lock().push(
+            pos,
+            priority,
+            Box::new(move |block_manager| {
+                bake_layers(
+                    pos,
+                    block_manager,
+                    &provider,
+                    lighting_mode,
+                    biome_blend_radius,
+                )
+            }),
+        );
+
+        let queue = self.queue.clone();
+        let block_manager = wm.mc.block_manager.clone();
+        let sender = wm.chunk_update_queue.0.clone();
+
+        //Each task bakes whichever section is most important by the time it runs
+        self.pool
+            .spawn(move || Self::bake_next(&queue, &block_manager, &sender));
+    }
+
+    fn bake_next(
+        queue: &Mutex<BakeQueue>,
+        block_manager: &RwLock<BlockManager>,
+        sender: &Sender<ChunkUpdateData>,
+    ) {
+        let Some((pos, ticket, job)) = queue.lock().pop() else {
+            return;
+        };
+
+        let baked_section = job(&block_manager.read());
+
+        //Holding the lock while sending keeps a newer bake of the same section from being sent first
+        let mut queue = queue.lock();
+
+        if queue.finish(pos, ticket) {
+            let _ = sender.send((pos, baked_section));
+        }
+    }
+
+    /// Bakes are ordered by their horizontal distance to this section. Cheap to call every frame.
+    pub fn set_camera_section(&self, pos: IVec2) {
+        let mut queue = self.queue.lock();
+
+        if queue.camera_section != pos {
+            queue.camera_section = pos;
+            queue.rebuild_heap();
+        }
+    }
+
+    /// Cancels the bake of a section, including one which is already running or waiting to be uploaded
+    pub fn cancel(&self, pos: IVec3) {
+        self.queue.lock().cancel(pos);
+    }
+
+    /// Cancels the bakes of every section for which `keep` returns false, e.g. once they're out of render distance
+    pub fn retain(&self, keep: impl Fn(IVec3) -> bool) {
+        self.queue.lock().retain(keep);
+    }
+
+    /// Cancels every bake
+    pub fn clear(&self) {
+        self.queue.lock().clear();
+    }
+
+    /// Whether a baked section which was just received from [WmRenderer::chunk_update_queue] should be uploaded,
+    /// which it shouldn't if its bake was superseded or cancelled after it was sent. Has to be called once for
+    /// every baked section received.
+    pub fn take_result(&self, pos: IVec3) -> bool {
+        self.queue.lock().take_result(pos)
+    }
+
+    /// The number of sections waiting to be baked
+    pub fn pending(&self) -> usize {
+        self.queue.lock().pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{ivec2, ivec3};
+
+    use super::*;
+
+    fn job() -> BakeJob {
+        Box::new(|_| BakedSection::default())
+    }
+
+    fn pop_all(queue: &mut BakeQueue) -> Vec<IVec3> {
+        std::iter::from_fn(|| queue.pop().map(|(pos, _, _)| pos)).collect()
+    }
+
+    #[test]
+    fn schedules_each_section_once() {
+        let mut queue = BakeQueue::default();
+
+        queue.push(ivec3(1, 0, 0), BakePriority::Normal, job());
+        queue.push(ivec3(1, 0, 0), BakePriority::Normal, job());
+        queue.push(ivec3(2, 0, 0), BakePriority::Normal, job());
+
+        assert_eq!(pop_all(&mut queue), [ivec3(1, 0, 0), ivec3(2, 0, 0)]);
+    }
+
+    #[test]
+    fn nearest_and_most_urgent_first() {
+        let mut queue = BakeQueue::default();
+        queue.camera_section = ivec2(10, 10);
+
+        queue.push(ivec3(10, 5, 14), BakePriority::Normal, job());
+        queue.push(ivec3(0, 0, 0), BakePriority::High, job());
+        queue.push(ivec3(11, -3, 10), BakePriority::Normal, job());
+        queue.push(ivec3(10, 0, 20), BakePriority::High, job());
+
+        assert_eq!(
+            pop_all(&mut queue),
+            [
+                ivec3(10, 0, 20),
+                ivec3(0, 0, 0),
+                ivec3(11, -3, 10),
+                ivec3(10, 5, 14)
+            ]
+        );
+    }
+
+    #[test]
+    fn camera_moving_reorders_pending_bakes() {
+        let mut queue = BakeQueue::default();
+
+        queue.push(ivec3(1, 0, 0), BakePriority::Normal, job());
+        queue.push(ivec3(8, 0, 0), BakePriority::Normal, job());
+
+        queue.camera_section = ivec2(9, 0);
+        queue.rebuild_heap();
+
+        assert_eq!(pop_all(&mut queue), [ivec3(8, 0, 0), ivec3(1, 0, 0)]);
+    }
+
+    #[test]
+    fn cancel_pending_and_running_bakes() {
+        let mut queue = BakeQueue::default();
+
+        queue.push(ivec3(1, 0, 0), BakePriority::Normal, job());
+        queue.push(ivec3(2, 0, 0), BakePriority::Normal, job());
+
+        let (pos, ticket, _) = queue.pop().unwrap();
+        assert_eq!(pos, ivec3(1, 0, 0));
+
+        queue.cancel(ivec3(1, 0, 0));
+        queue.cancel(ivec3(2, 0, 0));
+
+        assert!(!queue.finish(pos, ticket));
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn newer_bake_supersedes_running_one() {
+        let mut queue = BakeQueue::default();
+        let pos = ivec3(1, 0, 0);
+
+        queue.push(pos, BakePriority::Normal, job());
+        let (_, old, _) = queue.pop().unwrap();
+
+        queue.push(pos, BakePriority::High, job());
+        let (_, new, _) = queue.pop().unwrap();
+
+        //The older bake finishing last doesn't matter, only the newer one is sent
+        assert!(queue.finish(pos, new));
+        assert!(!queue.finish(pos, old));
+        assert!(queue.take_result(pos));
+    }
+
+    #[test]
+    fn drops_results_waiting_to_be_uploaded() {
+        let mut queue = BakeQueue::default();
+
+        for x in 0..3 {
+            queue.push(ivec3(x, 0, 0), BakePriority::Normal, job());
+            let (pos, ticket, _) = queue.pop().unwrap();
+            assert!(queue.finish(pos, ticket));
+        }
+
+        queue.cancel(ivec3(0, 0, 0));
+        queue.retain(|pos| pos.x != 1);
+
+        //Superseded while waiting, so only the newer bake is uploaded
+        queue.push(ivec3(2, 0, 0), BakePriority::Normal, job());
+        let (pos, ticket, _) = queue.pop().unwrap();
+        assert!(queue.finish(pos, ticket));
+
+        assert!(!queue.take_result(ivec3(0, 0, 0)));
+        assert!(!queue.take_result(ivec3(1, 0, 0)));
+        assert!(!queue.take_result(ivec3(2, 0, 0)));
+        assert!(queue.take_result(ivec3(2, 0, 0)));
+
+        //Sections baked elsewhere are always uploaded
+        assert!(queue.take_result(ivec3(5, 0, 0)));
+    }
+
+    #[test]
+    fn clear_drops_everything() {
+        let mut queue = BakeQueue::default();
+
+        queue.push(ivec3(0, 0, 0), BakePriority::Normal, job());
+        let (pos, ticket, _) = queue.pop().unwrap();
+        assert!(queue.finish(pos, ticket));
+        queue.push(ivec3(1, 0, 0), BakePriority::Normal, job());
+
+        queue.clear();
+
+        assert!(queue.pop().is_none());
+        assert!(!queue.take_result(pos));
+        assert!(queue.take_result(pos));
+    }
+}
@@ -0,0 +1,539 @@
+//! Biome tinting, see vanilla's `BiomeColors` and `BlockColors`
+//!
+//! Blocks such as grass and leaves are tinted with a colour taken from a colormap by the temperature and downfall
+//! of their biome, blended over the surrounding columns. Blocks without a [TintSource], or sections whose
+//! [BlockStateProvider] doesn't know about biomes, fall back to [BlockStateProvider::get_block_color].
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use glam::{ivec3, IVec3};
+
+use crate::mc::chunk::BlockStateProvider;
+use crate::mc::resource::{ResourcePath, ResourceProvider};
+
+pub const GRASS_COLORMAP: &str = "minecraft:textures/colormap/grass.png";
+pub const FOLIAGE_COLORMAP: &str = "minecraft:textures/colormap/foliage.png";
+
+/// Vanilla's default biome blend radius, which blends over 5x5 columns
+pub const DEFAULT_BIOME_BLEND_RADIUS: u32 = 2;
+
+/// The climate of a biome, as far as tinting is concerned. Colours are written like vanilla, as `0xRRGGBB`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Biome {
+    pub temperature: f32,
+    pub downfall: f32,
+    pub water_color: u32,
+    /// Replaces the grass colormap, like in badlands
+    pub grass_color: Option<u32>,
+    /// Replaces the foliage colormap, like in swamps
+    pub foliage_color: Option<u32>,
+}
+
+impl Default for Biome {
+    /// Plains
+    fn default() -> Self {
+        Self {
+            temperature: 0.8,
+            downfall: 0.4,
+            water_color: 0x3f76e4,
+            grass_color: None,
+            foliage_color: None,
+        }
+    }
+}
+
+/// Where the colour of a tinted block comes from
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TintSource {
+    Grass,
+    Foliage,
+    Water,
+    /// The same colour in every biome, like spruce leaves, as `0xRRGGBB`
+    Constant(u32),
+    /// Goes from green to yellow with the `age` of a melon or pumpkin stem
+    Stem,
+    /// Goes from dark to bright red with the `power` of redstone wire
+    RedstoneWire,
+}
+
+impl TintSource {
+    /// Turns the sources which depend on the blockstate into a constant colour, given the value of each property
+    /// of the state. Returns `None` if the state doesn't have the property the colour depends on
+    pub fn resolve(self, property: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        Some(match self {
+            Self::Stem => Self::Constant(stem_color(property("age")?.parse().ok()?)),
+            Self::RedstoneWire => {
+                Self::Constant(redstone_wire_color(property("power")?.parse().ok()?))
+            }
+            source => source,
+        })
+    }
+}
+
+/// See `BlockColors::create`
+fn stem_color(age: u32) -> u32 {
+    let age = age.min(7);
+
+    ((age * 32) << 16) | ((255 - age * 8) << 8) | (age * 4)
+}
+
+/// See `RedstoneWireBlock::COLORS`
+fn redstone_wire_color(power: u32) -> u32 {
+    let power = power.min(15) as f32 / 15.0;
+
+    let red = power * 0.6 + if power > 0.0 { 0.4 } else { 0.3 };
+    let green = (power * power * 0.7 - 0.5).clamp(0.0, 1.0);
+    let blue = (power * power * 0.6 - 0.7).clamp(0.0, 1.0);
+
+    let [red, green, blue] = [red, green, blue].map(|channel| (channel * 255.0).floor() as u32);
+
+    (red << 16) | (green << 8) | blue
+}
+
+/// The red, green and blue channels of a colour written like vanilla, as `0xRRGGBB`
+fn unpack_rgb(color: u32) -> [u32; 3] {
+    [(color >> 16) & 0xff, (color >> 8) & 0xff, color & 0xff]
+}
+
+/// Packs a colour like [BlockStateProvider::get_block_color] does, with red in the low byte as
+/// [Vertex::compressed](crate::render::pipeline::Vertex::compressed) expects
+fn pack_vertex_color([r, g, b]: [u32; 3]) -> u32 {
+    0xff000000 | r | (g << 8) | (b << 16)
+}
+
+/// The tint sources of vanilla blocks, see `BlockColors::create`. Opted into with
+/// [BiomeTints::add_vanilla_sources]
+pub const VANILLA_TINT_SOURCES: &[(&str, TintSource)] = &[
+    ("minecraft:grass_block", TintSource::Grass),
+    ("minecraft:short_grass", TintSource::Grass),
+    ("minecraft:tall_grass", TintSource::Grass),
+    ("minecraft:fern", TintSource::Grass),
+    ("minecraft:large_fern", TintSource::Grass),
+    ("minecraft:potted_fern", TintSource::Grass),
+    ("minecraft:sugar_cane", TintSource::Grass),
+    ("minecraft:pink_petals", TintSource::Grass),
+    ("minecraft:wildflowers", TintSource::Grass),
+    ("minecraft:oak_leaves", TintSource::Foliage),
+    ("minecraft:jungle_leaves", TintSource::Foliage),
+    ("minecraft:acacia_leaves", TintSource::Foliage),
+    ("minecraft:dark_oak_leaves", TintSource::Foliage),
+    ("minecraft:mangrove_leaves", TintSource::Foliage),
+    ("minecraft:vine", TintSource::Foliage),
+    ("minecraft:spruce_leaves", TintSource::Constant(0x619961)),
+    ("minecraft:birch_leaves", TintSource::Constant(0x80a755)),
+    ("minecraft:lily_pad", TintSource::Constant(0x208030)),
+    (
+        "minecraft:attached_melon_stem",
+        TintSource::Constant(0xe0c71c),
+    ),
+    (
+        "minecraft:attached_pumpkin_stem",
+        TintSource::Constant(0xe0c71c),
+    ),
+    ("minecraft:melon_stem", TintSource::Stem),
+    ("minecraft:pumpkin_stem", TintSource::Stem),
+    ("minecraft:redstone_wire", TintSource::RedstoneWire),
+    ("minecraft:water", TintSource::Water),
+    ("minecraft:bubble_column", TintSource::Water),
+    ("minecraft:water_cauldron", TintSource::Water),
+];
+
+/// A 256x256 colormap indexed by temperature and downfall
+pub struct Colormap {
+    pixels: Vec<u32>,
+}
+
+impl Colormap {
+    pub fn from_image_bytes(bytes: &[u8]) -> Option<Self> {
+        let image = image::load_from_memory(bytes).ok()?.to_rgba8();
+
+        if image.width() != 256 || image.height() != 256 {
+            return None;
+        }
+
+        Some(Self {
+            pixels: image
+                .pixels()
+                .map(|pixel| u32::from_be_bytes([0, pixel[0], pixel[1], pixel[2]]))
+                .collect(),
+        })
+    }
+
+    /// The colour at a temperature and downfall as `0xRRGGBB`, see `GrassColors::getColor`
+    pub fn sample(&self, temperature: f32, downfall: f32) -> u32 {
+        let temperature = temperature.clamp(0.0, 1.0);
+        let downfall = downfall.clamp(0.0, 1.0) * temperature;
+
+        let x = ((1.0 - temperature) * 255.0) as usize;
+        let y = ((1.0 - downfall) * 255.0) as usize;
+
+        self.pixels[(y << 8) | x]
+    }
+}
+
+/// The colours already looked up while baking a section, so that neighbouring blocks blending over the same
+/// columns only ask the provider for each biome once. Biomes are three dimensional, so columns are cached per height.
+#[derive(Default)]
+pub struct BiomeColorCache {
+    /// The colour of a source in the biome at a position, before blending
+    samples: RefCell<HashMap<(TintSource, IVec3), Option<u32>>>,
+    /// The blended colour of a source at a position, packed like [BlockStateProvider::get_block_color]
+    blended: RefCell<HashMap<(TintSource, IVec3), Option<u32>>>,
+}
+
+/// The colormaps and which blocks are tinted by them. Lives in the
+/// [BlockManager](crate::mc::BlockManager), and is reloaded by
+/// [MinecraftState::bake_blocks](crate::mc::MinecraftState::bake_blocks)
+pub struct BiomeTints {
+    grass: Option<Colormap>,
+    foliage: Option<Colormap>,
+    sources: HashMap<String, TintSource>,
+    /// [BiomeTints::sources] by block index, see [BiomeTints::index_blocks]
+    block_sources: Vec<Option<TintSource>>,
+}
+
+impl Default for BiomeTints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BiomeTints {
+    /// Doesn't tint any blocks until they're given a source with [BiomeTints::set_source] or
+    /// [BiomeTints::add_vanilla_sources]. The colormaps aren't loaded until [BiomeTints::reload]
+    pub fn new() -> Self {
+        Self {
+            grass: None,
+            foliage: None,
+            sources: HashMap::new(),
+            block_sources: Vec::new(),
+        }
+    }
+
+    /// Tints vanilla blocks like vanilla does, see [VANILLA_TINT_SOURCES]. Takes effect once the blocks are
+    /// indexed again.
+    pub fn add_vanilla_sources(&mut self) {
+        for (block, source) in VANILLA_TINT_SOURCES {
+            self.set_source(block, Some(*source));
+        }
+    }
+
+    /// Loads the colormaps again from the resource provider
+    pub fn reload(&mut self, resource_provider: &dyn ResourceProvider) {
+        let colormap = |path: &str| {
+            let colormap = resource_provider
+                .get_bytes(&ResourcePath::from(path))
+                .and_then(|bytes| Colormap::from_image_bytes(&bytes));
+
+            if colormap.is_none() {
+                log::warn!(
+                    "Couldn't load colormap {path}, blocks tinted by it will use get_block_color"
+                );
+            }
+
+            colormap
+        };
+
+        self.grass = colormap(GRASS_COLORMAP);
+        self.foliage = colormap(FOLIAGE_COLORMAP);
+    }
+
+    /// Changes how a block is tinted, or stops tinting it in core with `None`. Takes effect once the blocks are
+    /// indexed again.
+    pub fn set_source(&mut self, block: &str, source: Option<TintSource>) {
+        match source {
+            Some(source) => self.sources.insert(block.into(), source),
+            None => self.sources.remove(block),
+        };
+    }
+
+    /// Looks up the tint source of every block by its index in the block manager
+    pub fn index_blocks<'a>(&mut self, block_names: impl IntoIterator<Item = &'a String>) {
+        self.block_sources = block_names
+            .into_iter()
+            .map(|name| self.sources.get(name).copied())
+            .collect();
+    }
+
+    pub fn block_source(&self, block: u16) -> Option<TintSource> {
+        self.block_sources.get(block as usize).copied().flatten()
+    }
+
+    /// The colour of a source in a single biome as `0xRRGGBB`, or `None` if its colormap isn't loaded
+    pub fn color(&self, source: TintSource, biome: &Biome) -> Option<u32> {
+        match source {
+            TintSource::Grass => biome.grass_color.or_else(|| {
+                Some(
+                    self.grass
+                        .as_ref()?
+                        .sample(biome.temperature, biome.downfall),
+                )
+            }),
+            TintSource::Foliage => biome.foliage_color.or_else(|| {
+                Some(
+                    self.foliage
+                        .as_ref()?
+                        .sample(biome.temperature, biome.downfall),
+                )
+            }),
+            TintSource::Water => Some(biome.water_color),
+            TintSource::Constant(color) => Some(color),
+            //Depend on the blockstate rather than the biome, see [TintSource::resolve]
+            TintSource::Stem | TintSource::RedstoneWire => None,
+        }
+    }
+
+    /// The colour of a source at `pos`, relative to the section being baked, averaged over the columns within
+    /// `radius` blocks. Returns `None` if the provider doesn't know the biome there, packed like
+    /// [BlockStateProvider::get_block_color] otherwise. Colours are reused from `cache`, which should only be shared
+    /// between blocks of the same section.
+    pub fn blended_color<Provider: BlockStateProvider>(
+        &self,
+        source: TintSource,
+        pos: IVec3,
+        radius: u32,
+        provider: &Provider,
+        cache: &BiomeColorCache,
+    ) -> Option<u32> {
+        if let TintSource::Constant(color) = source {
+            return Some(pack_vertex_color(unpack_rgb(color)));
+        }
+
+        if let Some(color) = cache.blended.borrow().get(&(source, pos)) {
+            return *color;
+        }
+
+        let color = self.blend(source, pos, radius, provider, cache);
+        cache.blended.borrow_mut().insert((source, pos), color);
+
+        color
+    }
+
+    fn blend<Provider: BlockStateProvider>(
+        &self,
+        source: TintSource,
+        pos: IVec3,
+        radius: u32,
+        provider: &Provider,
+        cache: &BiomeColorCache,
+    ) -> Option<u32> {
+        let radius = radius as i32;
+        let mut sum = [0u32; 3];
+        let mut samples = 0;
+
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                let column = pos + ivec3(x, 0, z);
+
+                let sample = *cache
+                    .samples
+                    .borrow_mut()
+                    .entry((source, column))
+                    .or_insert_with(|| {
+                        provider
+                            .get_biome(column)
+                            .and_then(|biome| self.color(source, &biome))
+                    });
+
+                let Some(color) = sample else {
+                    continue;
+                };
+
+                sum.iter_mut()
+                    .zip(unpack_rgb(color))
+                    .for_each(|(sum, channel)| *sum += channel);
+                samples += 1;
+            }
+        }
+
+        if samples == 0 {
+            return None;
+        }
+
+        Some(pack_vertex_color(sum.map(|channel| channel / samples)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::chunk::{ChunkBlockState, LightLevel};
+    use crate::render::pipeline::Vertex;
+
+    struct Plains;
+
+    /// Counts how often it's asked for a biome
+    #[derive(Default)]
+    struct CountingPlains(std::cell::Cell<usize>);
+
+    impl BlockStateProvider for Plains {
+        fn get_state(&self, _pos: IVec3) -> ChunkBlockState {
+            ChunkBlockState::Air
+        }
+
+        fn get_light_level(&self, _pos: IVec3) -> LightLevel {
+            LightLevel::from_sky_and_block(15, 0)
+        }
+
+        fn is_section_empty(&self, _rel_pos: IVec3) -> bool {
+            true
+        }
+
+        fn get_biome(&self, _pos: IVec3) -> Option<Biome> {
+            Some(Biome::default())
+        }
+    }
+
+    impl BlockStateProvider for CountingPlains {
+        fn get_state(&self, pos: IVec3) -> ChunkBlockState {
+            Plains.get_state(pos)
+        }
+
+        fn get_light_level(&self, pos: IVec3) -> LightLevel {
+            Plains.get_light_level(pos)
+        }
+
+        fn is_section_empty(&self, rel_pos: IVec3) -> bool {
+            Plains.is_section_empty(rel_pos)
+        }
+
+        fn get_biome(&self, pos: IVec3) -> Option<Biome> {
+            self.0.set(self.0.get() + 1);
+            Plains.get_biome(pos)
+        }
+    }
+
+    /// The red, green and blue bytes of a vertex with the colour
+    fn vertex_rgb(color: u32) -> [u8; 3] {
+        let bytes = Vertex {
+            position: [0.0; 3],
+            uv: [0; 2],
+            normal: [0.0, 1.0, 0.0],
+            color,
+            uv_offset: 0,
+            lightmap_coords: 0,
+            ao: 255,
+        }
+        .compressed();
+
+        [bytes[3], bytes[4], bytes[5]]
+    }
+
+    #[test]
+    fn packs_red_in_the_low_byte() {
+        let tints = BiomeTints {
+            //Plains grass
+            grass: Some(Colormap {
+                pixels: vec![0x91bd59; 256 * 256],
+            }),
+            ..BiomeTints::new()
+        };
+        let cache = BiomeColorCache::default();
+
+        let grass = tints
+            .blended_color(TintSource::Grass, IVec3::ZERO, 2, &Plains, &cache)
+            .unwrap();
+        assert_eq!(vertex_rgb(grass), [0x91, 0xbd, 0x59]);
+
+        let birch = tints
+            .blended_color(
+                TintSource::Constant(0x80a755),
+                IVec3::ZERO,
+                2,
+                &Plains,
+                &cache,
+            )
+            .unwrap();
+        assert_eq!(vertex_rgb(birch), [0x80, 0xa7, 0x55]);
+
+        let water = tints
+            .blended_color(TintSource::Water, IVec3::ZERO, 0, &Plains, &cache)
+            .unwrap();
+        assert_eq!(vertex_rgb(water), [0x3f, 0x76, 0xe4]);
+    }
+
+    #[test]
+    fn looks_up_each_column_once() {
+        let tints = BiomeTints::new();
+        let cache = BiomeColorCache::default();
+        let provider = CountingPlains::default();
+
+        tints.blended_color(TintSource::Water, IVec3::ZERO, 2, &provider, &cache);
+        assert_eq!(provider.0.get(), 25);
+
+        //The next block over only adds a row of columns
+        tints.blended_color(TintSource::Water, ivec3(1, 0, 0), 2, &provider, &cache);
+        assert_eq!(provider.0.get(), 30);
+
+        //Other faces of the same block reuse the blended colour
+        tints.blended_color(TintSource::Water, ivec3(1, 0, 0), 2, &provider, &cache);
+        assert_eq!(provider.0.get(), 30);
+    }
+
+    #[test]
+    fn only_tints_vanilla_blocks_when_asked() {
+        let blocks = [
+            "minecraft:grass_block",
+            "minecraft:stone",
+            "minecraft:redstone_wire",
+        ]
+        .map(String::from);
+
+        let mut tints = BiomeTints::new();
+        tints.index_blocks(&blocks);
+        assert_eq!(tints.block_source(0), None);
+
+        tints.add_vanilla_sources();
+        tints.set_source("minecraft:stone", Some(TintSource::Constant(0x7f7f7f)));
+        tints.index_blocks(&blocks);
+
+        assert_eq!(tints.block_source(0), Some(TintSource::Grass));
+        assert_eq!(tints.block_source(1), Some(TintSource::Constant(0x7f7f7f)));
+        assert_eq!(tints.block_source(2), Some(TintSource::RedstoneWire));
+        assert_eq!(tints.block_source(3), None);
+    }
+
+    //Expected colours were taken from vanilla's BlockColors and RedstoneWireBlock.COLORS
+
+    #[test]
+    fn resolves_colours_from_the_blockstate() {
+        let resolve = |source: TintSource, property: &'static str, value: &'static str| {
+            source.resolve(|name| (name == property).then(|| value.to_string()))
+        };
+
+        assert_eq!(
+            resolve(TintSource::Stem, "age", "0"),
+            Some(TintSource::Constant(0x00ff00))
+        );
+        assert_eq!(
+            resolve(TintSource::Stem, "age", "3"),
+            Some(TintSource::Constant(0x60e70c))
+        );
+        assert_eq!(
+            resolve(TintSource::Stem, "age", "7"),
+            Some(TintSource::Constant(0xe0c71c))
+        );
+
+        for (power, color) in [
+            ("0", 0x4c0000),
+            ("1", 0x700000),
+            ("9", 0xc10000),
+            ("13", 0xea0600),
+            ("14", 0xf41b00),
+            ("15", 0xff3200),
+        ] {
+            assert_eq!(
+                resolve(TintSource::RedstoneWire, "power", power),
+                Some(TintSource::Constant(color)),
+                "power {power}"
+            );
+        }
+
+        assert_eq!(resolve(TintSource::RedstoneWire, "age", "3"), None);
+        assert_eq!(
+            resolve(TintSource::Grass, "age", "3"),
+            Some(TintSource::Grass)
+        );
+    }
+}
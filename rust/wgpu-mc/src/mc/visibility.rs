@@ -0,0 +1,349 @@
+//! Cave culling, see vanilla's `ChunkOcclusionDataBuilder` and `WorldRenderer::applyFrustum`
+//!
+//! When a section is baked, its blocks are flood filled to find which of its faces can see each other through
+//! blocks which aren't opaque. Starting at the camera's section, the sections which can be seen are then found by
+//! walking through faces which are connected, never turning back towards the camera. Like vanilla, sections which
+//! aren't in the storage, such as empty ones, are walked straight through.
+
+use std::collections::{HashSet, VecDeque};
+
+use glam::{IVec3, Vec3Swizzles};
+
+use crate::mc::chunk::SectionStorage;
+use crate::mc::direction::Direction;
+
+/// Which faces of a section can be seen from which other faces
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SectionVisibility(u64);
+
+impl SectionVisibility {
+    /// Every face can see every other face, as in a section without opaque blocks
+    pub const ALL: Self = Self((1 << 36) - 1);
+
+    pub const NONE: Self = Self(0);
+
+    pub fn is_visible_through(&self, from: Direction, to: Direction) -> bool {
+        self.0 & Self::bit(from, to) != 0
+    }
+
+    fn connect(&mut self, a: Direction, b: Direction) {
+        self.0 |= Self::bit(a, b) | Self::bit(b, a);
+    }
+
+    fn bit(from: Direction, to: Direction) -> u64 {
+        1 << (from as u64 * 6 + to as u64)
+    }
+}
+
+impl Default for SectionVisibility {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// Below this many opaque blocks, a section can't separate any of its faces from each other
+const MIN_OPAQUE_BLOCKS: u32 = 256;
+
+#[inline]
+fn block_index(pos: IVec3) -> usize {
+    (pos.x | (pos.z << 4) | (pos.y << 8)) as usize
+}
+
+/// Collects the opaque blocks of a section while it's being baked
+pub struct VisibilityBuilder {
+    opaque: [u64; 64],
+    opaque_count: u32,
+}
+
+impl Default for VisibilityBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VisibilityBuilder {
+    pub fn new() -> Self {
+        Self {
+            opaque: [0; 64],
+            opaque_count: 0,
+        }
+    }
+
+    /// Marks the block at `pos`, relative to the section's origin, as opaque
+    pub fn mark_opaque(&mut self, pos: IVec3) {
+        let index = block_index(pos);
+
+        if !Self::get(&self.opaque, index) {
+            Self::set(&mut self.opaque, index);
+            self.opaque_count += 1;
+        }
+    }
+
+    pub fn build(&self) -> SectionVisibility {
+        if self.opaque_count < MIN_OPAQUE_BLOCKS {
+            return SectionVisibility::ALL;
+        }
+
+        let mut visibility = SectionVisibility::NONE;
+        let mut visited = self.opaque;
+        let mut queue = Vec::new();
+
+        for index in 0..16 * 16 * 16 {
+            if Self::get(&visited, index) {
+                continue;
+            }
+
+            Self::set(&mut visited, index);
+            queue.push(index);
+
+            let mut faces = [false; 6];
+
+            while let Some(index) = queue.pop() {
+                let pos = IVec3::new(
+                    index as i32 & 15,
+                    index as i32 >> 8,
+                    (index as i32 >> 4) & 15,
+                );
+
+                for dir in Direction::ALL {
+                    let neighbour = pos + dir.to_vec();
+
+                    if neighbour.cmplt(IVec3::ZERO).any() || neighbour.cmpgt(IVec3::splat(15)).any()
+                    {
+                        faces[dir as usize] = true;
+                        continue;
+                    }
+
+                    let neighbour_index = block_index(neighbour);
+
+                    if !Self::get(&visited, neighbour_index) {
+                        Self::set(&mut visited, neighbour_index);
+                        queue.push(neighbour_index);
+                    }
+                }
+            }
+
+            for a in Direction::ALL
+                .into_iter()
+                .filter(|dir| faces[*dir as usize])
+            {
+                for b in Direction::ALL
+                    .into_iter()
+                    .filter(|dir| faces[*dir as usize])
+                {
+                    visibility.connect(a, b);
+                }
+            }
+        }
+
+        visibility
+    }
+
+    #[inline]
+    fn get(bits: &[u64; 64], index: usize) -> bool {
+        bits[index >> 6] & (1 << (index & 63)) != 0
+    }
+
+    #[inline]
+    fn set(bits: &mut [u64; 64], index: usize) {
+        bits[index >> 6] |= 1 << (index & 63);
+    }
+}
+
+/// Walks outwards from the camera's section through connected faces, returning every section which can be seen.
+/// The walk passes through missing sections but stays within the loaded sections, and within `render_distance`
+/// sections of the camera horizontally unless it's 0. Returns `None` if no sections are loaded, in which case
+/// nothing can be culled.
+pub fn find_reachable_sections(
+    storage: &SectionStorage,
+    camera_section: IVec3,
+    render_distance: i32,
+) -> Option<HashSet<IVec3>> {
+    let (mut min, mut max) = storage.iter().fold(None, |bounds, (pos, _)| match bounds {
+        None => Some((*pos, *pos)),
+        Some((min, max)) => Some((pos.min(min), pos.max(max))),
+    })?;
+
+    if render_distance > 0 {
+        let (near, far) = (
+            camera_section.xz() - render_distance,
+            camera_section.xz() + render_distance,
+        );
+
+        min = IVec3::new(min.x.max(near.x), min.y, min.z.max(near.y));
+        max = IVec3::new(max.x.min(far.x), max.y, max.z.min(far.y));
+    }
+
+    //Cameras above or below the world still look into it
+    let (min, max) = (min.min(camera_section), max.max(camera_section));
+
+    let mut reachable = HashSet::from([camera_section]);
+    //The section, the face it was entered through, and every direction walked to get there
+    let mut queue = VecDeque::from([(camera_section, None::<Direction>, 0u8)]);
+
+    while let Some((pos, entered_from, walked)) = queue.pop_front() {
+        let visibility = storage
+            .get(&pos)
+            .map_or(SectionVisibility::ALL, |section| section.visibility);
+
+        for dir in Direction::ALL {
+            if walked & (1 << dir.opposite() as u8) != 0 {
+                continue;
+            }
+
+            if entered_from.is_some_and(|from| !visibility.is_visible_through(from, dir)) {
+                continue;
+            }
+
+            let neighbour = pos + dir.to_vec();
+
+            if neighbour.cmplt(min).any()
+                || neighbour.cmpgt(max).any()
+                || !reachable.insert(neighbour)
+            {
+                continue;
+            }
+
+            queue.push_back((neighbour, Some(dir.opposite()), walked | (1 << dir as u8)));
+        }
+    }
+
+    Some(reachable)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::ivec3;
+
+    use super::*;
+    use crate::mc::chunk::BakedSection;
+
+    fn builder(opaque: impl IntoIterator<Item = IVec3>) -> VisibilityBuilder {
+        let mut builder = VisibilityBuilder::new();
+        opaque.into_iter().for_each(|pos| builder.mark_opaque(pos));
+        builder
+    }
+
+    fn layer(y: i32) -> impl Iterator<Item = IVec3> {
+        (0..16).flat_map(move |x| (0..16).map(move |z| ivec3(x, y, z)))
+    }
+
+    #[test]
+    fn wall_separates_faces() {
+        let visibility = builder(layer(8)).build();
+
+        assert!(!visibility.is_visible_through(Direction::Up, Direction::Down));
+        assert!(!visibility.is_visible_through(Direction::Down, Direction::Up));
+
+        //Both halves touch every side
+        for side in [
+            Direction::West,
+            Direction::East,
+            Direction::North,
+            Direction::South,
+        ] {
+            assert!(visibility.is_visible_through(Direction::Up, side));
+            assert!(visibility.is_visible_through(side, Direction::Down));
+            assert!(visibility.is_visible_through(side, side.opposite()));
+        }
+    }
+
+    #[test]
+    fn wall_with_hole_connects_faces() {
+        let visibility = builder(layer(8).filter(|pos| *pos != ivec3(7, 8, 7))).build();
+
+        assert_eq!(visibility, SectionVisibility::ALL);
+    }
+
+    #[test]
+    fn wall_against_a_face_hides_it() {
+        let visibility = builder(layer(0)).build();
+
+        for dir in Direction::ALL {
+            assert!(!visibility.is_visible_through(Direction::Down, dir));
+            assert!(!visibility.is_visible_through(dir, Direction::Down));
+        }
+        assert!(visibility.is_visible_through(Direction::Up, Direction::West));
+    }
+
+    #[test]
+    fn too_few_opaque_blocks() {
+        assert_eq!(VisibilityBuilder::new().build(), SectionVisibility::ALL);
+
+        //A wall one block short of the shortcut is never flood filled, and marking a block twice doesn't count
+        let mut wall = builder(layer(8).take(MIN_OPAQUE_BLOCKS as usize - 1));
+        wall.mark_opaque(ivec3(0, 8, 0));
+        assert_eq!(wall.build(), SectionVisibility::ALL);
+    }
+
+    #[test]
+    fn fully_opaque_section() {
+        let visibility = builder((0..16).flat_map(layer)).build();
+
+        assert_eq!(visibility, SectionVisibility::NONE);
+    }
+
+    /// Loads every section within 2 of the origin as fully opaque, apart from the camera's section at the
+    /// origin and any in `open`
+    fn cave(open: &[(IVec3, SectionVisibility)]) -> SectionStorage {
+        let mut storage = SectionStorage::new(64, 64);
+
+        for x in -2..=2 {
+            for y in -2..=2 {
+                for z in -2..=2 {
+                    let pos = ivec3(x, y, z);
+
+                    let visibility = match open.iter().find(|(open, _)| *open == pos) {
+                        Some((_, visibility)) => *visibility,
+                        None if pos == IVec3::ZERO => SectionVisibility::ALL,
+                        None => SectionVisibility::NONE,
+                    };
+
+                    storage.replace(
+                        pos,
+                        &BakedSection {
+                            layers: Vec::new(),
+                            visibility,
+                        },
+                    );
+                }
+            }
+        }
+
+        storage
+    }
+
+    fn neighbours(pos: IVec3) -> impl Iterator<Item = IVec3> {
+        Direction::ALL
+            .into_iter()
+            .map(move |dir| pos + dir.to_vec())
+    }
+
+    #[test]
+    fn sealed_cave() {
+        let reachable = find_reachable_sections(&cave(&[]), IVec3::ZERO, 0).unwrap();
+
+        //The walls themselves are seen, but nothing behind them
+        let expected: HashSet<IVec3> = neighbours(IVec3::ZERO).chain([IVec3::ZERO]).collect();
+        assert_eq!(reachable, expected);
+    }
+
+    #[test]
+    fn tunnel_out_of_cave() {
+        let mut tunnel = SectionVisibility::NONE;
+        tunnel.connect(Direction::West, Direction::East);
+
+        let storage = cave(&[(ivec3(1, 0, 0), tunnel)]);
+        let reachable = find_reachable_sections(&storage, IVec3::ZERO, 0).unwrap();
+
+        let expected: HashSet<IVec3> = neighbours(IVec3::ZERO)
+            .chain([IVec3::ZERO, ivec3(2, 0, 0)])
+            .collect();
+        assert_eq!(reachable, expected);
+    }
+
+    #[test]
+    fn nothing_loaded() {
+        assert!(find_reachable_sections(&SectionStorage::new(64, 64), IVec3::ZERO, 0).is_none());
+    }
+}
@@ -0,0 +1,249 @@
+//! Animated textures, which are strips of frames described by the `animation` section of the texture's `.mcmeta`.
+//! The [Atlas](crate::render::atlas::Atlas) keeps every frame of the strip and maps the texture to its first frame,
+//! so animating it is a matter of offsetting the UVs of the first frame by those of the current frame.
+
+use bytemuck::{Pod, Zeroable};
+use serde_derive::{Deserialize, Serialize};
+
+/// The largest number of animated textures an atlas can hold, as vertices store the index of their animation in
+/// 10 bits and index 0 means not animated
+pub const MAX_ANIMATIONS: usize = (1 << 10) - 1;
+
+/// The contents of a texture's `.mcmeta`
+#[derive(Debug, Deserialize)]
+pub struct TextureMetadata {
+    pub animation: Option<AnimationMetadata>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimationMetadata {
+    /// Whether to blend from each frame to the next instead of switching at once
+    #[serde(default)]
+    pub interpolate: bool,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// How many ticks each frame is shown for, unless the frame says otherwise
+    #[serde(default = "default_frametime")]
+    pub frametime: u32,
+    /// The order the frames play in, every frame in order if missing
+    pub frames: Option<Vec<AnimationFrame>>,
+}
+
+fn default_frametime() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AnimationFrame {
+    Index(u32),
+    Timed { index: u32, time: u32 },
+}
+
+impl AnimationMetadata {
+    /// The size of a frame in an image of the given size. Frames are square unless the metadata says otherwise,
+    /// like vanilla.
+    pub fn frame_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match (self.width, self.height) {
+            (Some(frame_width), Some(frame_height)) => (frame_width, frame_height),
+            (Some(frame_width), None) => (frame_width, height),
+            (None, Some(frame_height)) => (width, frame_height),
+            (None, None) => (width.min(height), width.min(height)),
+        }
+    }
+}
+
+/// The UV offsets of the current and next frame of an animated texture, as uploaded to the animated texture SSBO
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Zeroable, Pod)]
+pub struct AnimatedUV {
+    pub uv_1: [f32; 2],
+    pub uv_2: [f32; 2],
+    pub blend: f32,
+    pub padding: f32,
+}
+
+/// An animated texture in an atlas, with its frames sliced out of the strip
+#[derive(Debug, Clone)]
+pub struct AnimatedSprite {
+    pub metadata: AnimationMetadata,
+    /// The offset of each frame from the first frame of the strip in pixels, and how many ticks it's shown for, in
+    /// the order they play
+    pub frames: Vec<([u16; 2], u32)>,
+    /// The size of a frame in pixels
+    pub frame_size: (u32, u32),
+    duration: u32,
+}
+
+impl AnimatedSprite {
+    /// Slices an image of the given size into frames, row by row. Frames which are out of bounds are left out, and
+    /// `None` is returned if there are no frames left.
+    pub fn new(metadata: AnimationMetadata, width: u32, height: u32) -> Option<Self> {
+        let (frame_width, frame_height) = metadata.frame_size(width, height);
+
+        if frame_width == 0 || frame_height == 0 {
+            return None;
+        }
+
+        let columns = width / frame_width;
+        let count = columns * (height / frame_height);
+
+        let offset = |index: u32| {
+            [
+                ((index % columns) * frame_width) as u16,
+                ((index / columns) * frame_height) as u16,
+            ]
+        };
+
+        //Frames shown for 0 ticks would never be left
+        let frames: Vec<_> = match &metadata.frames {
+            Some(frames) => frames
+                .iter()
+                .filter_map(|frame| {
+                    let (index, time) = match *frame {
+                        AnimationFrame::Index(index) => (index, metadata.frametime),
+                        AnimationFrame::Timed { index, time } => (index, time),
+                    };
+
+                    (index < count).then(|| (offset(index), time.max(1)))
+                })
+                .collect(),
+            None => (0..count)
+                .map(|index| (offset(index), metadata.frametime.max(1)))
+                .collect(),
+        };
+
+        if frames.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            duration: frames.iter().map(|(_, time)| time).sum(),
+            frames,
+            frame_size: (frame_width, frame_height),
+            metadata,
+        })
+    }
+
+    /// The offsets of the frame shown at a tick and the one after it, blended between if the animation interpolates
+    pub fn at(&self, tick: u64) -> AnimatedUV {
+        let mut time = (tick % self.duration as u64) as u32;
+
+        for (index, &(offset, frame_time)) in self.frames.iter().enumerate() {
+            if time < frame_time {
+                let (next, _) = self.frames[(index + 1) % self.frames.len()];
+
+                return AnimatedUV {
+                    uv_1: offset.map(f32::from),
+                    uv_2: next.map(f32::from),
+                    blend: if self.metadata.interpolate {
+                        time as f32 / frame_time as f32
+                    } else {
+                        0.0
+                    },
+                    padding: 0.0,
+                };
+            }
+
+            time -= frame_time;
+        }
+
+        unreachable!("tick is wrapped to the duration of the animation")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animated(mcmeta: &str, width: u32, height: u32) -> Option<AnimatedSprite> {
+        let metadata: TextureMetadata = serde_json::from_str(mcmeta).unwrap();
+
+        AnimatedSprite::new(metadata.animation.unwrap(), width, height)
+    }
+
+    fn frames(
+        sprite: &AnimatedSprite,
+        ticks: impl IntoIterator<Item = u64>,
+    ) -> Vec<([f32; 2], [f32; 2])> {
+        ticks
+            .into_iter()
+            .map(|tick| sprite.at(tick))
+            .map(|uv| (uv.uv_1, uv.uv_2))
+            .collect()
+    }
+
+    #[test]
+    fn plays_strip_in_order() {
+        let sprite = animated(r#"{"animation": {"frametime": 2}}"#, 16, 64).unwrap();
+
+        assert_eq!(sprite.frame_size, (16, 16));
+        assert_eq!(
+            frames(&sprite, [0, 1, 2, 5, 7, 8]),
+            [
+                ([0.0, 0.0], [0.0, 16.0]),
+                ([0.0, 0.0], [0.0, 16.0]),
+                ([0.0, 16.0], [0.0, 32.0]),
+                ([0.0, 32.0], [0.0, 48.0]),
+                ([0.0, 48.0], [0.0, 0.0]),
+                ([0.0, 0.0], [0.0, 16.0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn slices_frames_row_by_row() {
+        let sprite = animated(r#"{"animation": {"width": 16, "height": 16}}"#, 32, 32).unwrap();
+
+        assert_eq!(
+            sprite.frames,
+            [([0, 0], 1), ([16, 0], 1), ([0, 16], 1), ([16, 16], 1)]
+        );
+    }
+
+    #[test]
+    fn custom_frames_and_times() {
+        //Frame 9 is out of bounds and left out, and a time of 0 is shown for a tick
+        let sprite = animated(
+            r#"{"animation": {"frametime": 3, "frames": [2, {"index": 0, "time": 5}, 9, {"index": 1, "time": 0}]}}"#,
+            16,
+            48,
+        )
+        .unwrap();
+
+        assert_eq!(sprite.frames, [([0, 32], 3), ([0, 0], 5), ([0, 16], 1)]);
+        assert_eq!(
+            frames(&sprite, [0, 2, 3, 7, 8, 9]),
+            [
+                ([0.0, 32.0], [0.0, 0.0]),
+                ([0.0, 32.0], [0.0, 0.0]),
+                ([0.0, 0.0], [0.0, 16.0]),
+                ([0.0, 0.0], [0.0, 16.0]),
+                ([0.0, 16.0], [0.0, 32.0]),
+                ([0.0, 32.0], [0.0, 0.0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn interpolates_towards_next_frame() {
+        let sprite = animated(
+            r#"{"animation": {"frametime": 4, "interpolate": true}}"#,
+            16,
+            32,
+        )
+        .unwrap();
+
+        let blends: Vec<f32> = (0..8).map(|tick| sprite.at(tick).blend).collect();
+        assert_eq!(blends, [0.0, 0.25, 0.5, 0.75, 0.0, 0.25, 0.5, 0.75]);
+
+        let sprite = animated(r#"{"animation": {"frametime": 4}}"#, 16, 32).unwrap();
+        assert_eq!(sprite.at(2).blend, 0.0);
+    }
+
+    #[test]
+    fn no_frames() {
+        assert!(animated(r#"{"animation": {"frames": [4]}}"#, 16, 64).is_none());
+        assert!(animated(r#"{"animation": {}}"#, 0, 0).is_none());
+    }
+}
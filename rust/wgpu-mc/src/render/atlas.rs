@@ -1,23 +1,96 @@
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
 use std::sync::Arc;
 
-use bytemuck::{Pod, Zeroable};
-use guillotiere::euclid::Size2D;
-use guillotiere::AtlasAllocator;
+use arc_swap::ArcSwap;
+use guillotiere::{size2, AllocatorOptions, AtlasAllocator, DEFAULT_OPTIONS};
 use image::imageops::overlay;
-use image::{ImageBuffer, Rgba};
-use minecraft_assets::schemas;
+use image::{DynamicImage, GenericImage, ImageBuffer, Rgba, RgbaImage};
 use parking_lot::RwLock;
-use wgpu::Extent3d;
+use serde_derive::Serialize;
 
 use crate::mc::resource::{ResourcePath, ResourceProvider};
+use crate::render::animation::{
+    AnimatedSprite, AnimatedUV, AnimationMetadata, TextureMetadata, MAX_ANIMATIONS,
+};
 use crate::texture::{TextureAndView, UV};
 use crate::{Display, WmRenderer};
 
-/// The width and height of an [atlas](Atlas];
+/// The width and height an [atlas](Atlas) starts out with
 pub const ATLAS_DIMENSIONS: u32 = 2048;
 
+/// The largest an [atlas](Atlas) can grow to, as UVs are stored in 16 bits
+pub const MAX_ATLAS_DIMENSIONS: u32 = 32768;
+
+/// How many mip levels below the full size image the block atlas has by default, like vanilla
+pub const DEFAULT_MIP_LEVELS: u32 = 4;
+
+/// The path of the generated texture which stands in for textures that couldn't be loaded, see [Atlas::missing_texture]
+pub const MISSING_TEXTURE: &str = "wgpu_mc:missing";
+
+#[derive(Debug)]
+pub enum AtlasError {
+    InvalidImage(ResourcePath, image::ImageError),
+    /// There is no space left in the atlas for the image
+    OutOfSpace(ResourcePath),
+}
+
+impl std::fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidImage(path, error) => write!(f, "invalid image {path}: {error}"),
+            Self::OutOfSpace(path) => write!(f, "no space left in the atlas for {path}"),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidImage(_, error) => Some(error),
+            Self::OutOfSpace(_) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AtlasDumpError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Json(serde_json::Error),
+}
+
+impl From<std::io::Error> for AtlasDumpError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Where a texture is in an [Atlas], see [Atlas::layout]
+#[derive(Debug, Serialize)]
+pub struct SpriteLayout {
+    pub path: String,
+    /// The rectangle of the texture in pixels, which is only the first frame for animated textures
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The index of the texture's animation, 0 if it isn't animated
+    pub animation_index: u32,
+    pub animation: Option<AnimationMetadata>,
+}
+
+/// Every texture in an [Atlas] along with its size, as written by [Atlas::dump]
+#[derive(Debug, Serialize)]
+pub struct AtlasLayout {
+    pub size: u32,
+    pub mip_levels: u32,
+    pub sprites: Vec<SpriteLayout>,
+}
+
 /// A texture atlas. This is used in many places, most notably terrain and entity rendering.
 /// Combines multiple small textures into a single big one, which can help improve performance.
 ///
@@ -50,7 +123,7 @@ pub const ATLAS_DIMENSIONS: u32 = 2048;
 ///             &resource_provider.get_bytes(&dirt).unwrap()
 ///         )
 ///     ], &*resource_provider
-/// );
+/// ).unwrap();
 ///
 /// atlas.upload(&wm_renderer);
 /// ```
@@ -59,14 +132,19 @@ pub struct Atlas {
     pub allocator: RwLock<AtlasAllocator>,
     /// The atlas image buffer itself. This is what gets uploaded to the GPU
     pub image: RwLock<ImageBuffer<Rgba<u8>, Vec<u8>>>,
-    /// The mapping of image [ResourcePath]s to UV coordinates
+    /// The mapping of image [ResourcePath]s to UV coordinates. Animated textures map to their first frame
     pub uv_map: RwLock<HashMap<ResourcePath, UV>>,
-    /// The representation of the [Atlas]'s image buffer on the GPU, which can be bound to a draw call
-    pub texture: Arc<TextureAndView>,
-    /// Not every [Atlas] is used for block textures, but the ones that are store the information for each animated texture here
-    pub animated_textures: RwLock<Vec<schemas::texture::TextureAnimation>>,
+    /// The representation of the [Atlas]'s image buffer on the GPU, which can be bound to a draw call. Replaced by
+    /// a bigger texture when the atlas grows, see [Atlas::upload]
+    pub texture: ArcSwap<TextureAndView>,
+    /// Every animated texture in the atlas, the texture at index `i` having animation index `i + 1`
+    pub animated_textures: RwLock<Vec<AnimatedSprite>>,
+    /// The animation index of each animated texture, which vertices store to find their frame offsets. Textures
+    /// which aren't animated have index 0
     pub animated_texture_offsets: RwLock<HashMap<ResourcePath, u32>>,
     size: u32,
+    max_size: u32,
+    mip_levels: u32,
 }
 
 impl Debug for Atlas {
@@ -76,60 +154,147 @@ impl Debug for Atlas {
 }
 
 impl Atlas {
-    pub fn new(display: &Display, _resizes: bool) -> Self {
-        let tv = TextureAndView::from_rgb_bytes(
-            display,
-            &vec![0u8; (ATLAS_DIMENSIONS * ATLAS_DIMENSIONS) as usize * 4],
-            Extent3d {
-                width: ATLAS_DIMENSIONS,
-                height: ATLAS_DIMENSIONS,
-                depth_or_array_layers: 1,
-            },
-            None,
-            wgpu::TextureFormat::Rgba8Unorm,
-        )
-        .unwrap();
+    /// An atlas which `resizes` doubles in size whenever it runs out of space, up to the largest texture the
+    /// device supports. UVs are in pixels, so they stay valid as it grows.
+    pub fn new(display: &Display, resizes: bool) -> Self {
+        Self::with_mip_levels(display, resizes, 0)
+    }
+
+    /// An atlas with `mip_levels` mips below the full size image. Textures are allocated at multiples of
+    /// `2^mip_levels` pixels, so that no texel of a lower mip is shared between neighbouring textures.
+    pub fn with_mip_levels(display: &Display, resizes: bool, mip_levels: u32) -> Self {
+        let image = ImageBuffer::new(ATLAS_DIMENSIONS, ATLAS_DIMENSIONS);
+        let mip_levels = mip_levels.min(ATLAS_DIMENSIONS.ilog2());
+
+        let max_size = if resizes {
+            display
+                .device
+                .limits()
+                .max_texture_dimension_2d
+                .clamp(ATLAS_DIMENSIONS, MAX_ATLAS_DIMENSIONS)
+        } else {
+            ATLAS_DIMENSIONS
+        };
 
         Self {
-            allocator: RwLock::new(AtlasAllocator::new(Size2D::new(
-                ATLAS_DIMENSIONS as i32,
-                ATLAS_DIMENSIONS as i32,
-            ))),
-            image: RwLock::new(ImageBuffer::new(ATLAS_DIMENSIONS, ATLAS_DIMENSIONS)),
+            allocator: RwLock::new(Self::create_allocator(ATLAS_DIMENSIONS, mip_levels)),
+            texture: ArcSwap::new(Arc::new(
+                TextureAndView::from_mip_chain(display, &mip_chain(&image, mip_levels), None)
+                    .unwrap(),
+            )),
+            image: RwLock::new(image),
             uv_map: Default::default(),
-            texture: Arc::new(tv),
             animated_textures: RwLock::new(Vec::new()),
             animated_texture_offsets: Default::default(),
             size: ATLAS_DIMENSIONS,
+            max_size,
+            mip_levels,
         }
     }
 
-    /// Add multiple textures to the atlas. This automatically handles .mcmeta files when dealing with block textures
+    fn create_allocator(size: u32, mip_levels: u32) -> AtlasAllocator {
+        let alignment = 1 << mip_levels;
+
+        AtlasAllocator::with_options(
+            size2(size as i32, size as i32),
+            &AllocatorOptions {
+                alignment: size2(alignment, alignment),
+                ..DEFAULT_OPTIONS
+            },
+        )
+    }
+
+    /// The current width and height of the atlas
+    pub fn size(&self) -> u32 {
+        self.image.read().width()
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    /// Add multiple textures to the atlas. This automatically handles .mcmeta files when dealing with block textures.
+    /// Stops at the first image which can't be decoded or doesn't fit, the images before it stay allocated.
     pub fn allocate<'a, T>(
         &self,
         images: impl IntoIterator<Item = (&'a ResourcePath, &'a T)>,
         resource_provider: &dyn ResourceProvider,
-    ) where
+    ) -> Result<(), AtlasError>
+    where
         T: AsRef<[u8]> + 'a,
     {
+        images.into_iter().try_for_each(|(name, slice)| {
+            let image = image::load_from_memory(slice.as_ref())
+                .map_err(|error| AtlasError::InvalidImage(name.clone(), error))?;
+
+            self.allocate_images([(name, &image)], resource_provider)
+        })
+    }
+
+    /// Like [Atlas::allocate], for images which have already been decoded or were generated
+    pub fn allocate_images<'a>(
+        &self,
+        images: impl IntoIterator<Item = (&'a ResourcePath, &'a DynamicImage)>,
+        resource_provider: &dyn ResourceProvider,
+    ) -> Result<(), AtlasError> {
         let mut allocator = self.allocator.write();
         let mut image_buffer = self.image.write();
         let mut map = self.uv_map.write();
 
         let mut animated_textures = self.animated_textures.write();
-        // let mut animated_texture_offsets = self.animated_texture_offsets.write();
+        let mut animated_texture_offsets = self.animated_texture_offsets.write();
 
-        images.into_iter().for_each(|(name, slice)| {
+        images.into_iter().try_for_each(|(name, image)| {
             self.allocate_one(
                 &mut image_buffer,
                 &mut map,
                 &mut allocator,
                 &mut animated_textures,
+                &mut animated_texture_offsets,
                 name,
-                slice.as_ref(),
+                image,
                 resource_provider,
-            );
+            )
+        })
+    }
+
+    /// The UV of the magenta and black checkerboard texture used in place of textures which couldn't be loaded.
+    /// It is generated and allocated the first time it's needed, returns `None` if there was no space left for it.
+    pub fn missing_texture(&self, resource_provider: &dyn ResourceProvider) -> Option<UV> {
+        let path = ResourcePath::from(MISSING_TEXTURE);
+
+        //Locked in the same order as in allocate
+        let mut allocator = self.allocator.write();
+        let mut image_buffer = self.image.write();
+        let mut map = self.uv_map.write();
+        let mut animated_textures = self.animated_textures.write();
+        let mut animated_texture_offsets = self.animated_texture_offsets.write();
+
+        if let Some(uv) = map.get(&path) {
+            return Some(*uv);
+        }
+
+        let image = RgbaImage::from_fn(16, 16, |x, y| {
+            if (x < 8) == (y < 8) {
+                Rgba([248, 0, 248, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
         });
+
+        self.allocate_one(
+            &mut image_buffer,
+            &mut map,
+            &mut allocator,
+            &mut animated_textures,
+            &mut animated_texture_offsets,
+            &path,
+            &DynamicImage::ImageRgba8(image),
+            resource_provider,
+        )
+        .ok()?;
+
+        map.get(&path).copied()
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -138,35 +303,63 @@ impl Atlas {
         image_buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
         map: &mut HashMap<ResourcePath, UV>,
         allocator: &mut AtlasAllocator,
-        animated_textures: &mut Vec<schemas::texture::TextureAnimation>,
+        animated_textures: &mut Vec<AnimatedSprite>,
+        animated_texture_offsets: &mut HashMap<ResourcePath, u32>,
         path: &ResourcePath,
-        image_bytes: &[u8],
+        image: &DynamicImage,
         resource_provider: &dyn ResourceProvider,
-    ) {
-        let image = image::load_from_memory(image_bytes).unwrap();
+    ) -> Result<(), AtlasError> {
+        let size = size2(image.width() as i32, image.height() as i32);
+
+        let allocation = loop {
+            if let Some(allocation) = allocator.allocate(size) {
+                break allocation;
+            }
+
+            let current_size = image_buffer.width();
+
+            if current_size >= self.max_size {
+                return Err(AtlasError::OutOfSpace(path.clone()));
+            }
+
+            //Growing keeps every allocation where it is, so only the image has to be copied over
+            let new_size = (current_size * 2).min(self.max_size);
+            allocator.grow(size2(new_size as i32, new_size as i32));
 
-        let allocation = allocator
-            .allocate(Size2D::new(image.width() as i32, image.height() as i32))
-            .unwrap();
+            let mut grown = ImageBuffer::new(new_size, new_size);
+            grown.copy_from(&*image_buffer, 0, 0).unwrap();
+            *image_buffer = grown;
+        };
 
         overlay(
             image_buffer,
-            &image,
+            image,
             allocation.rectangle.min.x as i64,
             allocation.rectangle.min.y as i64,
         );
 
-        let mcmeta_path = path.append(".mcmeta");
+        let animation = resource_provider
+            .get_string(&path.texture_meta_file())
+            .and_then(|string| serde_json::from_str::<TextureMetadata>(&string).ok())
+            .and_then(|metadata| metadata.animation)
+            .and_then(|animation| AnimatedSprite::new(animation, image.width(), image.height()));
 
-        let mcmeta = resource_provider
-            .get_string(&mcmeta_path)
-            .and_then(|string| serde_json::from_str::<schemas::texture::Texture>(&string).ok());
+        let (width, height) = match animation {
+            Some(animation) if animated_textures.len() < MAX_ANIMATIONS => {
+                let frame_size = animation.frame_size;
 
-        if let Some(texture) = mcmeta {
-            if let Some(animation) = texture.animation {
-                animated_textures.push(animation)
+                animated_textures.push(animation);
+                animated_texture_offsets.insert(path.clone(), animated_textures.len() as u32);
+
+                frame_size
             }
-        }
+            Some(animation) => {
+                log::warn!("Too many animated textures, {} won't be animated", path.0);
+
+                animation.frame_size
+            }
+            None => (image.width(), image.height()),
+        };
 
         map.insert(
             path.clone(),
@@ -176,43 +369,163 @@ impl Atlas {
                     allocation.rectangle.min.y as u16,
                 ),
                 (
-                    allocation.rectangle.max.x as u16,
-                    allocation.rectangle.max.y as u16,
+                    //Allocations are padded to the mip alignment, so the texture may not fill it
+                    (allocation.rectangle.min.x as u32 + width) as u16,
+                    (allocation.rectangle.min.y as u32 + height) as u16,
                 ),
             ),
         );
+
+        Ok(())
     }
 
-    /// Upload the atlas texture to the GPU. If the Atlas has to resize the texture on the GPU, then the bindable_texture that this struct provides may
-    /// become obsolete if you .load() the BindableTexture before calling upload(), so you should get the BindableTexture after calling this function and not before-hand.
+    /// Upload the atlas texture to the GPU. If the atlas has grown since the last upload, a new texture is created
+    /// in place of [Atlas::texture], and anything bound to the old one has to be bound again, see
+    /// [RenderGraph::update_atlas_textures](crate::render::graph::RenderGraph::update_atlas_textures).
     /// Returns true if the atlas was resized.
     pub fn upload(&self, wm: &WmRenderer) -> bool {
-        wm.gpu.queue.write_texture(
-            self.texture.texture.as_image_copy(),
-            self.image.read().as_raw(),
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * self.size),
-                rows_per_image: Some(self.size),
-            },
-            Extent3d {
-                width: self.size,
-                height: self.size,
-                depth_or_array_layers: 1,
-            },
-        );
+        let image = self.image.read();
+        let texture = self.texture.load();
+        let mips = mip_chain(&image, self.mip_levels);
+
+        if texture.texture.width() != image.width() || texture.texture.height() != image.height() {
+            self.texture.store(Arc::new(
+                TextureAndView::from_mip_chain(&wm.gpu, &mips, None).unwrap(),
+            ));
+
+            return true;
+        }
+
+        texture.write_mip_chain(&wm.gpu.queue, &mips);
 
         false
     }
 
+    /// The frame offsets of every animated texture at a tick, indexed by animation index. Index 0 is left at zero
+    /// for textures which aren't animated.
+    pub fn animation_frames(&self, tick: u64) -> Vec<AnimatedUV> {
+        std::iter::once(AnimatedUV::default())
+            .chain(
+                self.animated_textures
+                    .read()
+                    .iter()
+                    .map(|animation| animation.at(tick)),
+            )
+            .collect()
+    }
+
+    /// Where every texture is in the atlas, sorted by path so that layouts can be diffed between builds and
+    /// resource packs
+    pub fn layout(&self) -> AtlasLayout {
+        //Locked in the same order as in allocate
+        let map = self.uv_map.read();
+        let animated_textures = self.animated_textures.read();
+        let animated_texture_offsets = self.animated_texture_offsets.read();
+
+        let mut sprites = map
+            .iter()
+            .map(|(path, uv)| {
+                let animation_index = animated_texture_offsets.get(path).copied().unwrap_or(0);
+
+                SpriteLayout {
+                    path: path.0.clone(),
+                    x: uv.0 .0 as u32,
+                    y: uv.0 .1 as u32,
+                    width: (uv.1 .0 - uv.0 .0) as u32,
+                    height: (uv.1 .1 - uv.0 .1) as u32,
+                    animation_index,
+                    animation: animation_index
+                        .checked_sub(1)
+                        .and_then(|index| animated_textures.get(index as usize))
+                        .map(|animation| animation.metadata.clone()),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        sprites.sort_by(|a, b| a.path.cmp(&b.path));
+
+        AtlasLayout {
+            size: self.size(),
+            mip_levels: self.mip_levels,
+            sprites,
+        }
+    }
+
+    /// Writes the atlas image to a PNG and its [layout](Atlas::layout) to a JSON file, to debug textures which look
+    /// wrong
+    pub fn dump(&self, png: &Path, json: &Path) -> Result<(), AtlasDumpError> {
+        self.image.read().save(png).map_err(AtlasDumpError::Image)?;
+
+        serde_json::to_writer_pretty(BufWriter::new(File::create(json)?), &self.layout())
+            .map_err(AtlasDumpError::Json)
+    }
+
+    /// Whether every pixel in a region of the atlas is fully opaque
+    pub fn is_opaque(&self, uv: UV) -> bool {
+        let image = self.image.read();
+
+        let (x0, x1) = (uv.0 .0.min(uv.1 .0) as u32, uv.0 .0.max(uv.1 .0) as u32);
+        let (y0, y1) = (uv.0 .1.min(uv.1 .1) as u32, uv.0 .1.max(uv.1 .1) as u32);
+
+        (y0..y1.min(image.height()))
+            .all(|y| (x0..x1.min(image.width())).all(|x| image.get_pixel(x, y).0[3] == 255))
+    }
+
+    /// Removes every texture and shrinks the atlas back to its initial size
     pub fn clear(&self) {
-        self.allocator.write().clear();
+        *self.allocator.write() = Self::create_allocator(self.size, self.mip_levels);
+        self.uv_map.write().clear();
         self.animated_texture_offsets.write().clear();
         self.animated_textures.write().clear();
         *self.image.write() = ImageBuffer::new(self.size, self.size);
     }
 }
 
+/// The image followed by `mip_levels` mips, each half the size of the one before
+fn mip_chain(image: &RgbaImage, mip_levels: u32) -> Vec<RgbaImage> {
+    let mut mips = vec![image.clone()];
+
+    for _ in 0..mip_levels {
+        let mip = downsample(mips.last().unwrap());
+        mips.push(mip);
+    }
+
+    mips
+}
+
+/// Halves an image, weighting the colour of each 2x2 block by alpha like vanilla does. Otherwise the transparent
+/// pixels around cutout textures, which are usually black, would darken their edges in the lower mips.
+fn downsample(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = (image.width(), image.height());
+
+    RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let mut colour = [0u32; 3];
+        let mut alpha = 0u32;
+
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = image.get_pixel((x * 2 + dx).min(width - 1), (y * 2 + dy).min(height - 1));
+            let a = pixel[3] as u32;
+
+            colour
+                .iter_mut()
+                .zip(pixel.0)
+                .for_each(|(sum, channel)| *sum += channel as u32 * a);
+            alpha += a;
+        }
+
+        if alpha == 0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+
+        Rgba([
+            (colour[0] / alpha) as u8,
+            (colour[1] / alpha) as u8,
+            (colour[2] / alpha) as u8,
+            (alpha / 4) as u8,
+        ])
+    })
+}
+
 /// Stores uploaded textures which will be automatically updated whenever necessary
 #[derive(Debug)]
 pub struct TextureManager {
@@ -230,7 +543,8 @@ impl TextureManager {
             address_mode_w: wgpu::AddressMode::Repeat,
             mag_filter: wgpu::FilterMode::Nearest,
             min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            //Blends between mips like vanilla's GL_NEAREST_MIPMAP_LINEAR
+            mipmap_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
 
@@ -239,59 +553,21 @@ impl TextureManager {
             atlases: RwLock::new(HashMap::new()),
         }
     }
-}
 
-#[repr(C)]
-#[derive(Copy, Clone, Zeroable, Pod)]
-#[allow(unused)]
-struct AnimatedUV {
-    pub uv_1: [f32; 2],
-    pub uv_2: [f32; 2],
-    pub blend: f32,
-    pub padding: f32,
-}
+    /// [Dumps](Atlas::dump) every atlas into a directory as `<name>.png` and `<name>.json`, where the name is the
+    /// atlas' name with anything but letters, digits, `-` and `_` replaced by `_`
+    pub fn dump_atlases(&self, directory: &Path) -> Result<(), AtlasDumpError> {
+        fs::create_dir_all(directory)?;
+
+        for (name, atlas) in self.atlases.read().iter() {
+            let name = name.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_");
+
+            atlas.dump(
+                &directory.join(format!("{name}.png")),
+                &directory.join(format!("{name}.json")),
+            )?;
+        }
 
-// impl AnimatedTexture {
-//     pub fn new(width: u32, height: u32, real_width: f32, animation: AnimationData) -> Self {
-//         Self {
-//             width,
-//             height,
-//             frame_size: width,
-//             real_width,
-//             real_frame_size: real_width,
-//             animation,
-//             frame_count: height / width,
-//             subframe: 0,
-//         }
-//     }
-
-//     pub fn get_frame_size(&self) -> u32 {
-//         self.frame_size
-//     }
-
-//     pub fn update(&self, subframe: u32) -> [f32; 5] {
-
-//         //Due to padding in the buffer, some of these elements are always left as 0.0
-//         let mut out = [0.0; 5];
-//         let mut current_frame = (subframe / self.animation.frame_time) % self.frame_count;
-
-//         if self.animation.frames.is_some() { //if custom frame order is present translate to that
-//             current_frame = self.animation.frames.as_ref().unwrap()[current_frame as usize];
-//         }
-
-//         out[1] = self.real_frame_size * (current_frame as f32);
-
-//         if self.animation.interpolate {
-//             let mut next_frame = ((subframe / self.animation.frame_time) + 1) % self.frame_count;
-
-//             if self.animation.frames.is_some() { //if custom frame order is present translate to that
-//                 next_frame = self.animation.frames.as_ref().unwrap()[next_frame as usize];
-//             }
-
-//             out[3] = self.real_frame_size * (next_frame as f32);
-//             out[4] = ((subframe % self.animation.frame_time) as f32) / (self.animation.frame_time as f32);
-//         }
-
-//         out
-//     }
-// }
+        Ok(())
+    }
+}
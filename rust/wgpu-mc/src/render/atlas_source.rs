@@ -0,0 +1,306 @@
+//! Atlas definitions, which since 1.19.3 decide up front which sprites go into each atlas. They're read from
+//! `assets/<namespace>/atlases/<atlas>.json`, and every pack's copy of a definition applies on top of the ones
+//! below it.
+
+use std::collections::HashMap;
+
+use image::{DynamicImage, Rgba};
+use indexmap::IndexMap;
+use linked_hash_map::LinkedHashMap;
+use rayon::prelude::*;
+use serde_derive::Deserialize;
+
+use crate::mc::resource::pack::{FilterPattern, ResourceFilter};
+use crate::mc::resource::{ResourcePath, ResourceProvider};
+use crate::render::atlas::{Atlas, AtlasError};
+use crate::render::pipeline::BLOCK_ATLAS;
+
+/// The atlases vanilla defines, used when the [ResourceProvider] can't list the `atlases` directory
+pub const VANILLA_ATLASES: [&str; 14] = [
+    "armor_trims",
+    "banner_patterns",
+    "beds",
+    "blocks",
+    "chests",
+    "decorated_pot",
+    "gui",
+    "map_decorations",
+    "mob_effects",
+    "paintings",
+    "particles",
+    "shield_patterns",
+    "shulker_boxes",
+    "signs",
+];
+
+/// The errors of each atlas which failed to load completely, by atlas name
+pub type AtlasReport = IndexMap<String, Vec<AtlasSourceError>>;
+
+#[derive(Debug)]
+pub enum AtlasSourceError {
+    InvalidDefinition(ResourcePath, serde_json::Error),
+    /// The pattern of a `filter` source isn't a valid regex
+    InvalidFilter(regex::Error),
+    MissingTexture(ResourcePath),
+    InvalidImage(ResourcePath, image::ImageError),
+    /// A palette of a `paletted_permutations` source doesn't have as many pixels as its key
+    PaletteMismatch(ResourcePath),
+    Atlas(AtlasError),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AtlasDefinition {
+    pub sources: Vec<AtlasSource>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum AtlasSource {
+    /// Every texture in `textures/<source>/`, named `<prefix><path relative to source>`
+    #[serde(rename = "directory", alias = "minecraft:directory")]
+    Directory { source: String, prefix: String },
+    /// One texture, named `sprite` or the texture itself
+    #[serde(rename = "single", alias = "minecraft:single")]
+    Single {
+        resource: String,
+        sprite: Option<String>,
+    },
+    /// Removes the sprites added so far which match the pattern
+    #[serde(rename = "filter", alias = "minecraft:filter")]
+    Filter { pattern: FilterPattern },
+    /// Every texture recoloured with every palette, named `<texture><separator><permutation>`
+    #[serde(
+        rename = "paletted_permutations",
+        alias = "minecraft:paletted_permutations"
+    )]
+    PalettedPermutations {
+        textures: Vec<String>,
+        palette_key: String,
+        permutations: LinkedHashMap<String, String>,
+        #[serde(default = "default_separator")]
+        separator: String,
+    },
+    /// Sources like `unstitch` which wgpu-mc doesn't support yet
+    #[serde(other)]
+    Unsupported,
+}
+
+fn default_separator() -> String {
+    "_".into()
+}
+
+/// The name of the [Atlas] an atlas definition fills, which is [BLOCK_ATLAS] for `minecraft:atlases/blocks.json`
+pub fn atlas_name(definition: &ResourcePath) -> String {
+    match &definition.0[..] {
+        "minecraft:atlases/blocks.json" => BLOCK_ATLAS.into(),
+        name => name.strip_suffix(".json").unwrap_or(name).into(),
+    }
+}
+
+#[derive(Debug)]
+enum Sprite {
+    File(ResourcePath),
+    /// A texture with the colours of `palette_key` swapped for the colours in the same position in `palette`
+    Permutation {
+        texture: ResourcePath,
+        palette_key: ResourcePath,
+        palette: ResourcePath,
+    },
+}
+
+fn load_image(
+    resource_provider: &dyn ResourceProvider,
+    path: &ResourcePath,
+) -> Result<DynamicImage, AtlasSourceError> {
+    let bytes = resource_provider
+        .get_bytes(path)
+        .ok_or_else(|| AtlasSourceError::MissingTexture(path.clone()))?;
+
+    image::load_from_memory(&bytes)
+        .map_err(|error| AtlasSourceError::InvalidImage(path.clone(), error))
+}
+
+impl Sprite {
+    fn load(
+        &self,
+        resource_provider: &dyn ResourceProvider,
+    ) -> Result<DynamicImage, AtlasSourceError> {
+        let (texture, palette_key, palette) = match self {
+            Sprite::File(path) => return load_image(resource_provider, path),
+            Sprite::Permutation {
+                texture,
+                palette_key,
+                palette,
+            } => (texture, palette_key, palette),
+        };
+
+        let keys = load_image(resource_provider, palette_key)?.into_rgba8();
+        let values = load_image(resource_provider, palette)?.into_rgba8();
+
+        if keys.width() * keys.height() != values.width() * values.height() {
+            return Err(AtlasSourceError::PaletteMismatch(palette.clone()));
+        }
+
+        //Matched on colour alone, like vanilla
+        let palette = keys
+            .pixels()
+            .zip(values.pixels())
+            .map(|(key, value)| ([key[0], key[1], key[2]], *value))
+            .collect::<HashMap<_, _>>();
+
+        let mut image = load_image(resource_provider, texture)?.into_rgba8();
+
+        image
+            .pixels_mut()
+            .filter(|pixel| pixel[3] != 0)
+            .for_each(|pixel| {
+                let Rgba([r, g, b, a]) = *pixel;
+                let mapped = palette
+                    .get(&[r, g, b])
+                    .copied()
+                    .unwrap_or(Rgba([r, g, b, 255]));
+
+                *pixel = Rgba([
+                    mapped[0],
+                    mapped[1],
+                    mapped[2],
+                    (a as u16 * mapped[3] as u16 / 255) as u8,
+                ]);
+            });
+
+        Ok(DynamicImage::ImageRgba8(image))
+    }
+}
+
+/// The sprites of an atlas, collected from its sources in order
+#[derive(Debug, Default)]
+pub struct AtlasBuilder {
+    sprites: IndexMap<ResourcePath, Sprite>,
+}
+
+impl AtlasBuilder {
+    /// Applies every copy of an atlas definition, lowest priority first. `textures` is every texture file which
+    /// `directory` sources may pick from, see [ResourceProvider::list_resources].
+    pub fn load(
+        resource_provider: &dyn ResourceProvider,
+        definition: &ResourcePath,
+        textures: &[ResourcePath],
+    ) -> Result<Self, AtlasSourceError> {
+        let mut builder = Self::default();
+
+        for bytes in resource_provider.get_all_bytes(definition) {
+            let definition: AtlasDefinition = serde_json::from_slice(&bytes)
+                .map_err(|error| AtlasSourceError::InvalidDefinition(definition.clone(), error))?;
+
+            definition
+                .sources
+                .iter()
+                .try_for_each(|source| builder.add_source(source, textures))?;
+        }
+
+        Ok(builder)
+    }
+
+    pub fn add_source(
+        &mut self,
+        source: &AtlasSource,
+        textures: &[ResourcePath],
+    ) -> Result<(), AtlasSourceError> {
+        match source {
+            AtlasSource::Directory { source, prefix } => {
+                let directory = format!("textures/{source}/");
+
+                for texture in textures {
+                    let Some(name) = texture
+                        .path()
+                        .strip_prefix(&directory)
+                        .and_then(|name| name.strip_suffix(".png"))
+                    else {
+                        continue;
+                    };
+
+                    self.sprites.insert(
+                        ResourcePath::from((texture.namespace(), &format!("{prefix}{name}")[..])),
+                        Sprite::File(texture.clone()),
+                    );
+                }
+            }
+            AtlasSource::Single { resource, sprite } => {
+                let resource = ResourcePath::from(resource);
+                let sprite = sprite
+                    .as_ref()
+                    .map_or_else(|| resource.clone(), ResourcePath::from);
+
+                self.sprites
+                    .insert(sprite, Sprite::File(resource.texture_file()));
+            }
+            AtlasSource::Filter { pattern } => {
+                let filter =
+                    ResourceFilter::new(pattern).map_err(AtlasSourceError::InvalidFilter)?;
+
+                self.sprites
+                    .retain(|sprite, _| !filter.matches(sprite.namespace(), sprite.path()));
+            }
+            AtlasSource::PalettedPermutations {
+                textures,
+                palette_key,
+                permutations,
+                separator,
+            } => {
+                let palette_key = ResourcePath::from(palette_key).texture_file();
+
+                for texture in textures {
+                    let texture = ResourcePath::from(texture);
+
+                    for (suffix, palette) in permutations {
+                        self.sprites.insert(
+                            texture.append(&format!("{separator}{suffix}")),
+                            Sprite::Permutation {
+                                texture: texture.texture_file(),
+                                palette_key: palette_key.clone(),
+                                palette: ResourcePath::from(palette).texture_file(),
+                            },
+                        );
+                    }
+                }
+            }
+            AtlasSource::Unsupported => {}
+        }
+
+        Ok(())
+    }
+
+    /// The names of the sprites collected so far
+    pub fn sprites(&self) -> impl Iterator<Item = &ResourcePath> {
+        self.sprites.keys()
+    }
+
+    /// Loads every sprite and allocates it in the atlas, sorted by name so that the layout is the same between
+    /// runs. Sprites which fail to load are left out and returned as errors.
+    pub fn build(
+        mut self,
+        atlas: &Atlas,
+        resource_provider: &dyn ResourceProvider,
+    ) -> Vec<AtlasSourceError> {
+        self.sprites.sort_by(|a, _, b, _| a.0.cmp(&b.0));
+
+        let images = self
+            .sprites
+            .par_iter()
+            .map(|(name, sprite)| Ok((name, sprite.load(resource_provider)?)))
+            .collect::<Vec<Result<_, AtlasSourceError>>>();
+
+        images
+            .into_iter()
+            .filter_map(|image| {
+                image
+                    .and_then(|(name, image)| {
+                        atlas
+                            .allocate_images([(name, &image)], resource_provider)
+                            .map_err(AtlasSourceError::Atlas)
+                    })
+                    .err()
+            })
+            .collect()
+    }
+}
@@ -1,4 +1,4 @@
-use glam::ivec3;
+use glam::{ivec3, IVec3};
 use linked_hash_map::LinkedHashMap;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -11,11 +11,11 @@ use wgpu::{
     StoreOp,
 };
 
-use crate::mc::chunk::RenderLayer;
 use crate::mc::entity::InstanceVertex;
 use crate::mc::resource::ResourcePath;
 use crate::mc::Scene;
 use crate::render::entity::EntityVertex;
+use crate::render::indirect::{terrain_matrices, SectionCulling};
 use crate::render::pipeline::{QuadVertex, BLOCK_ATLAS};
 use crate::render::shader::WgslShader;
 use crate::render::shaderpack::{
@@ -132,6 +132,8 @@ pub struct RenderGraph {
     pub config: ShaderPackConfig,
     pub pipelines: LinkedHashMap<String, BoundPipeline>,
     pub resources: HashMap<String, ResourceBacking>,
+    /// Culls sections on the GPU for `@geo_terrain_indirect` pipelines, if the device supports it
+    pub section_culling: Option<SectionCulling>,
 }
 
 impl RenderGraph {
@@ -168,7 +170,12 @@ impl RenderGraph {
                     }
                     BindGroupDef::Resource(resource) => {
                         match (&resource[..], &custom_bind_groups) {
-                            ("@bg_ssbo_chunks", _) => wm.bind_group_layouts.get("ssbo").unwrap(),
+                            ("@bg_ssbo_chunks" | "@bg_ssbo_animated", _) => {
+                                wm.bind_group_layouts.get("ssbo").unwrap()
+                            }
+                            ("@bg_section_draws", _) => {
+                                wm.bind_group_layouts.get("section_draws").unwrap()
+                            }
                             ("@bg_entity", _) => wm.bind_group_layouts.get("entity").unwrap(),
                             (_, Some(custom)) => {
                                 if let Some(entry) = custom.get(resource) {
@@ -258,7 +265,10 @@ impl RenderGraph {
                 });
 
             let shader = WgslShader::init(
-                &ResourcePath(format!("wgpu_mc:shaders/{}.wgsl", pipeline_name)),
+                &ResourcePath(format!(
+                    "wgpu_mc:shaders/{}.wgsl",
+                    pipeline_config.shader.as_ref().unwrap_or(pipeline_name)
+                )),
                 &*wm.mc.resource_provider,
                 &wm.gpu.device,
                 "frag".into(),
@@ -267,7 +277,7 @@ impl RenderGraph {
             .unwrap();
 
             let vertex_buffer = match &pipeline_config.geometry[..] {
-                "@geo_terrain" => None,
+                "@geo_terrain" | "@geo_terrain_indirect" => None,
                 "@geo_entities" => Some(vec![EntityVertex::desc(), InstanceVertex::desc()]),
                 "@geo_quad" => Some(vec![QuadVertex::desc()]),
                 "@geo_sun_moon" => Some(vec![SunMoonVertex::desc()]),
@@ -314,7 +324,7 @@ impl RenderGraph {
                         depth_stencil: pipeline_config.depth.as_ref().map(|_| {
                             wgpu::DepthStencilState {
                                 format: wgpu::TextureFormat::Depth32Float,
-                                depth_write_enabled: true,
+                                depth_write_enabled: pipeline_config.depth_write,
                                 depth_compare: wgpu::CompareFunction::Less,
                                 stencil: wgpu::StencilState::default(),
                                 bias: Default::default(),
@@ -420,6 +430,7 @@ impl RenderGraph {
             config,
             pipelines: LinkedHashMap::new(),
             resources,
+            section_culling: None,
         };
 
         let atlases = wm.mc.texture_manager.atlases.read();
@@ -429,7 +440,7 @@ impl RenderGraph {
         graph.resources.extend([
             (
                 "@texture_block_atlas".into(),
-                ResourceBacking::Texture2D(block_atlas.texture.clone()),
+                ResourceBacking::Texture2D(block_atlas.texture.load_full()),
             ),
             (
                 "@sampler".into(),
@@ -439,9 +450,87 @@ impl RenderGraph {
 
         graph.create_pipelines(wm, custom_bind_groups, custom_geometry);
 
+        if graph.draws_terrain_indirectly() && terrain_matrices(&graph.resources).is_some() {
+            graph.section_culling = SectionCulling::new(wm);
+        }
+
         graph
     }
 
+    /// Binds the block atlas texture again if the atlas has grown since it was last bound, see [Atlas::upload].
+    /// Should be called before rendering each frame.
+    ///
+    /// [Atlas::upload]: crate::render::atlas::Atlas::upload
+    pub fn update_atlas_textures(&mut self, wm: &WmRenderer) {
+        let Some(texture) = wm
+            .mc
+            .texture_manager
+            .atlases
+            .read()
+            .get(BLOCK_ATLAS)
+            .map(|atlas| atlas.texture.load_full())
+        else {
+            return;
+        };
+
+        if let Some(ResourceBacking::Texture2D(current)) =
+            self.resources.get("@texture_block_atlas")
+        {
+            if Arc::ptr_eq(current, &texture) {
+                return;
+            }
+        }
+
+        self.resources.insert(
+            "@texture_block_atlas".into(),
+            ResourceBacking::Texture2D(texture),
+        );
+
+        self.rebind(wm, "@texture_block_atlas");
+    }
+
+    /// Recreates the bind groups which use a resource, after it has been replaced
+    fn rebind(&mut self, wm: &WmRenderer, resource_id: &str) {
+        for bound_pipeline in self.pipelines.values_mut() {
+            for ((slot, wm_bind_group), (_, def)) in bound_pipeline
+                .bind_groups
+                .iter_mut()
+                .zip(&bound_pipeline.config.bind_groups)
+            {
+                let BindGroupDef::Entries(entries) = def else {
+                    continue;
+                };
+
+                if !entries.values().any(|id| id == resource_id) {
+                    continue;
+                }
+
+                let entries = entries
+                    .iter()
+                    .flat_map(|(index, resource_id)| {
+                        self.resources[resource_id].get_bind_group_entries(*index as u32)
+                    })
+                    .collect::<Vec<wgpu::BindGroupEntry>>();
+
+                *wm_bind_group = WmBindGroup::Custom(wm.gpu.device.create_bind_group(
+                    &wgpu::BindGroupDescriptor {
+                        label: None,
+                        layout: &bound_pipeline.pipeline.get_bind_group_layout(*slot),
+                        entries: &entries,
+                    },
+                ));
+            }
+        }
+    }
+
+    fn draws_terrain_indirectly(&self) -> bool {
+        self.config
+            .pipelines
+            .pipelines
+            .values()
+            .any(|pipeline| pipeline.geometry == "@geo_terrain_indirect")
+    }
+
     pub fn render(
         &self,
         wm: &WmRenderer,
@@ -456,6 +545,27 @@ impl RenderGraph {
 
         let mut should_clear_depth = true;
 
+        scene.section_storage.write().update_reachable_sections();
+
+        if self.draws_terrain_indirectly() {
+            let mut section_draws = scene.section_draws.lock();
+
+            section_draws.update(
+                wm,
+                &scene.section_storage.read(),
+                *scene.camera_section_pos.read(),
+            );
+
+            if let Some(section_culling) = &self.section_culling {
+                section_culling.cull(
+                    wm,
+                    encoder,
+                    &section_draws,
+                    terrain_matrices(&self.resources).unwrap(),
+                );
+            }
+        }
+
         for (pipeline_name, bound_pipeline) in &self.pipelines {
             let pipeline_config = self.config.pipelines.pipelines.get(pipeline_name).unwrap();
 
@@ -532,16 +642,25 @@ impl RenderGraph {
                 "@geo_terrain" => {
                     render_pass.set_pipeline(&bound_pipeline.pipeline);
 
+                    let chunk_buffer = arena.alloc(scene.chunk_buffer.load_full());
+                    let animated_bind_group =
+                        arena.alloc(wm.mc.animated_block_bind_group.load_full());
+
                     for (index, bind_group) in bound_pipeline.bind_groups.iter() {
                         match bind_group {
                             WmBindGroup::Resource(name) => match &name[..] {
                                 "@bg_ssbo_chunks" => {
                                     render_pass.set_bind_group(
                                         *index,
-                                        &scene.chunk_buffer.bind_group,
+                                        &chunk_buffer.bind_group,
                                         &[],
                                     );
                                 }
+                                "@bg_ssbo_animated" => {
+                                    if let Some(bind_group) = &**animated_bind_group {
+                                        render_pass.set_bind_group(*index, bind_group, &[]);
+                                    }
+                                }
                                 _ => unimplemented!(),
                             },
                             WmBindGroup::Custom(bind_group) => {
@@ -550,42 +669,119 @@ impl RenderGraph {
                         }
                     }
 
-                    render_pass.set_index_buffer(
-                        scene.chunk_buffer.buffer.slice(..),
-                        wgpu::IndexFormat::Uint32,
-                    );
+                    render_pass
+                        .set_index_buffer(chunk_buffer.buffer.slice(..), wgpu::IndexFormat::Uint32);
 
                     let sections = scene.section_storage.write();
                     let camera_pos = *scene.camera_section_pos.read();
                     for (pos, section) in sections.iter() {
                         let rel_pos = ivec3(pos.x - camera_pos.x, pos.y, pos.z - camera_pos.y);
-                        let a: Vec3<f32> =
-                            [rel_pos.x as f32, rel_pos.y as f32, rel_pos.z as f32].into();
-                        let b: Vec3<f32> = a + Vec3::new(1.0, 1.0, 1.0);
 
-                        let bounds: AABB<f32> =
-                            AABB::new((a * 16.0).into_array(), (b * 16.0).into_array());
-
-                        if !bounds.coherent_test_against_frustum(frustum, 0).0 {
+                        if !sections.is_reachable(pos) || !section_in_frustum(rel_pos, frustum) {
                             continue;
                         }
-                        if let Some(layer) = &section.layers[RenderLayer::Solid as usize] {
-                            let mut pc: HashMap<String, (Vec<u8>, ShaderStages)> = HashMap::new();
-                            //println!("draw {pos}");
-                            pc.insert(
-                                "@pc_section_position".to_string(),
-                                (
-                                    bytemuck::cast_slice(&rel_pos.to_array()).to_vec(),
-                                    ShaderStages::VERTEX,
-                                ),
-                            );
-                            set_push_constants(pipeline_config, &mut render_pass, Some(pc));
-                            render_pass.draw_indexed(
-                                layer.index_range.clone(),
-                                0,
-                                layer.vertex_range.start..layer.vertex_range.start + 1,
+                        let mut pc: HashMap<String, (Vec<u8>, ShaderStages)> = HashMap::new();
+                        pc.insert(
+                            "@pc_section_position".to_string(),
+                            (
+                                bytemuck::cast_slice(&rel_pos.to_array()).to_vec(),
+                                ShaderStages::VERTEX,
+                            ),
+                        );
+                        set_push_constants(pipeline_config, &mut render_pass, Some(pc));
+
+                        for render_layer in &pipeline_config.layers {
+                            if let Some(Some(layer)) = section.layers.get(*render_layer as usize) {
+                                render_pass.draw_indexed(
+                                    layer.index_range.clone(),
+                                    0,
+                                    layer.vertex_range.start..layer.vertex_range.start + 1,
+                                );
+                            }
+                        }
+                    }
+                }
+                "@geo_terrain_indirect" => {
+                    render_pass.set_pipeline(&bound_pipeline.pipeline);
+
+                    let chunk_buffer = arena.alloc(scene.chunk_buffer.load_full());
+                    let animated_bind_group =
+                        arena.alloc(wm.mc.animated_block_bind_group.load_full());
+                    let section_draws = scene.section_draws.lock();
+                    let draws_bind_group = arena.alloc(section_draws.bind_group.clone());
+
+                    for (index, bind_group) in bound_pipeline.bind_groups.iter() {
+                        match bind_group {
+                            WmBindGroup::Resource(name) => match &name[..] {
+                                "@bg_ssbo_chunks" => {
+                                    render_pass.set_bind_group(
+                                        *index,
+                                        &chunk_buffer.bind_group,
+                                        &[],
+                                    );
+                                }
+                                "@bg_ssbo_animated" => {
+                                    if let Some(bind_group) = &**animated_bind_group {
+                                        render_pass.set_bind_group(*index, bind_group, &[]);
+                                    }
+                                }
+                                "@bg_section_draws" => {
+                                    render_pass.set_bind_group(*index, &**draws_bind_group, &[]);
+                                }
+                                _ => unimplemented!(),
+                            },
+                            WmBindGroup::Custom(bind_group) => {
+                                render_pass.set_bind_group(*index, bind_group, &[]);
+                            }
+                        }
+                    }
+
+                    render_pass
+                        .set_index_buffer(chunk_buffer.buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+                    if self.section_culling.is_some() {
+                        let command_buffer = arena.alloc(section_draws.command_buffer.clone());
+                        let count_buffer = arena.alloc(section_draws.count_buffer.clone());
+
+                        for render_layer in &pipeline_config.layers {
+                            render_pass.multi_draw_indexed_indirect_count(
+                                command_buffer,
+                                section_draws.command_offset(*render_layer),
+                                count_buffer,
+                                section_draws.count_offset(*render_layer),
+                                section_draws.capacity,
                             );
                         }
+                    } else {
+                        let camera_pos = *scene.camera_section_pos.read();
+
+                        let visible: Vec<bool> = section_draws
+                            .draws
+                            .iter()
+                            .map(|draw| {
+                                let pos = IVec3::from_array(draw.position);
+                                let rel_pos =
+                                    ivec3(pos.x - camera_pos.x, pos.y, pos.z - camera_pos.y);
+
+                                section_in_frustum(rel_pos, frustum)
+                            })
+                            .collect();
+
+                        for render_layer in &pipeline_config.layers {
+                            for (index, draw) in section_draws.draws.iter().enumerate() {
+                                if !visible[index] || draw.layer != *render_layer as u32 {
+                                    continue;
+                                }
+
+                                //The instance index is how the shader finds the draw's data
+                                let index = index as u32;
+                                render_pass.draw_indexed(
+                                    draw.first_index..draw.first_index + draw.index_count,
+                                    0,
+                                    index..index + 1,
+                                );
+                            }
+                        }
                     }
                 }
                 "@geo_entities" => {
@@ -816,6 +1012,15 @@ fn get_environmental_push_constants(scene: &Scene) -> HashMap<String, (Vec<u8>,
     pc
 }
 
+fn section_in_frustum(rel_pos: IVec3, frustum: &Frustum<f32>) -> bool {
+    let a: Vec3<f32> = [rel_pos.x as f32, rel_pos.y as f32, rel_pos.z as f32].into();
+    let b: Vec3<f32> = a + Vec3::new(1.0, 1.0, 1.0);
+
+    let bounds: AABB<f32> = AABB::new((a * 16.0).into_array(), (b * 16.0).into_array());
+
+    bounds.coherent_test_against_frustum(frustum, 0).0
+}
+
 pub fn set_push_constants(
     pipeline: &PipelineConfig,
     render_pass: &mut wgpu::RenderPass,
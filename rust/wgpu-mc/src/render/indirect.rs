@@ -0,0 +1,349 @@
+//! GPU-driven terrain rendering
+//!
+//! Pipelines using the `@geo_terrain_indirect` geometry don't get a push constant per section. Instead, every
+//! layer of every section gets a [SectionDrawData] entry in a storage buffer, which the terrain shader reads
+//! through its instance index. When the device supports [INDIRECT_CULLING_FEATURES], a compute pass frustum culls
+//! the entries and writes a `DrawIndexedIndirect` command for each visible one, so that each layer is drawn with a
+//! single `multi_draw_indexed_indirect_count`. Otherwise, the sections are culled and drawn one by one on the CPU.
+
+use std::sync::Arc;
+
+use glam::{IVec2, IVec3};
+
+use crate::mc::chunk::{RenderLayer, SectionStorage};
+use crate::render::graph::ResourceBacking;
+use crate::WmRenderer;
+
+/// Device features needed to cull and draw sections on the GPU
+pub const INDIRECT_CULLING_FEATURES: wgpu::Features =
+    wgpu::Features::MULTI_DRAW_INDIRECT_COUNT.union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+
+/// Size of one `DrawIndexedIndirect` command in bytes
+const DRAW_COMMAND_SIZE: u64 = 20;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// One layer of a section, as read by the culling pass and the terrain shader
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SectionDrawData {
+    /// Section position in section coordinates
+    pub position: [i32; 3],
+    /// The [RenderLayer] of this draw
+    pub layer: u32,
+    /// Start of the section's vertices in the chunk buffer, in 4 byte words
+    pub vertex_offset: u32,
+    pub first_index: u32,
+    pub index_count: u32,
+    pub _padding: u32,
+}
+
+/// Per-frame uniform shared by the culling pass and the terrain shader
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SectionDrawUniform {
+    /// The camera's section on the X and Z axes, section positions are made relative to it
+    camera_section: [i32; 2],
+    draw_count: u32,
+    /// How many commands fit in the region of each layer in the command buffer
+    max_draws: u32,
+}
+
+/// The per-scene buffers behind `@geo_terrain_indirect`
+pub struct SectionDraws {
+    /// A CPU copy of the draws, used when the sections are culled on the CPU
+    pub draws: Vec<SectionDrawData>,
+    pub draw_buffer: Arc<wgpu::Buffer>,
+    pub uniform_buffer: Arc<wgpu::Buffer>,
+    /// [RenderLayer::COUNT] regions of `capacity` commands each, filled in by the culling pass
+    pub command_buffer: Arc<wgpu::Buffer>,
+    /// The number of commands written into each layer's region
+    pub count_buffer: Arc<wgpu::Buffer>,
+    /// Bind group for `@bg_section_draws`: the draws and the uniform
+    pub bind_group: Arc<wgpu::BindGroup>,
+    pub capacity: u32,
+    /// The [SectionStorage::generation] and [SectionStorage::reachable_from] the draws were built from
+    built_from: Option<(u64, Option<(IVec3, u64)>)>,
+}
+
+impl SectionDraws {
+    pub fn new(wm: &WmRenderer) -> Self {
+        Self::with_capacity(wm, 1024)
+    }
+
+    fn with_capacity(wm: &WmRenderer, capacity: u32) -> Self {
+        let device = &wm.gpu.device;
+
+        let draw_buffer = Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("section draws"),
+            size: capacity as u64 * std::mem::size_of::<SectionDrawData>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+
+        let uniform_buffer = Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("section draw uniform"),
+            size: std::mem::size_of::<SectionDrawUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+
+        let command_buffer = Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("section draw commands"),
+            size: RenderLayer::COUNT as u64 * capacity as u64 * DRAW_COMMAND_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        }));
+
+        let count_buffer = Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("section draw counts"),
+            size: RenderLayer::COUNT as u64 * 4,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+
+        let bind_group = Arc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("section draws"),
+            layout: wm.bind_group_layouts.get("section_draws").unwrap(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: draw_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        }));
+
+        Self {
+            draws: Vec::new(),
+            draw_buffer,
+            uniform_buffer,
+            command_buffer,
+            count_buffer,
+            bind_group,
+            capacity,
+            built_from: None,
+        }
+    }
+
+    /// Rebuilds the draws if any section changed or different sections became reachable since the last update,
+    /// and uploads the camera position
+    pub fn update(&mut self, wm: &WmRenderer, storage: &SectionStorage, camera_section: IVec2) {
+        let built_from = (storage.generation(), storage.reachable_from());
+
+        if self.built_from != Some(built_from) {
+            self.draws.clear();
+            self.draws.extend(
+                storage
+                    .iter()
+                    .filter(|(pos, _)| storage.is_reachable(pos))
+                    .flat_map(|(pos, section)| {
+                        section
+                            .layers
+                            .iter()
+                            .enumerate()
+                            .filter_map(move |(layer, ranges)| {
+                                let ranges = ranges.as_ref()?;
+
+                                Some(SectionDrawData {
+                                    position: pos.to_array(),
+                                    layer: layer as u32,
+                                    vertex_offset: ranges.vertex_range.start,
+                                    first_index: ranges.index_range.start,
+                                    index_count: ranges.index_range.end - ranges.index_range.start,
+                                    _padding: 0,
+                                })
+                            })
+                    }),
+            );
+
+            if self.draws.len() > self.capacity as usize {
+                let draws = std::mem::take(&mut self.draws);
+                *self = Self::with_capacity(wm, (draws.len() as u32).next_power_of_two());
+                self.draws = draws;
+            }
+
+            wm.gpu
+                .queue
+                .write_buffer(&self.draw_buffer, 0, bytemuck::cast_slice(&self.draws));
+
+            self.built_from = Some(built_from);
+        }
+
+        wm.gpu.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&SectionDrawUniform {
+                camera_section: camera_section.to_array(),
+                draw_count: self.draws.len() as u32,
+                max_draws: self.capacity,
+            }),
+        );
+    }
+
+    /// Byte offset of a layer's commands in [SectionDraws::command_buffer]
+    pub fn command_offset(&self, layer: RenderLayer) -> u64 {
+        layer as u64 * self.capacity as u64 * DRAW_COMMAND_SIZE
+    }
+
+    /// Byte offset of a layer's command count in [SectionDraws::count_buffer]
+    pub fn count_offset(&self, layer: RenderLayer) -> u64 {
+        layer as u64 * 4
+    }
+}
+
+/// The compute pipeline which frustum culls sections into draw commands
+#[derive(Debug)]
+pub struct SectionCulling {
+    pipeline: wgpu::ComputePipeline,
+    matrix_layout: wgpu::BindGroupLayout,
+    draws_layout: wgpu::BindGroupLayout,
+}
+
+impl SectionCulling {
+    /// Returns `None` if the device doesn't support [INDIRECT_CULLING_FEATURES]
+    pub fn new(wm: &WmRenderer) -> Option<Self> {
+        let device = &wm.gpu.device;
+
+        if !device.features().contains(INDIRECT_CULLING_FEATURES) {
+            return None;
+        }
+
+        let buffer_entry = |binding: u32, ty: wgpu::BufferBindingType| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let matrix_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("section culling matrices"),
+            entries: &[
+                buffer_entry(0, wgpu::BufferBindingType::Uniform),
+                buffer_entry(1, wgpu::BufferBindingType::Uniform),
+                buffer_entry(2, wgpu::BufferBindingType::Uniform),
+            ],
+        });
+
+        let draws_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("section culling draws"),
+            entries: &[
+                buffer_entry(0, wgpu::BufferBindingType::Storage { read_only: true }),
+                buffer_entry(1, wgpu::BufferBindingType::Uniform),
+                buffer_entry(2, wgpu::BufferBindingType::Storage { read_only: false }),
+                buffer_entry(3, wgpu::BufferBindingType::Storage { read_only: false }),
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("section culling"),
+            bind_group_layouts: &[&matrix_layout, &draws_layout],
+            push_constant_ranges: &[],
+        });
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("section culling"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("section_culling.wgsl").into()),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("section culling"),
+            layout: Some(&layout),
+            module: &module,
+            entry_point: "cull",
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Some(Self {
+            pipeline,
+            matrix_layout,
+            draws_layout,
+        })
+    }
+
+    /// Records the culling pass, which has to run before the terrain is drawn. `matrices` are the model, view and
+    /// projection matrix uniforms used by the terrain shader.
+    pub fn cull(
+        &self,
+        wm: &WmRenderer,
+        encoder: &mut wgpu::CommandEncoder,
+        section_draws: &SectionDraws,
+        matrices: [&wgpu::Buffer; 3],
+    ) {
+        let device = &wm.gpu.device;
+
+        let matrix_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.matrix_layout,
+            entries: &[0, 1, 2].map(|binding| wgpu::BindGroupEntry {
+                binding,
+                resource: matrices[binding as usize].as_entire_binding(),
+            }),
+        });
+
+        let draws_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.draws_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: section_draws.draw_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: section_draws.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: section_draws.command_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: section_draws.count_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        encoder.clear_buffer(&section_draws.count_buffer, 0, None);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("section culling"),
+            timestamp_writes: None,
+        });
+
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &matrix_bind_group, &[]);
+        compute_pass.set_bind_group(1, &draws_bind_group, &[]);
+        compute_pass.dispatch_workgroups(
+            (section_draws.draws.len() as u32).div_ceil(WORKGROUP_SIZE),
+            1,
+            1,
+        );
+    }
+}
+
+/// The model, view and projection matrix buffers of a render graph, if it has all three
+pub fn terrain_matrices<'a>(
+    resources: &'a std::collections::HashMap<String, ResourceBacking>,
+) -> Option<[&'a wgpu::Buffer; 3]> {
+    let buffer = |name: &str| match resources.get(name) {
+        Some(ResourceBacking::Buffer(buffer, _)) => Some(&**buffer),
+        _ => None,
+    };
+
+    Some([
+        buffer("@mat4_model")?,
+        buffer("@mat4_view")?,
+        buffer("@mat4_perspective")?,
+    ])
+}
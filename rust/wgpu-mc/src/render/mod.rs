@@ -1,6 +1,9 @@
+pub mod animation;
 pub mod atlas;
+pub mod atlas_source;
 pub mod entity;
 pub mod graph;
+pub mod indirect;
 pub mod pipeline;
 pub mod shader;
 pub mod shaderpack;
@@ -1,3 +1,4 @@
+use glam::{vec2, Vec3};
 use wgpu::{BindGroupLayout, SamplerBindingType};
 
 use std::collections::HashMap;
@@ -13,6 +14,7 @@ pub struct Vertex {
     pub color: u32,
     pub uv_offset: u32,
     pub lightmap_coords: u8,
+    /// Ambient occlusion and directional shading, 0 is black and 255 is fully lit
     pub ao: u8,
 }
 
@@ -21,14 +23,15 @@ impl Vertex {
 
     pub fn compressed(self) -> [u8; Self::VERTEX_LENGTH] {
         // XYZ: 4 bytes (1 for each axis)
-        // Normal: 3 bits
         // Color: 3 bytes
         // UV: 4 bytes
         // Animated UV index: 10 bits
         // XYZ add one flag: 3 bits
         // Block light nibble: 1 byte (4 bits for block, 4 bits for sky)
+        // AO: 1 byte
+        // Normal: 2 bytes, octahedral
 
-        // Total: 101 bits (13 bytes)
+        // Total: 125 bits (16 bytes)
         let mut array = [0; Self::VERTEX_LENGTH];
 
         let x = self.position[0] * 16.0;
@@ -62,24 +65,35 @@ impl Vertex {
         array[8] = self.uv[1].to_le_bytes()[0];
         array[9] = self.uv[1].to_le_bytes()[1];
 
-        let normal_bits: u8 = match self.normal {
-            [-1.0, 0.0, 0.0] => 0b00000100,
-            [1.0, 0.0, 0.0] => 0b00000000,
-            [0.0, 1.0, 0.0] => 0b00000001,
-            [0.0, -1.0, 0.0] => 0b00000101,
-            [0.0, 0.0, 1.0] => 0b00000010,
-            [0.0, 0.0, -1.0] => 0b00000110,
-            _ => unreachable!("Invalid vertex normal"),
-        };
-
-        //UV index and normal
+        //UV index
         array[10] = self.uv_offset as u8;
-        array[11] = (((self.uv_offset >> 8) as u8) & 0b11) | (normal_bits << 2) | (flag_byte << 5);
+        array[11] = (((self.uv_offset >> 8) as u8) & 0b11) | (flag_byte << 5);
         array[12] = self.lightmap_coords;
         array[13] = self.ao;
 
+        //normal
+        let [u, v] = Self::encode_normal(self.normal);
+        array[14] = u;
+        array[15] = v;
+
         array
     }
+
+    /// Packs a unit normal into two snorm bytes with an octahedral mapping, so normals of rotated faces survive
+    /// compression. Decoded by `decode_normal` in the terrain shaders.
+    pub fn encode_normal(normal: [f32; 3]) -> [u8; 2] {
+        let n = Vec3::from_array(normal);
+        let n = n / (n.x.abs() + n.y.abs() + n.z.abs()).max(f32::EPSILON);
+
+        let mut oct = vec2(n.x, n.z);
+
+        if n.y < 0.0 {
+            oct = (1.0 - vec2(oct.y, oct.x).abs()) * vec2(oct.x.signum(), oct.y.signum());
+        }
+
+        oct.to_array()
+            .map(|x| ((x.clamp(-1.0, 1.0) * 127.0).round() as i8) as u8)
+    }
 }
 
 #[repr(C)]
@@ -288,7 +302,125 @@ pub fn create_bind_group_layouts(device: &wgpu::Device) -> HashMap<String, BindG
                 ],
             }),
         ),
+        (
+            "section_draws".into(),
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Section Draws Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            }),
+        ),
     ]
     .into_iter()
     .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec3, Quat, Vec2};
+
+    use super::*;
+
+    /// Same as `decode_normal` in `terrain.wgsl`
+    fn decode_normal([u, v]: [u8; 2]) -> Vec3 {
+        let snorm8 = |x: u8| (x as i8 as f32 / 127.0).max(-1.0);
+
+        let mut oct = vec2(snorm8(u), snorm8(v));
+        let y = 1.0 - oct.x.abs() - oct.y.abs();
+
+        if y < 0.0 {
+            let sign = Vec2::select(oct.cmpge(Vec2::ZERO), Vec2::ONE, Vec2::NEG_ONE);
+            oct = (1.0 - vec2(oct.y, oct.x).abs()) * sign;
+        }
+
+        vec3(oct.x, y, oct.y).normalize()
+    }
+
+    fn assert_round_trip(normal: Vec3) {
+        let normal = normal.normalize();
+        let decoded = decode_normal(Vertex::encode_normal(normal.to_array()));
+
+        //Two bytes are good to about a degree
+        assert!(
+            decoded.angle_between(normal).to_degrees() < 1.0,
+            "{normal} came back as {decoded}"
+        );
+    }
+
+    #[test]
+    fn axis_normals_round_trip_exactly() {
+        for normal in [
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ] {
+            assert_eq!(
+                decode_normal(Vertex::encode_normal(normal.to_array())),
+                normal
+            );
+        }
+    }
+
+    #[test]
+    fn rotated_normals_round_trip() {
+        //Elements can be rotated by 22.5 or 45 degrees about any axis, which tilts their faces' normals
+        for angle in [22.5f32, 45.0, -22.5, -45.0] {
+            let angle = angle.to_radians();
+
+            for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+                for normal in [
+                    Vec3::X,
+                    Vec3::NEG_X,
+                    Vec3::Y,
+                    Vec3::NEG_Y,
+                    Vec3::Z,
+                    Vec3::NEG_Z,
+                ] {
+                    assert_round_trip(Quat::from_axis_angle(axis, angle) * normal);
+                }
+            }
+        }
+
+        assert_round_trip(vec3(1.0, 1.0, 1.0));
+        assert_round_trip(vec3(-1.0, -1.0, -1.0));
+        assert_round_trip(vec3(0.3, -0.8, -0.5));
+    }
+
+    #[test]
+    fn lower_hemisphere_with_zeroes() {
+        //`signum` is 1 for 0.0 and -1 for -0.0, and either has to land on the same normal
+        for normal in [
+            vec3(0.0, -1.0, 1.0),
+            vec3(-0.0, -1.0, 1.0),
+            vec3(1.0, -1.0, 0.0),
+            vec3(1.0, -1.0, -0.0),
+            vec3(0.0, -1.0, -0.0),
+            vec3(-0.0, -1.0, -0.0),
+        ] {
+            assert_round_trip(normal);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+struct SectionDraw {
+    position: vec3<i32>,
+    layer: u32,
+    vertex_offset: u32,
+    first_index: u32,
+    index_count: u32,
+    padding: u32
+}
+
+struct SectionDrawUniform {
+    camera_section: vec2<i32>,
+    draw_count: u32,
+    max_draws: u32
+}
+
+struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32
+}
+
+@group(0) @binding(0) var<uniform> mat4_model: mat4x4<f32>;
+@group(0) @binding(1) var<uniform> mat4_view: mat4x4<f32>;
+@group(0) @binding(2) var<uniform> mat4_persp: mat4x4<f32>;
+
+@group(1) @binding(0) var<storage> draws: array<SectionDraw>;
+@group(1) @binding(1) var<uniform> uniforms: SectionDrawUniform;
+@group(1) @binding(2) var<storage, read_write> commands: array<DrawIndexedIndirect>;
+@group(1) @binding(3) var<storage, read_write> counts: array<atomic<u32>>;
+
+//A section is culled when all 8 corners of its bounding box are outside of the same clip plane
+fn is_visible(min_corner: vec3<f32>) -> bool {
+    let mvp = mat4_persp * mat4_view * mat4_model;
+
+    var outside_left = true;
+    var outside_right = true;
+    var outside_bottom = true;
+    var outside_top = true;
+    var outside_near = true;
+    var outside_far = true;
+
+    for (var i = 0u; i < 8u; i++) {
+        let corner = min_corner + vec3<f32>(f32(i & 1u), f32((i >> 1u) & 1u), f32((i >> 2u) & 1u)) * 16.0;
+        let clip = mvp * vec4(corner, 1.0);
+
+        outside_left = outside_left && clip.x < -clip.w;
+        outside_right = outside_right && clip.x > clip.w;
+        outside_bottom = outside_bottom && clip.y < -clip.w;
+        outside_top = outside_top && clip.y > clip.w;
+        outside_near = outside_near && clip.z < 0.0;
+        outside_far = outside_far && clip.z > clip.w;
+    }
+
+    return !(outside_left || outside_right || outside_bottom || outside_top || outside_near || outside_far);
+}
+
+@compute @workgroup_size(64)
+fn cull(@builtin(global_invocation_id) id: vec3<u32>) {
+    let draw_index = id.x;
+
+    if (draw_index >= uniforms.draw_count) {
+        return;
+    }
+
+    let draw = draws[draw_index];
+    let rel_pos = vec3<i32>(draw.position.x - uniforms.camera_section.x, draw.position.y, draw.position.z - uniforms.camera_section.y);
+
+    if (!is_visible(vec3<f32>(rel_pos) * 16.0)) {
+        return;
+    }
+
+    let slot = atomicAdd(&counts[draw.layer], 1u);
+
+    var command: DrawIndexedIndirect;
+    command.index_count = draw.index_count;
+    command.instance_count = 1u;
+    command.first_index = draw.first_index;
+    command.base_vertex = 0;
+    //The terrain shader finds the section's data through its instance index
+    command.first_instance = draw_index;
+
+    commands[draw.layer * uniforms.max_draws + slot] = command;
+}
@@ -3,6 +3,8 @@
 use linked_hash_map::LinkedHashMap;
 use serde_derive::*;
 
+use crate::mc::chunk::RenderLayer;
+
 /// semver
 pub const CONFIG_VERSION: &str = "v0.0.1";
 /// (major, minor, patch)
@@ -143,6 +145,14 @@ fn blend_default() -> String {
     "alpha_blending".into()
 }
 
+fn layers_default() -> Vec<RenderLayer> {
+    vec![RenderLayer::Solid]
+}
+
+fn depth_write_default() -> bool {
+    true
+}
+
 #[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum BindGroupDef {
@@ -154,6 +164,10 @@ pub enum BindGroupDef {
 pub struct PipelineConfig {
     pub geometry: String,
 
+    /// The shader in `wgpu_mc:shaders/`, without its extension. Defaults to the name of the pipeline, and lets
+    /// several pipelines share a shader with different blending or layers
+    pub shader: Option<String>,
+
     #[serde(default)]
     pub output: Vec<String>,
 
@@ -170,6 +184,13 @@ pub struct PipelineConfig {
 
     #[serde(default = "blend_default")]
     pub blending: String,
+
+    /// Only used by `@geo_terrain` and `@geo_terrain_indirect`. The terrain layers this pipeline draws, in order
+    #[serde(default = "layers_default")]
+    pub layers: Vec<RenderLayer>,
+
+    #[serde(default = "depth_write_default")]
+    pub depth_write: bool,
 }
 
 #[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use image::GenericImageView;
+use image::{GenericImageView, RgbaImage};
 use wgpu::Extent3d;
 
 use crate::{Display, WmRenderer};
@@ -96,6 +96,71 @@ impl TextureAndView {
             format,
         })
     }
+
+    /// Creates an RGBA texture with a level for each image in `mips`, largest first
+    pub fn from_mip_chain(
+        wgpu_state: &Display,
+        mips: &[RgbaImage],
+        label: Option<&str>,
+    ) -> Result<Self, anyhow::Error> {
+        let size = Extent3d {
+            width: mips[0].width(),
+            height: mips[0].height(),
+            depth_or_array_layers: 1,
+        };
+
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+
+        let texture = wgpu_state.device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: mips.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let tv = Self {
+            texture,
+            view,
+            format,
+        };
+
+        tv.write_mip_chain(&wgpu_state.queue, mips);
+
+        Ok(tv)
+    }
+
+    /// Writes each image in `mips` to the mip level of the same index
+    pub fn write_mip_chain(&self, queue: &wgpu::Queue, mips: &[RgbaImage]) {
+        for (level, mip) in mips.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                mip.as_raw(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(mip.width() * 4),
+                    rows_per_image: Some(mip.height()),
+                },
+                Extent3d {
+                    width: mip.width(),
+                    height: mip.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
 }
 
 ///Represents a texture that has been uploaded to GPU and has an associated `BindGroup`